@@ -0,0 +1,696 @@
+use std::{collections::HashMap, fs, io, net::ToSocketAddrs, path::PathBuf, str::FromStr};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use reqwest::Url;
+use serde::Deserialize;
+use thiserror::Error;
+
+/// Config file read when neither --config nor DPS_CONFIG is given, skipped if it does not exist
+pub const DEFAULT_CONFIG_FILE: &str = "dps.toml";
+
+/// Log levels accepted by log.level
+const LOG_LEVELS: [&str; 6] = ["off", "error", "warn", "info", "debug", "trace"];
+
+/// Largest page Elasticsearch returns without raising index.max_result_window
+const MAX_PAGE_SIZE: i64 = 10000;
+
+/// Shortest admin key accepted
+const MIN_ADMIN_KEY_LENGTH: usize = 32;
+
+/// Every setting that can be overridden, as (key, environment variable, command line flag)
+const SETTINGS: [(&str, &str, &str); 38] = [
+    ("server.bind", "DPS_BIND", "--bind"),
+    ("server.workers", "DPS_WORKERS", "--workers"),
+    ("elasticsearch.nodes", "DPS_ELASTICSEARCH_NODES", "--elasticsearch-nodes"),
+    ("elasticsearch.username", "DPS_ELASTICSEARCH_USERNAME", "--elasticsearch-username"),
+    ("elasticsearch.password", "DPS_ELASTICSEARCH_PASSWORD", "--elasticsearch-password"),
+    ("elasticsearch.sniff_interval", "DPS_ELASTICSEARCH_SNIFF_INTERVAL", "--elasticsearch-sniff-interval"),
+    ("elasticsearch.health_check_interval", "DPS_ELASTICSEARCH_HEALTH_CHECK_INTERVAL", "--elasticsearch-health-check-interval"),
+    ("elasticsearch.index_cache_ttl", "DPS_ELASTICSEARCH_INDEX_CACHE_TTL", "--elasticsearch-index-cache-ttl"),
+    ("elasticsearch.api_key_cache_ttl", "DPS_ELASTICSEARCH_API_KEY_CACHE_TTL", "--elasticsearch-api-key-cache-ttl"),
+    ("elasticsearch.api_key", "DPS_ELASTICSEARCH_API_KEY", "--elasticsearch-api-key"),
+    ("elasticsearch.bearer_token", "DPS_ELASTICSEARCH_BEARER_TOKEN", "--elasticsearch-bearer-token"),
+    ("elasticsearch.tls.ca_certificate", "DPS_ELASTICSEARCH_CA_CERTIFICATE", "--elasticsearch-ca-certificate"),
+    ("elasticsearch.tls.ca_fingerprint", "DPS_ELASTICSEARCH_CA_FINGERPRINT", "--elasticsearch-ca-fingerprint"),
+    ("elasticsearch.tls.verify_certificates", "DPS_ELASTICSEARCH_VERIFY_CERTIFICATES", "--elasticsearch-verify-certificates"),
+    ("index.shards", "DPS_INDEX_SHARDS", "--shards"),
+    ("index.replicas", "DPS_INDEX_REPLICAS", "--replicas"),
+    ("search.default_page_size", "DPS_DEFAULT_PAGE_SIZE", "--default-page-size"),
+    ("auth.enabled", "DPS_AUTH_ENABLED", "--auth-enabled"),
+    ("auth.admin_key", "DPS_ADMIN_KEY", "--admin-key"),
+    ("rate_limit.enabled", "DPS_RATE_LIMIT_ENABLED", "--rate-limit-enabled"),
+    ("rate_limit.search_per_minute", "DPS_RATE_LIMIT_SEARCH", "--rate-limit-search"),
+    ("rate_limit.write_per_minute", "DPS_RATE_LIMIT_WRITE", "--rate-limit-write"),
+    ("rate_limit.ip_search_per_minute", "DPS_RATE_LIMIT_IP_SEARCH", "--rate-limit-ip-search"),
+    ("rate_limit.ip_write_per_minute", "DPS_RATE_LIMIT_IP_WRITE", "--rate-limit-ip-write"),
+    ("rate_limit.monthly_quota", "DPS_MONTHLY_QUOTA", "--monthly-quota"),
+    ("rate_limit.trust_forwarded_for", "DPS_TRUST_FORWARDED_FOR", "--trust-forwarded-for"),
+    ("cors.search.allowed_origins", "DPS_CORS_SEARCH_ORIGINS", "--cors-search-origins"),
+    ("cors.search.allow_credentials", "DPS_CORS_SEARCH_CREDENTIALS", "--cors-search-credentials"),
+    ("cors.admin.allowed_origins", "DPS_CORS_ADMIN_ORIGINS", "--cors-admin-origins"),
+    ("cors.admin.allow_credentials", "DPS_CORS_ADMIN_CREDENTIALS", "--cors-admin-credentials"),
+    ("log.level", "DPS_LOG_LEVEL", "--log-level"),
+    ("access_log.enabled", "DPS_ACCESS_LOG", "--access-log"),
+    ("access_log.sample_ratio", "DPS_ACCESS_LOG_SAMPLE_RATIO", "--access-log-sample-ratio"),
+    ("access_log.redact_search_terms", "DPS_ACCESS_LOG_REDACT_SEARCH_TERMS", "--access-log-redact-search-terms"),
+    ("tracing.otlp_endpoint", "DPS_OTLP_ENDPOINT", "--otlp-endpoint"),
+    ("tracing.service_name", "DPS_SERVICE_NAME", "--service-name"),
+    ("tracing.sample_ratio", "DPS_TRACE_SAMPLE_RATIO", "--trace-sample-ratio"),
+    ("config", "DPS_CONFIG", "--config"),
+];
+
+const USAGE: &str = "Usage: dps [OPTIONS]
+
+Settings are read from the config file, then environment variables, then these flags
+
+Options:
+  --config <PATH>                     Config file [env: DPS_CONFIG] [default: dps.toml]
+  --bind <ADDRESS>                    Address the server listens on [env: DPS_BIND] [default: 127.0.0.1:8080]
+  --workers <COUNT>                   Worker threads [env: DPS_WORKERS] [default: one per CPU]
+  --elasticsearch-nodes <URLS>        Comma separated Elasticsearch nodes [env: DPS_ELASTICSEARCH_NODES] [default: http://127.0.0.1:9200]
+  --elasticsearch-sniff-interval <SECONDS>
+                                      Discovers the nodes of the cluster at startup and every interval [env: DPS_ELASTICSEARCH_SNIFF_INTERVAL]
+  --elasticsearch-health-check-interval <SECONDS>
+                                      Checks the health of the cluster every interval [env: DPS_ELASTICSEARCH_HEALTH_CHECK_INTERVAL] [default: 5]
+  --elasticsearch-index-cache-ttl <SECONDS>
+                                      Keeps the existence and mappings of indexes for this long, 0 disables the cache [env: DPS_ELASTICSEARCH_INDEX_CACHE_TTL] [default: 30]
+  --elasticsearch-api-key-cache-ttl <SECONDS>
+                                      Keeps the API keys that authenticated requests for this long, 0 disables the cache [env: DPS_ELASTICSEARCH_API_KEY_CACHE_TTL] [default: 30]
+  --elasticsearch-username <NAME>     Basic auth username [env: DPS_ELASTICSEARCH_USERNAME]
+  --elasticsearch-password <SECRET>   Basic auth password [env: DPS_ELASTICSEARCH_PASSWORD]
+  --elasticsearch-api-key <KEY>       API key, as id:api_key or its base64 encoding [env: DPS_ELASTICSEARCH_API_KEY]
+  --elasticsearch-bearer-token <TOKEN>
+                                      Bearer token [env: DPS_ELASTICSEARCH_BEARER_TOKEN]
+  --elasticsearch-ca-certificate <PATH>
+                                      PEM CA certificate of the cluster [env: DPS_ELASTICSEARCH_CA_CERTIFICATE]
+  --elasticsearch-ca-fingerprint <SHA256>
+                                      Hex SHA-256 fingerprint of the CA certificate [env: DPS_ELASTICSEARCH_CA_FINGERPRINT]
+  --elasticsearch-verify-certificates <BOOL>
+                                      false accepts any certificate, for local development only [env: DPS_ELASTICSEARCH_VERIFY_CERTIFICATES] [default: true]
+  --shards <COUNT>                    Shards of new indexes [env: DPS_INDEX_SHARDS] [default: 3]
+  --replicas <COUNT>                  Replicas of new indexes [env: DPS_INDEX_REPLICAS] [default: 0]
+  --default-page-size <COUNT>         Search results per page when count is not given [env: DPS_DEFAULT_PAGE_SIZE] [default: 20]
+  --auth-enabled <BOOL>               Require an API key in the X-API-Key header [env: DPS_AUTH_ENABLED] [default: true]
+  --admin-key <KEY>                   Admin API key that always works, used to create the other keys [env: DPS_ADMIN_KEY]
+  --rate-limit-enabled <BOOL>         Limit requests per API key and per client IP [env: DPS_RATE_LIMIT_ENABLED] [default: true]
+  --rate-limit-search <COUNT>         Search requests per minute of an API key, 0 for no limit [env: DPS_RATE_LIMIT_SEARCH] [default: 600]
+  --rate-limit-write <COUNT>          Write requests per minute of an API key, 0 for no limit [env: DPS_RATE_LIMIT_WRITE] [default: 120]
+  --rate-limit-ip-search <COUNT>      Search requests per minute of a client IP, 0 for no limit [env: DPS_RATE_LIMIT_IP_SEARCH] [default: 300]
+  --rate-limit-ip-write <COUNT>       Write requests per minute of a client IP, 0 for no limit [env: DPS_RATE_LIMIT_IP_WRITE] [default: 60]
+  --monthly-quota <COUNT>             Requests of an API key per calendar month and server instance, reset on restart [env: DPS_MONTHLY_QUOTA] [default: no quota]
+  --trust-forwarded-for <BOOL>        Take the client IP from Forwarded / X-Forwarded-For, only behind a proxy [env: DPS_TRUST_FORWARDED_FOR] [default: false]
+  --cors-search-origins <ORIGINS>     Comma separated origins or patterns allowed to call the search routes, * allows any [env: DPS_CORS_SEARCH_ORIGINS] [default: *]
+  --cors-search-credentials <BOOL>    Let browsers send cookies to the search routes [env: DPS_CORS_SEARCH_CREDENTIALS] [default: false]
+  --cors-admin-origins <ORIGINS>      Comma separated origins or patterns allowed to call every other route [env: DPS_CORS_ADMIN_ORIGINS] [default: none]
+  --cors-admin-credentials <BOOL>     Let browsers send cookies to every other route [env: DPS_CORS_ADMIN_CREDENTIALS] [default: false]
+  --log-level <LEVEL>                 off, error, warn, info, debug or trace [env: DPS_LOG_LEVEL] [default: info]
+  --access-log <BOOL>                 Write a JSON line per request to stdout [env: DPS_ACCESS_LOG] [default: true]
+  --access-log-sample-ratio <RATIO>   Share of the requests logged, 0 to 1, server errors are always logged [env: DPS_ACCESS_LOG_SAMPLE_RATIO] [default: 1]
+  --access-log-redact-search-terms <BOOL>
+                                      Replace search terms in the logged query strings [env: DPS_ACCESS_LOG_REDACT_SEARCH_TERMS] [default: true]
+  --otlp-endpoint <URL>               OTLP/HTTP traces endpoint of the collector, such as http://127.0.0.1:4318/v1/traces [env: DPS_OTLP_ENDPOINT] [default: no export]
+  --service-name <NAME>               Service name of the exported spans [env: DPS_SERVICE_NAME] [default: dps]
+  --trace-sample-ratio <RATIO>        Share of the traces started here that are exported, 0 to 1 [env: DPS_TRACE_SAMPLE_RATIO] [default: 1]
+  -h, --help                          Print this help";
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("{USAGE}")]
+    Help,
+    #[error("Failed to read config file {0}, {1}")]
+    Read(PathBuf, io::Error),
+    #[error("Failed to parse config file {0}, {1}")]
+    Parse(PathBuf, toml::de::Error),
+    #[error("Unknown flag {0}, see --help")]
+    UnknownFlag(String),
+    #[error("Missing value for flag {0}")]
+    MissingValue(String),
+    #[error("Invalid value [{value}] for {key}, {reason}")]
+    InvalidValue { key: String, value: String, reason: String },
+    #[error("Failed to load CA certificate {0}, {1}")]
+    Certificate(PathBuf, String),
+    #[error("Failed to pin the certificate of {0}, {1}")]
+    Fingerprint(String, String),
+}
+
+impl ConfigError {
+    fn invalid(key: &str, value: impl ToString, reason: impl ToString) -> Self {
+        Self::InvalidValue { key: key.to_string(), value: value.to_string(), reason: reason.to_string() }
+    }
+}
+
+/// Settings of the server, see dps.example.toml
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub elasticsearch: ElasticConfig,
+    pub index: IndexConfig,
+    pub search: SearchConfig,
+    pub auth: AuthConfig,
+    pub rate_limit: RateLimitConfig,
+    pub cors: CorsConfig,
+    pub log: LogConfig,
+    pub access_log: AccessLogConfig,
+    pub tracing: TracingConfig,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub bind: String,
+    /// None starts one worker per CPU
+    pub workers: Option<usize>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self { bind: "127.0.0.1:8080".to_string(), workers: None }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ElasticConfig {
+    /// Urls of the seed nodes, requests are spread over them and retried on another node when one fails
+    pub nodes: Vec<String>,
+    /// Seconds between two discoveries of the http nodes of the cluster, None only uses the seed nodes
+    pub sniff_interval: Option<u64>,
+    /// Seconds between two checks of the health of the cluster, requests fail fast while the last check could not reach it
+    pub health_check_interval: u64,
+    /// Seconds the existence, mappings and settings of an index are cached, changes made through this service are seen right away
+    pub index_cache_ttl: u64,
+    /// Seconds the API key of a request is cached, keys rotated or deleted through another instance keep working on this one until it has passed
+    pub api_key_cache_ttl: u64,
+    /// Basic auth, given together with password
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// "id:api_key" or its base64 encoding, as returned by the create API key API
+    pub api_key: Option<String>,
+    pub bearer_token: Option<String>,
+    pub tls: TlsConfig,
+}
+
+impl Default for ElasticConfig {
+    fn default() -> Self {
+        Self {
+            nodes: vec!["http://127.0.0.1:9200".to_string()],
+            sniff_interval: None,
+            health_check_interval: 5,
+            index_cache_ttl: 30,
+            api_key_cache_ttl: 30,
+            username: None,
+            password: None,
+            api_key: None,
+            bearer_token: None,
+            tls: TlsConfig::default()
+        }
+    }
+}
+
+impl ElasticConfig {
+    /// Id and key of api_key, None if it is not set or malformed
+    pub fn api_key_parts(&self) -> Option<(String, String)> {
+        let api_key = self.api_key.as_deref()?.trim();
+
+        let decoded = match api_key.contains(':') {
+            true => api_key.to_string(),
+            false => String::from_utf8(STANDARD.decode(api_key).ok()?).ok()?
+        };
+
+        match decoded.split_once(':') {
+            Some((id, key)) if !id.is_empty() && !key.is_empty() => Some((id.to_string(), key.to_string())),
+            _ => None
+        }
+    }
+
+    /// tls.ca_fingerprint as bytes, None if it is not set or malformed
+    pub fn ca_fingerprint_bytes(&self) -> Option<Vec<u8>> {
+        let hex: String = self.tls.ca_fingerprint.as_deref()?.chars().filter(|x| *x != ':').collect();
+
+        if hex.len() != 64 || !hex.is_ascii() {
+            return None;
+        }
+
+        (0..64).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok()).collect()
+    }
+}
+
+/// Trust settings of https nodes
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsConfig {
+    /// PEM file of the CA that signed the certificates of the nodes, trusted on top of the system CAs
+    pub ca_certificate: Option<PathBuf>,
+    /// SHA-256 fingerprint of a certificate in the chain of the nodes, in hex with optional colons
+    ///
+    /// The nodes are tried in order at startup until one presents it, the matching certificate is then trusted as the CA of every node
+    pub ca_fingerprint: Option<String>,
+    /// false accepts any certificate, for local development only
+    pub verify_certificates: bool,
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        Self { ca_certificate: None, ca_fingerprint: None, verify_certificates: true }
+    }
+}
+
+/// Settings given to every index created through the API
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct IndexConfig {
+    pub shards: u32,
+    pub replicas: u32,
+}
+
+impl Default for IndexConfig {
+    fn default() -> Self {
+        Self { shards: 3, replicas: 0 }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct SearchConfig {
+    /// Amount of results returned when a search does not give count
+    pub default_page_size: i64,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self { default_page_size: 20 }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    /// false lets every request through as admin
+    pub enabled: bool,
+    /// Admin key that is not stored, for creating the first keys
+    pub admin_key: Option<String>,
+}
+
+impl Default for AuthConfig {
+    fn default() -> Self {
+        Self { enabled: true, admin_key: None }
+    }
+}
+
+/// Limits are token buckets refilled over a minute, 0 disables a limit
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    pub enabled: bool,
+    /// Search requests per minute of an API key
+    pub search_per_minute: u32,
+    /// Write requests per minute of an API key
+    pub write_per_minute: u32,
+    /// Search requests per minute of a client IP
+    pub ip_search_per_minute: u32,
+    /// Write requests per minute of a client IP
+    pub ip_write_per_minute: u32,
+    /// Requests of an API key per calendar month, None for no quota
+    ///
+    /// Counted in memory, every server instance enforces it on its own requests and starts over from zero when it restarts
+    pub monthly_quota: Option<u64>,
+    /// Takes the client IP from Forwarded / X-Forwarded-For instead of the connection
+    pub trust_forwarded_for: bool,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            search_per_minute: 600,
+            write_per_minute: 120,
+            ip_search_per_minute: 300,
+            ip_write_per_minute: 60,
+            monthly_quota: None,
+            trust_forwarded_for: false
+        }
+    }
+}
+
+/// The search routes and every other route, such as index and API key management, have their own policy
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct CorsConfig {
+    /// /api/search routes
+    pub search: CorsPolicy,
+    /// Every other route
+    pub admin: CorsPolicy,
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        Self {
+            search: CorsPolicy {
+                allowed_origins: vec!["*".to_string()],
+                allowed_methods: vec!["GET".to_string(), "POST".to_string()],
+                ..Default::default()
+            },
+            admin: CorsPolicy::default()
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct CorsPolicy {
+    /// Origins allowed to call the routes, "*" in an origin matches any characters, "*" alone allows any origin, empty allows none
+    pub allowed_origins: Vec<String>,
+    pub allowed_methods: Vec<String>,
+    /// Request headers browsers may send
+    pub allowed_headers: Vec<String>,
+    /// Seconds browsers may cache a preflight response
+    pub max_age: Option<usize>,
+    /// Lets browsers send cookies and HTTP auth, not allowed with "*"
+    pub allow_credentials: bool,
+}
+
+impl Default for CorsPolicy {
+    fn default() -> Self {
+        Self {
+            allowed_origins: vec![],
+            allowed_methods: ["GET", "POST", "PUT", "DELETE"].iter().map(|x| x.to_string()).collect(),
+            allowed_headers: vec!["Content-Type".to_string(), "X-API-Key".to_string()],
+            max_age: Some(3600),
+            allow_credentials: false
+        }
+    }
+}
+
+impl CorsPolicy {
+    fn validate(&self, key: &str) -> Result<(), ConfigError> {
+        for origin in self.allowed_origins.iter().filter(|x| *x != "*") {
+            // Patterns have to be origins once their "*" are filled in
+            match Url::parse(&origin.replace('*', "x")) {
+                Ok(url) if url.has_host() && url.path() == "/" && !origin.ends_with('/') => (),
+                _ => return Err(ConfigError::invalid(&format!("{}.allowed_origins", key), origin, "expected an origin such as https://example.com or a pattern such as https://*.example.com"))
+            }
+        }
+
+        if self.allow_credentials && self.allowed_origins.iter().any(|x| x == "*") {
+            return Err(ConfigError::invalid(&format!("{}.allow_credentials", key), true, "credentials can not be allowed for any origin, list the origins instead"));
+        }
+
+        for method in &self.allowed_methods {
+            if method.parse::<actix_web::http::Method>().is_err() || method.chars().any(|x| x.is_ascii_lowercase()) {
+                return Err(ConfigError::invalid(&format!("{}.allowed_methods", key), method, "expected an uppercase HTTP method such as GET"));
+            }
+        }
+
+        for header in &self.allowed_headers {
+            if header.parse::<actix_web::http::header::HeaderName>().is_err() {
+                return Err(ConfigError::invalid(&format!("{}.allowed_headers", key), header, "expected a header name"));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    pub level: String,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self { level: "info".to_string() }
+    }
+}
+
+/// One JSON line per request on stdout, apart from the logs on stderr
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct AccessLogConfig {
+    pub enabled: bool,
+    /// Share of the requests logged, requests answered with a server error are always logged
+    pub sample_ratio: f64,
+    /// Replaces the search terms and filters of the logged query strings with "[redacted]"
+    pub redact_search_terms: bool,
+}
+
+impl Default for AccessLogConfig {
+    fn default() -> Self {
+        Self { enabled: true, sample_ratio: 1.0, redact_search_terms: true }
+    }
+}
+
+/// Spans are exported over OTLP/HTTP when otlp_endpoint is set, the W3C traceparent is passed on to Elasticsearch either way
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct TracingConfig {
+    /// Traces endpoint of the collector, such as http://127.0.0.1:4318/v1/traces
+    pub otlp_endpoint: Option<String>,
+    pub service_name: String,
+    /// Share of the traces started here that are exported, traces continued from a caller follow its sampling decision
+    pub sample_ratio: f64,
+}
+
+impl Default for TracingConfig {
+    fn default() -> Self {
+        Self { otlp_endpoint: None, service_name: "dps".to_string(), sample_ratio: 1.0 }
+    }
+}
+
+impl Config {
+    /// Loads the config of the process from the config file, the environment and the command line
+    pub fn load() -> Result<Self, ConfigError> {
+        Self::from_sources(std::env::vars().collect(), std::env::args().skip(1).collect())
+    }
+
+    /// Loads the config from the config file, then overrides it with env, then with args, and validates the result
+    pub fn from_sources(env: HashMap<String, String>, args: Vec<String>) -> Result<Self, ConfigError> {
+        let flags = parse_args(args)?;
+
+        let env_overrides: Vec<(&str, String)> = SETTINGS
+            .iter()
+            .filter_map(|(key, var, _)| env.get(*var).map(|value| (*key, value.clone())))
+            .collect();
+
+        // The config file itself can be chosen through both
+        let config_file = flags.iter().chain(env_overrides.iter()).find(|(key, _)| *key == "config").map(|(_, path)| path.clone());
+
+        let mut config = match config_file {
+            Some(path) => Self::from_file(PathBuf::from(path))?,
+            None => match PathBuf::from(DEFAULT_CONFIG_FILE) {
+                path if path.exists() => Self::from_file(path)?,
+                _ => Self::default()
+            }
+        };
+
+        for (key, value) in env_overrides.into_iter().chain(flags) {
+            config.set(key, &value)?;
+        }
+
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    fn from_file(path: PathBuf) -> Result<Self, ConfigError> {
+        let content = fs::read_to_string(&path).map_err(|x| ConfigError::Read(path.clone(), x))?;
+
+        toml::from_str(&content).map_err(|x| ConfigError::Parse(path, x))
+    }
+
+    /// Overrides a setting with a value given as text, lists are comma separated
+    fn set(&mut self, key: &str, value: &str) -> Result<(), ConfigError> {
+        let list = || value.split(',').map(|x| x.trim().to_string()).filter(|x| !x.is_empty()).collect();
+
+        match key {
+            "server.bind" => self.server.bind = value.to_string(),
+            "server.workers" => self.server.workers = Some(parse_value(key, value, "expected a number of workers")?),
+            "elasticsearch.nodes" => self.elasticsearch.nodes = list(),
+            "elasticsearch.sniff_interval" => self.elasticsearch.sniff_interval = Some(parse_value(key, value, "expected a number of seconds")?),
+            "elasticsearch.health_check_interval" => self.elasticsearch.health_check_interval = parse_value(key, value, "expected a number of seconds")?,
+            "elasticsearch.index_cache_ttl" => self.elasticsearch.index_cache_ttl = parse_value(key, value, "expected a number of seconds")?,
+            "elasticsearch.api_key_cache_ttl" => self.elasticsearch.api_key_cache_ttl = parse_value(key, value, "expected a number of seconds")?,
+            "elasticsearch.username" => self.elasticsearch.username = Some(value.to_string()),
+            "elasticsearch.password" => self.elasticsearch.password = Some(value.to_string()),
+            "elasticsearch.api_key" => self.elasticsearch.api_key = Some(value.to_string()),
+            "elasticsearch.bearer_token" => self.elasticsearch.bearer_token = Some(value.to_string()),
+            "elasticsearch.tls.ca_certificate" => self.elasticsearch.tls.ca_certificate = Some(PathBuf::from(value)),
+            "elasticsearch.tls.ca_fingerprint" => self.elasticsearch.tls.ca_fingerprint = Some(value.to_string()),
+            "elasticsearch.tls.verify_certificates" => self.elasticsearch.tls.verify_certificates = parse_value(key, value, "expected true or false")?,
+            "index.shards" => self.index.shards = parse_value(key, value, "expected a number of shards")?,
+            "index.replicas" => self.index.replicas = parse_value(key, value, "expected a number of replicas")?,
+            "search.default_page_size" => self.search.default_page_size = parse_value(key, value, "expected a page size")?,
+            "auth.enabled" => self.auth.enabled = parse_value(key, value, "expected true or false")?,
+            "auth.admin_key" => self.auth.admin_key = Some(value.to_string()),
+            "rate_limit.enabled" => self.rate_limit.enabled = parse_value(key, value, "expected true or false")?,
+            "rate_limit.search_per_minute" => self.rate_limit.search_per_minute = parse_value(key, value, "expected a number of requests")?,
+            "rate_limit.write_per_minute" => self.rate_limit.write_per_minute = parse_value(key, value, "expected a number of requests")?,
+            "rate_limit.ip_search_per_minute" => self.rate_limit.ip_search_per_minute = parse_value(key, value, "expected a number of requests")?,
+            "rate_limit.ip_write_per_minute" => self.rate_limit.ip_write_per_minute = parse_value(key, value, "expected a number of requests")?,
+            "rate_limit.monthly_quota" => self.rate_limit.monthly_quota = Some(parse_value(key, value, "expected a number of requests")?),
+            "rate_limit.trust_forwarded_for" => self.rate_limit.trust_forwarded_for = parse_value(key, value, "expected true or false")?,
+            "cors.search.allowed_origins" => self.cors.search.allowed_origins = list(),
+            "cors.search.allow_credentials" => self.cors.search.allow_credentials = parse_value(key, value, "expected true or false")?,
+            "cors.admin.allowed_origins" => self.cors.admin.allowed_origins = list(),
+            "cors.admin.allow_credentials" => self.cors.admin.allow_credentials = parse_value(key, value, "expected true or false")?,
+            "log.level" => self.log.level = value.trim().to_lowercase(),
+            "access_log.enabled" => self.access_log.enabled = parse_value(key, value, "expected true or false")?,
+            "access_log.sample_ratio" => self.access_log.sample_ratio = parse_value(key, value, "expected a ratio between 0 and 1")?,
+            "access_log.redact_search_terms" => self.access_log.redact_search_terms = parse_value(key, value, "expected true or false")?,
+            "tracing.otlp_endpoint" => self.tracing.otlp_endpoint = Some(value.trim().to_string()),
+            "tracing.service_name" => self.tracing.service_name = value.trim().to_string(),
+            "tracing.sample_ratio" => self.tracing.sample_ratio = parse_value(key, value, "expected a ratio between 0 and 1")?,
+            // Already used to read the config file
+            _ => ()
+        }
+
+        Ok(())
+    }
+
+    /// Checks every setting, returns the first invalid one
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if let Err(x) = self.server.bind.to_socket_addrs() {
+            return Err(ConfigError::invalid("server.bind", &self.server.bind, x));
+        }
+
+        if self.server.workers == Some(0) {
+            return Err(ConfigError::invalid("server.workers", 0, "at least one worker is required"));
+        }
+
+        if self.elasticsearch.nodes.is_empty() {
+            return Err(ConfigError::invalid("elasticsearch.nodes", "", "at least one node is required"));
+        }
+
+        for node in &self.elasticsearch.nodes {
+            match Url::parse(node) {
+                Ok(url) if ["http", "https"].contains(&url.scheme()) && url.has_host() => (),
+                Ok(_) => return Err(ConfigError::invalid("elasticsearch.nodes", node, "expected an http or https url")),
+                Err(x) => return Err(ConfigError::invalid("elasticsearch.nodes", node, x))
+            }
+        }
+
+        if self.elasticsearch.nodes.iter().any(|x| Url::parse(x).unwrap().scheme() != Url::parse(&self.elasticsearch.nodes[0]).unwrap().scheme()) {
+            return Err(ConfigError::invalid("elasticsearch.nodes", self.elasticsearch.nodes.join(","), "every node must use the same scheme"));
+        }
+
+        if self.elasticsearch.sniff_interval == Some(0) {
+            return Err(ConfigError::invalid("elasticsearch.sniff_interval", 0, "expected at least one second"));
+        }
+
+        if self.elasticsearch.health_check_interval == 0 {
+            return Err(ConfigError::invalid("elasticsearch.health_check_interval", 0, "expected at least one second"));
+        }
+
+        if self.elasticsearch.username.is_some() != self.elasticsearch.password.is_some() {
+            return Err(ConfigError::invalid("elasticsearch.username", self.elasticsearch.username.as_deref().unwrap_or(""), "username and password must be given together"));
+        }
+
+        let auth_methods = [self.elasticsearch.username.is_some(), self.elasticsearch.api_key.is_some(), self.elasticsearch.bearer_token.is_some()];
+
+        if auth_methods.iter().filter(|x| **x).count() > 1 {
+            return Err(ConfigError::invalid("elasticsearch", "", "only one of username, api_key and bearer_token can be given"));
+        }
+
+        if self.elasticsearch.api_key.is_some() && self.elasticsearch.api_key_parts().is_none() {
+            return Err(ConfigError::invalid("elasticsearch.api_key", "<redacted>", "expected id:api_key or its base64 encoding"));
+        }
+
+        let tls = &self.elasticsearch.tls;
+
+        if let Some(fingerprint) = &tls.ca_fingerprint {
+            if self.elasticsearch.ca_fingerprint_bytes().is_none() {
+                return Err(ConfigError::invalid("elasticsearch.tls.ca_fingerprint", fingerprint, "expected a hex SHA-256 fingerprint"));
+            }
+
+            if !self.elasticsearch.nodes[0].starts_with("https://") {
+                return Err(ConfigError::invalid("elasticsearch.tls.ca_fingerprint", fingerprint, "the first node must be an https url"));
+            }
+        }
+
+        if tls.ca_certificate.is_some() && tls.ca_fingerprint.is_some() {
+            return Err(ConfigError::invalid("elasticsearch.tls", "", "only one of ca_certificate and ca_fingerprint can be given"));
+        }
+
+        if !tls.verify_certificates && (tls.ca_certificate.is_some() || tls.ca_fingerprint.is_some()) {
+            return Err(ConfigError::invalid("elasticsearch.tls.verify_certificates", false, "ca_certificate and ca_fingerprint are ignored when certificates are not verified"));
+        }
+
+        if self.index.shards == 0 {
+            return Err(ConfigError::invalid("index.shards", 0, "at least one shard is required"));
+        }
+
+        if !(1..=MAX_PAGE_SIZE).contains(&self.search.default_page_size) {
+            return Err(ConfigError::invalid("search.default_page_size", self.search.default_page_size, format!("expected between 1 and {}", MAX_PAGE_SIZE)));
+        }
+
+        if self.auth.admin_key.as_ref().map(|x| x.len() < MIN_ADMIN_KEY_LENGTH).unwrap_or(false) {
+            return Err(ConfigError::invalid("auth.admin_key", "<redacted>", format!("expected at least {} characters", MIN_ADMIN_KEY_LENGTH)));
+        }
+
+        if self.rate_limit.monthly_quota == Some(0) {
+            return Err(ConfigError::invalid("rate_limit.monthly_quota", 0, "expected at least one request, leave it out for no quota"));
+        }
+
+        self.cors.search.validate("cors.search")?;
+        self.cors.admin.validate("cors.admin")?;
+
+        if !LOG_LEVELS.contains(&self.log.level.as_str()) {
+            return Err(ConfigError::invalid("log.level", &self.log.level, format!("expected one of {}", LOG_LEVELS.join(", "))));
+        }
+
+        if !(0.0..=1.0).contains(&self.access_log.sample_ratio) {
+            return Err(ConfigError::invalid("access_log.sample_ratio", self.access_log.sample_ratio, "expected a ratio between 0 and 1"));
+        }
+
+        if let Some(endpoint) = &self.tracing.otlp_endpoint {
+            match Url::parse(endpoint) {
+                Ok(url) if ["http", "https"].contains(&url.scheme()) && url.has_host() => (),
+                _ => return Err(ConfigError::invalid("tracing.otlp_endpoint", endpoint, "expected an http or https url"))
+            }
+        }
+
+        if self.tracing.service_name.is_empty() {
+            return Err(ConfigError::invalid("tracing.service_name", "", "expected a service name"));
+        }
+
+        if !(0.0..=1.0).contains(&self.tracing.sample_ratio) {
+            return Err(ConfigError::invalid("tracing.sample_ratio", self.tracing.sample_ratio, "expected a ratio between 0 and 1"));
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_value<T: FromStr>(key: &str, value: &str, reason: &str) -> Result<T, ConfigError> {
+    value.trim().parse().map_err(|_| ConfigError::invalid(key, value, reason))
+}
+
+/// Splits the command line into (setting key, value), flags are given as "--flag value" or "--flag=value"
+fn parse_args(args: Vec<String>) -> Result<Vec<(&'static str, String)>, ConfigError> {
+    let mut flags = vec![];
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        if arg == "-h" || arg == "--help" {
+            return Err(ConfigError::Help);
+        }
+
+        let (flag, value) = match arg.split_once('=') {
+            Some((flag, value)) => (flag.to_string(), Some(value.to_string())),
+            None => (arg, None)
+        };
+
+        let key = match SETTINGS.iter().find(|(_, _, x)| *x == flag) {
+            Some((key, _, _)) => *key,
+            None => return Err(ConfigError::UnknownFlag(flag))
+        };
+
+        match value.or_else(|| args.next()) {
+            Some(value) => flags.push((key, value)),
+            None => return Err(ConfigError::MissingValue(flag))
+        }
+    }
+
+    Ok(flags)
+}
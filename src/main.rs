@@ -1,51 +1,170 @@
+use std::{sync::Arc, time::Duration};
+
 use actix_web::web;
-use actix_web::{web::Data, App, HttpServer};
-use middlewares::cors::cors;
+use actix_web::{middleware::from_fn, web::Data, App, HttpServer};
+use middlewares::{access_log::{access_log, AccessLogger}, auth::authenticate, cors::cors, metrics::track_requests, rate_limit::rate_limit, trace::trace_requests};
+mod config;
+use crate::config::{Config, ConfigError, CorsConfig};
 mod models;
-use crate::models::client::EClient;
+use crate::models::{client::EClient, backend::SearchBackend, rate_limit::RateLimiter};
 mod routes;
 use crate::routes::*;
 mod middlewares;
+mod telemetry;
+#[cfg(test)]
+mod tests;
+
+/// Registers the routes of the API, the search routes and every other route each get their CORS policy, the metrics and the health probes
+fn api(cors_config: CorsConfig) -> impl FnOnce(&mut web::ServiceConfig) {
+    move |cfg| {
+        // Registered first, /api would match the search routes as well
+        cfg.service(
+            web::scope("/api/search")
+                // Last wrapped runs first, the rate limits need the API key and CORS answers preflights before both
+                .wrap(from_fn(rate_limit))
+                .wrap(from_fn(authenticate))
+                .wrap(cors(&cors_config.search))
+                .wrap(from_fn(track_requests))
+                .wrap(from_fn(trace_requests))
+                .wrap(from_fn(access_log))
+                .route("/{index}", web::get().to(search))
+                .route("", web::post().to(post_search))
+                .route("/{index}/facets/{field}", web::post().to(search_facet_values))
+        );
+
+        cfg.service(
+            web::scope("/api")
+                .wrap(from_fn(rate_limit))
+                .wrap(from_fn(authenticate))
+                .wrap(cors(&cors_config.admin))
+                .wrap(from_fn(track_requests))
+                .wrap(from_fn(trace_requests))
+                .wrap(from_fn(access_log))
+                .route("/document/{index}/{document_id}", web::get().to(get_document))
+                .route("/document", web::post().to(create_document))
+                .route("/document", web::put().to(update_document))
+                .route("/document/{index}/{document_id}", web::delete().to(delete_document))
+                .route("/documents/bulk", web::post().to(bulk_documents))
+
+                .route("/index", web::get().to(get_index))
+                .route("/index", web::post().to(create_index))
+                .route("/index/{index}", web::delete().to(delete_index))
+                .route("/index/{index}/export", web::get().to(export_index))
+                .route("/index/{index}/import", web::post().to(import_index))
+
+                .route("/mappings/{index}", web::get().to(get_mapping))
+                .route("/mappings", web::put().to(update_mapping))
+
+                .route("/diagnostics/nodes", web::get().to(node_health))
+
+                .route("/keys", web::post().to(create_api_key))
+                .route("/keys", web::get().to(list_api_keys))
+                .route("/keys/secured", web::post().to(create_secured_api_key))
+                .route("/keys/{id}/rotate", web::post().to(rotate_api_key))
+                .route("/keys/{id}", web::delete().to(delete_api_key))
+                .route("/usage", web::get().to(get_usage))
+
+                // #[delete("/api/document/{index}/{document_id}")]
+                .route("/welcome", web::get().to(welcome))
+
+                // Temporary
+                .service(
+                    web::scope("/test")
+                        .route("add_data", web::get().to(hardcoded_data_for_testing))
+                )
+        );
+
+        // Scraped by Prometheus, outside /api so that it needs no API key
+        cfg.route("/metrics", web::get().to(metrics));
+
+        // Probed by load balancers and orchestrators, outside /api as well
+        cfg.service(
+            web::scope("/health")
+                .route("/live", web::get().to(health_live))
+                .route("/ready", web::get().to(health_ready))
+                .route("/deep", web::get().to(health_deep))
+        );
+    }
+}
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
-    // Debug mode
-    std::env::set_var("RUST_LOG", "debug");
-    env_logger::init();
+    let config = match Config::load() {
+        Ok(x) => x,
+        Err(ConfigError::Help) => {
+            println!("{}", ConfigError::Help);
+            return Ok(());
+        },
+        Err(x) => {
+            eprintln!("Invalid configuration: {}", x);
+            std::process::exit(2);
+        }
+    };
+
+    let tracer_provider = match telemetry::init(&config) {
+        Ok(x) => x,
+        Err(x) => {
+            eprintln!("Invalid configuration: {}", x);
+            std::process::exit(2);
+        }
+    };
+
+    let client = match EClient::new(&config) {
+        Ok(x) => x,
+        Err(x) => {
+            eprintln!("Invalid configuration: {}", x);
+            std::process::exit(2);
+        }
+    };
+
+    let client = Arc::new(client);
+
+    if let Some(interval) = config.elasticsearch.sniff_interval {
+        actix_web::rt::spawn(client.clone().sniff_every(Duration::from_secs(interval)));
+    }
+
+    actix_web::rt::spawn(client.clone().monitor_health_every(Duration::from_secs(config.elasticsearch.health_check_interval)));
+
+    let search_backend: Arc<dyn SearchBackend> = client;
+    let cors_config = config.cors.clone();
+    let auth_config = config.auth.clone();
+    let rate_limit_config = config.rate_limit.clone();
+    let rate_limiter = Data::new(RateLimiter::default());
+    let access_log_config = config.access_log.clone();
+    let access_logger = Data::new(AccessLogger::stdout());
+
+    if auth_config.enabled && auth_config.admin_key.is_none() {
+        log::warn!("Authentication is enabled without an admin key, only API keys that already exist can be used");
+    }
+    if !auth_config.enabled {
+        log::warn!("Authentication is disabled, every request is let through as admin");
+    }
 
     // Start server
-    HttpServer::new(move || {
+    let mut server = HttpServer::new(move || {
         App::new()
-            .wrap(cors())
-            .service(
-                web::scope("/api")
-                    .app_data(Data::new(EClient::new("http://127.0.0.1:9200")))
-                    .route("/document/{index}/{document_id}", web::get().to(get_document))
-                    .route("/document", web::post().to(create_document))
-                    .route("/document", web::put().to(update_document))
-                    .route("/document/{index}/{document_id}", web::delete().to(delete_document))
-
-                    .route("/search/{index}", web::get().to(search))
-                    .route("/search", web::post().to(post_search))
-                    
-                    .route("/index", web::get().to(get_index))
-                    .route("/index", web::post().to(create_index))
-                    .route("/index/{index}", web::delete().to(delete_index))
-
-                    .route("/mappings/{index}", web::get().to(get_mapping))
-                    .route("/mappings", web::put().to(update_mapping))
-
-                    // #[delete("/api/document/{index}/{document_id}")]
-                    .route("/welcome", web::get().to(welcome))
-
-                    // Temporary
-                    .service(
-                        web::scope("/test")
-                            .route("add_data", web::get().to(hardcoded_data_for_testing))
-                    )
-            )
-        })
-    .bind(("127.0.0.1", 8080))?
-    .run()
-    .await
-}
\ No newline at end of file
+            .app_data(Data::from(search_backend.clone()))
+            .app_data(Data::new(auth_config.clone()))
+            .app_data(Data::new(rate_limit_config.clone()))
+            .app_data(rate_limiter.clone())
+            .app_data(Data::new(access_log_config.clone()))
+            .app_data(access_logger.clone())
+            .configure(api(cors_config.clone()))
+        });
+
+    if let Some(workers) = config.server.workers {
+        server = server.workers(workers);
+    }
+
+    let result = server
+        .bind(&config.server.bind)?
+        .run()
+        .await;
+
+    // Exports the spans still batched
+    if let Err(x) = tracer_provider.shutdown() {
+        log::warn!("Failed to export the last spans, {}", x);
+    }
+
+    result
+}
@@ -0,0 +1,175 @@
+use std::{
+    io::{self, Write},
+    sync::Mutex,
+    time::{Instant, SystemTime, UNIX_EPOCH}
+};
+
+use actix_web::{
+    body::{BoxBody, EitherBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::header::{HeaderName, HeaderValue},
+    middleware::Next,
+    web::Data,
+    HttpMessage
+};
+use percent_encoding::percent_decode_str;
+use serde_json::json;
+
+use crate::{
+    config::AccessLogConfig,
+    models::{ErrorTypes, api_keys::ApiKey, rate_limit::civil_from_days, request_context}
+};
+
+/// Header holding the id of a request, taken from the caller when valid and sent back in every response
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+/// Longest request id taken from the caller
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+/// Query parameters holding what users search for
+const SEARCH_TERM_PARAMS: [&str; 3] = ["search_term", "facet_query", "filters"];
+
+/// Id of the request being answered, in the request extensions
+#[derive(Clone, Debug)]
+pub struct RequestId(pub String);
+
+/// Where the access log lines are written
+pub struct AccessLogger {
+    output: Mutex<Box<dyn Write + Send>>
+}
+
+impl AccessLogger {
+    pub fn new(output: Box<dyn Write + Send>) -> Self {
+        Self { output: Mutex::new(output) }
+    }
+
+    pub fn stdout() -> Self {
+        Self::new(Box::new(io::stdout()))
+    }
+
+    fn write(&self, line: &str) {
+        let mut output = self.output.lock().unwrap();
+
+        if let Err(x) = writeln!(output, "{}", line).and_then(|_| output.flush()) {
+            log::warn!("Failed to write access log, {}", x);
+        }
+    }
+}
+
+/// Gives the request an id, sent back in the X-Request-Id header and in error bodies, and writes its access log line
+///
+/// Nothing is logged without an AccessLogger, a missing AccessLogConfig logs every request
+pub async fn access_log(req: ServiceRequest, next: Next<impl MessageBody>) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    let started = Instant::now();
+
+    let request_id = req
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|x| x.to_str().ok())
+        .filter(|x| is_valid_request_id(x))
+        .map(|x| x.to_string())
+        .unwrap_or_else(new_request_id);
+
+    req.extensions_mut().insert(RequestId(request_id.clone()));
+
+    let (resp, context) = request_context::scope(next.call(req)).await;
+    let resp = resp?;
+
+    if let Some(logger) = resp.request().app_data::<Data<AccessLogger>>() {
+        let config = resp.request().app_data::<Data<AccessLogConfig>>().map(|x| x.get_ref().clone()).unwrap_or_default();
+
+        if config.enabled && (resp.status().is_server_error() || sampled(config.sample_ratio)) {
+            let request = resp.request();
+            let key_id = request.extensions().get::<ApiKey>().map(|x| x.id.clone());
+
+            let line = json!({
+                "timestamp": timestamp(SystemTime::now()),
+                "request_id": request_id,
+                "method": request.method().as_str(),
+                "route": request.match_pattern(),
+                "path": request.path(),
+                "query": query_string(request.query_string(), config.redact_search_terms),
+                "index": context.index,
+                "status": resp.status().as_u16(),
+                "duration_ms": (started.elapsed().as_secs_f64() * 1e6).round() / 1e3,
+                "api_key": key_id,
+                "took_ms": context.took
+            });
+
+            logger.write(&line.to_string());
+        }
+    }
+
+    // Made of characters that are valid in a header
+    let header = HeaderValue::from_str(&request_id).unwrap();
+
+    let mut resp = match resp.response().error().and_then(|x| x.as_error::<ErrorTypes>()) {
+        Some(error) => {
+            let body = json!({"error": error.to_string(), "request_id": request_id}).to_string();
+            resp.map_body(|_, _| EitherBody::right(BoxBody::new(body)))
+        },
+        None => resp.map_into_left_body()
+    };
+
+    resp.headers_mut().insert(HeaderName::from_static("x-request-id"), header);
+
+    Ok(resp)
+}
+
+/// Ids of callers are kept when they are short and only hold letters, digits and - _ . :
+fn is_valid_request_id(id: &str) -> bool {
+    !id.is_empty() && id.len() <= MAX_REQUEST_ID_LENGTH && id.chars().all(|x| x.is_ascii_alphanumeric() || "-_.:".contains(x))
+}
+
+/// 32 random hex characters
+fn new_request_id() -> String {
+    let mut bytes = [0; 16];
+    openssl::rand::rand_bytes(&mut bytes).expect("Failed to generate request id");
+
+    bytes.iter().map(|x| format!("{:02x}", x)).collect()
+}
+
+fn sampled(ratio: f64) -> bool {
+    if ratio >= 1.0 {
+        return true;
+    }
+
+    let mut bytes = [0; 8];
+    openssl::rand::rand_bytes(&mut bytes).expect("Failed to sample access log");
+
+    (u64::from_le_bytes(bytes) as f64 / u64::MAX as f64) < ratio
+}
+
+/// The query string, with the values of the search term parameters replaced when redacted
+fn query_string(query: &str, redact_search_terms: bool) -> Option<String> {
+    if query.is_empty() {
+        return None;
+    }
+
+    if !redact_search_terms {
+        return Some(query.to_string());
+    }
+
+    let params: Vec<String> = query
+        .split('&')
+        .map(|param| match param.split_once('=') {
+            // Names are compared as the query is parsed, search%5Fterm is search_term
+            Some((name, _)) if SEARCH_TERM_PARAMS.contains(&percent_decode_str(name).decode_utf8_lossy().as_ref()) => format!("{}=[redacted]", name),
+            _ => param.to_string()
+        })
+        .collect();
+
+    Some(params.join("&"))
+}
+
+/// RFC 3339 time in UTC with milliseconds, such as 2024-05-01T12:30:00.250Z
+fn timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let seconds = since_epoch.as_secs();
+    let (year, month, day) = civil_from_days((seconds / 86400) as i64);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year, month, day, seconds % 86400 / 3600, seconds % 3600 / 60, seconds % 60, since_epoch.subsec_millis()
+    )
+}
@@ -0,0 +1,80 @@
+use actix_web::{
+    body::{EitherBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    middleware::Next,
+    web::Data,
+    HttpMessage
+};
+use openssl::memcmp;
+
+use crate::{
+    config::AuthConfig,
+    models::{
+        ErrorTypes,
+        api_keys::{ApiKey, split_api_key},
+        backend::SearchBackend,
+        secured_keys::{SECURED_KEY_ID, resolve_secured_key}
+    }
+};
+
+/// Header holding the API key
+pub const API_KEY_HEADER: &str = "X-API-Key";
+
+/// Routes that do not require an API key
+const PUBLIC_PATHS: [&str; 1] = ["/api/welcome"];
+
+/// Validates the API key of the request and adds it to the request, handlers check its role and indexes through ReqData<ApiKey>
+///
+/// Every request is let through as admin when authentication is disabled, a missing AuthConfig keeps it enabled
+pub async fn authenticate(req: ServiceRequest, next: Next<impl MessageBody>) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    if PUBLIC_PATHS.contains(&req.path()) {
+        return Ok(next.call(req).await?.map_into_left_body());
+    }
+
+    let config = req.app_data::<Data<AuthConfig>>().map(|x| x.get_ref().clone()).unwrap_or_default();
+
+    let key = match config.enabled {
+        true => {
+            let search_backend = req.app_data::<Data<dyn SearchBackend>>().cloned();
+            let full_key = req.headers().get(API_KEY_HEADER).and_then(|x| x.to_str().ok()).map(|x| x.trim().to_string());
+
+            let resolved = match search_backend {
+                Some(x) => resolve_key(&config, x.get_ref(), full_key.as_deref()).await,
+                None => Err(ErrorTypes::Unknown)
+            };
+
+            // Answered here so that the outer middlewares, such as CORS, still see a response
+            match resolved {
+                Ok(x) => x,
+                Err(x) => return Ok(req.error_response(x).map_into_right_body())
+            }
+        },
+        false => ApiKey::unrestricted("anonymous", "authentication disabled")
+    };
+
+    req.extensions_mut().insert(key);
+
+    Ok(next.call(req).await?.map_into_left_body())
+}
+
+/// Finds the key matching the full key sent, the admin key of the config first
+async fn resolve_key(config: &AuthConfig, search_backend: &dyn SearchBackend, full_key: Option<&str>) -> Result<ApiKey, ErrorTypes> {
+    let full_key = full_key.filter(|x| !x.is_empty()).ok_or(ErrorTypes::MissingApiKey)?;
+
+    if let Some(admin_key) = &config.admin_key {
+        if admin_key.len() == full_key.len() && memcmp::eq(admin_key.as_bytes(), full_key.as_bytes()) {
+            return Ok(ApiKey::unrestricted("admin", "admin key of the config"));
+        }
+    }
+
+    let (id, secret) = split_api_key(full_key).ok_or(ErrorTypes::InvalidApiKey)?;
+
+    if id == SECURED_KEY_ID {
+        return resolve_secured_key(search_backend, secret).await;
+    }
+
+    match search_backend.resolve_api_key(id).await? {
+        Some(stored) if stored.verify(secret) => Ok(stored.key),
+        _ => Err(ErrorTypes::InvalidApiKey)
+    }
+}
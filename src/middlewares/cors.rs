@@ -1,5 +1,33 @@
 use actix_cors::Cors;
 
-pub fn cors() -> Cors {
-    Cors::permissive()
-}
\ No newline at end of file
+use crate::{config::CorsPolicy, models::api_keys::pattern_matches};
+
+/// Response headers browsers may read, set by the rate limits, exports and access log
+const EXPOSED_HEADERS: [&str; 6] = ["Retry-After", "X-RateLimit-Limit", "X-RateLimit-Remaining", "X-RateLimit-Reset", "Content-Disposition", "X-Request-Id"];
+
+/// Builds the CORS middleware of a policy, requests from other origins are refused
+///
+/// Origins holding a "*" are patterns, "*" alone allows any origin
+pub fn cors(policy: &CorsPolicy) -> Cors {
+    let mut cors = Cors::default()
+        .allowed_methods(policy.allowed_methods.iter().map(|x| x.as_str()))
+        .allowed_headers(policy.allowed_headers.iter().map(|x| x.as_str()))
+        .expose_headers(EXPOSED_HEADERS)
+        .max_age(policy.max_age);
+
+    if policy.allow_credentials {
+        cors = cors.supports_credentials();
+    }
+
+    if policy.allowed_origins.iter().any(|x| x == "*") {
+        return cors.allow_any_origin();
+    }
+
+    policy.allowed_origins.iter().fold(cors, |cors, origin| match origin.contains('*') {
+        true => {
+            let pattern = origin.clone();
+            cors.allowed_origin_fn(move |origin, _| origin.to_str().map(|x| pattern_matches(&pattern, x)).unwrap_or(false))
+        },
+        false => cors.allowed_origin(origin)
+    })
+}
@@ -0,0 +1,31 @@
+use std::time::Instant;
+
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    middleware::Next
+};
+
+use crate::models::{ErrorTypes, metrics::METRICS};
+
+/// Records the count and latency of requests by method, route and status, the errors they returned and the requests in flight
+///
+/// The latency is the time to the response head, streamed bodies such as exports are not waited for
+pub async fn track_requests(req: ServiceRequest, next: Next<impl MessageBody>) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let _in_flight = METRICS.http_started();
+    let started = Instant::now();
+
+    let method = req.method().to_string();
+    // The route pattern, requests to every index share it
+    let route = req.match_pattern().unwrap_or_else(|| "unmatched".to_string());
+
+    let resp = next.call(req).await?;
+
+    if let Some(error) = resp.response().error().and_then(|x| x.as_error::<ErrorTypes>()) {
+        METRICS.count_error(error.name());
+    }
+
+    METRICS.observe_http(&method, &route, resp.status().as_u16(), started.elapsed());
+
+    Ok(resp)
+}
@@ -1 +1,6 @@
-pub mod cors;
\ No newline at end of file
+pub mod access_log;
+pub mod auth;
+pub mod cors;
+pub mod metrics;
+pub mod rate_limit;
+pub mod trace;
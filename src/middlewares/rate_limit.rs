@@ -0,0 +1,108 @@
+use actix_web::{
+    body::{EitherBody, MessageBody},
+    dev::{ServiceRequest, ServiceResponse},
+    http::{Method, header::{HeaderMap, HeaderName, HeaderValue}},
+    middleware::Next,
+    web::Data,
+    HttpMessage
+};
+
+use crate::{
+    config::{AuthConfig, RateLimitConfig},
+    models::{ErrorTypes, api_keys::ApiKey, rate_limit::{Allowance, RateLimiter}}
+};
+
+/// Limits requests per client IP and per API key, separately for searches and writes, and enforces the monthly quota of API keys
+///
+/// Runs after authenticate, which adds the API key to the request. Limits of secured keys apply even when rate limiting is disabled
+pub async fn rate_limit(req: ServiceRequest, next: Next<impl MessageBody>) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    let limiter = match req.app_data::<Data<RateLimiter>>().cloned() {
+        Some(x) => x,
+        None => return Ok(next.call(req).await?.map_into_left_body())
+    };
+
+    let config = req.app_data::<Data<RateLimitConfig>>().map(|x| x.get_ref().clone()).unwrap_or_default();
+    let auth_enabled = req.app_data::<Data<AuthConfig>>().map(|x| x.enabled).unwrap_or(true);
+
+    // Every request goes through the same key when authentication is disabled, only the IP limits mean anything then
+    let key = req.extensions().get::<ApiKey>().cloned().filter(|_| auth_enabled);
+
+    let (kind, key_limit, ip_limit) = match is_search(&req) {
+        true => ("search", config.search_per_minute, config.ip_search_per_minute),
+        false => ("write", config.write_per_minute, config.ip_write_per_minute)
+    };
+
+    let mut buckets = vec![];
+
+    if config.enabled {
+        if ip_limit > 0 {
+            buckets.push((format!("ip:{}:{}", client_ip(&req, config.trust_forwarded_for), kind), ip_limit));
+        }
+
+        if let (Some(key), true) = (&key, key_limit > 0) {
+            buckets.push((format!("key:{}:{}", key.id, kind), key_limit));
+        }
+    }
+
+    // Secured keys with the same restrictions and parent are the same key
+    if let Some(secured) = key.as_ref().and_then(|x| x.secured.as_ref()) {
+        if let Some(limit) = secured.rate_limit {
+            buckets.push((format!("secured:{}", serde_json::to_string(secured).unwrap()), limit));
+        }
+    }
+
+    // Either every bucket gives a token or none does, a request refused by one bucket does not use up the others
+    let tightest = match limiter.acquire(&buckets) {
+        Ok(x) => x,
+        Err(x) => {
+            let mut resp = req.error_response(ErrorTypes::RateLimited(x.retry_after));
+            insert_headers(resp.headers_mut(), &x);
+
+            return Ok(resp.map_into_right_body());
+        }
+    };
+
+    if let Some(key) = &key {
+        let quota = config.monthly_quota.filter(|_| config.enabled);
+
+        if let Err(retry_after) = limiter.record_operation(&key.id, quota) {
+            let error = ErrorTypes::QuotaExceeded{ quota: quota.unwrap_or_default(), retry_after };
+
+            return Ok(req.error_response(error).map_into_right_body());
+        }
+    }
+
+    let mut resp = next.call(req).await?;
+
+    if let Some(allowance) = tightest {
+        insert_headers(resp.headers_mut(), &allowance);
+    }
+
+    Ok(resp.map_into_left_body())
+}
+
+/// Searches and reads, every other request counts as a write
+fn is_search(req: &ServiceRequest) -> bool {
+    matches!(*req.method(), Method::GET | Method::HEAD | Method::OPTIONS) || req.path().starts_with("/api/search")
+}
+
+fn client_ip(req: &ServiceRequest, trust_forwarded_for: bool) -> String {
+    let ip = match trust_forwarded_for {
+        true => req.connection_info().realip_remote_addr().map(|x| x.to_string()),
+        false => req.peer_addr().map(|x| x.ip().to_string())
+    };
+
+    ip.unwrap_or_else(|| "unknown".to_string())
+}
+
+fn insert_headers(headers: &mut HeaderMap, allowance: &Allowance) {
+    let values = [
+        ("x-ratelimit-limit", allowance.limit as u64),
+        ("x-ratelimit-remaining", allowance.remaining as u64),
+        ("x-ratelimit-reset", allowance.reset)
+    ];
+
+    for (name, value) in values {
+        headers.insert(HeaderName::from_static(name), HeaderValue::from(value));
+    }
+}
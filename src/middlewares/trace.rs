@@ -0,0 +1,44 @@
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    middleware::Next,
+    HttpMessage
+};
+use tracing::{Instrument, field::Empty};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+use crate::{middlewares::access_log::RequestId, telemetry::extract_context};
+
+/// Runs the request in a span named after its route, continuing the trace of the traceparent header when the caller sent one
+///
+/// Spans of the handler and of the Elasticsearch requests it makes are nested in it
+pub async fn trace_requests(req: ServiceRequest, next: Next<impl MessageBody>) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let route = req.match_pattern().unwrap_or_else(|| "unmatched".to_string());
+    // Set by access_log, so that the trace of a logged request can be found
+    let request_id = req.extensions().get::<RequestId>().map(|x| x.0.clone());
+
+    let span = tracing::info_span!(
+        "request",
+        otel.name = format!("{} {}", req.method(), route),
+        otel.kind = "server",
+        otel.status_code = Empty,
+        http.request.method = %req.method(),
+        http.route = route,
+        url.path = req.path(),
+        request_id = request_id,
+        http.response.status_code = Empty
+    );
+
+    // A malformed traceparent starts a new trace
+    let _ = span.set_parent(extract_context(req.headers()));
+
+    let resp = next.call(req).instrument(span.clone()).await?;
+
+    span.record("http.response.status_code", resp.status().as_u16());
+
+    if resp.status().is_server_error() {
+        span.record("otel.status_code", "ERROR");
+    }
+
+    Ok(resp)
+}
@@ -0,0 +1,492 @@
+use std::{fmt, time::{SystemTime, UNIX_EPOCH}};
+
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use elasticsearch::{
+    DeleteParts, GetParts, IndexParts, SearchParts,
+    indices::{IndicesCreateParts, IndicesExistsParts, IndicesPutMappingParts},
+    params::Refresh
+};
+use openssl::{memcmp, rand::rand_bytes, sha::sha256};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use super::{EClient, ErrorTypes, pool::TRACEPARENT, request_context, secured_keys::{SecuredKeyParams, verifying_key}};
+
+/// Hidden index holding the API keys, it can not be reached through the index and document routes
+pub const API_KEYS_INDEX: &str = "dps-api-keys";
+
+/// Most API keys returned when listing them
+const MAX_API_KEYS: i64 = 10000;
+
+/// What an API key is allowed to do, every role can do what the roles before it can
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// Search, read documents, mappings and exports
+    Search,
+    /// Also create, update, delete and import documents
+    Write,
+    /// Also manage indexes, mappings and API keys
+    Admin
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Role::Search => "search",
+            Role::Write => "write",
+            Role::Admin => "admin"
+        })
+    }
+}
+
+/// An API key, without its secret
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ApiKey {
+    pub id: String,
+    pub name: String,
+    pub role: Role,
+    /// Patterns of the indexes the key can access, "*" matches any characters
+    pub indices: Vec<String>,
+    /// Seconds since the unix epoch
+    pub created_at: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rotated_at: Option<u64>,
+    /// Restrictions of the secured key the request was made with, never stored
+    #[serde(skip)]
+    pub secured: Option<SecuredKeyParams>
+}
+
+impl ApiKey {
+    /// An admin key over every index, used for the admin key of the config and when authentication is disabled
+    pub fn unrestricted(id: &str, name: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            name: name.to_string(),
+            role: Role::Admin,
+            indices: vec!["*".to_string()],
+            created_at: 0,
+            rotated_at: None,
+            secured: None
+        }
+    }
+
+    /// Fails if the role of the key is below role, secured keys can only go through authorize_search
+    pub fn require(&self, role: Role) -> Result<(), ErrorTypes> {
+        if self.secured.is_some() {
+            return Err(ErrorTypes::Forbidden(format!("secured API keys of [{}] can only search", self.id)));
+        }
+
+        match self.role >= role {
+            true => Ok(()),
+            false => Err(ErrorTypes::Forbidden(format!("API key [{}] requires the {} role", self.id, role)))
+        }
+    }
+
+    /// Fails if the role of the key is below role or if none of its patterns match index
+    ///
+    /// "*" as index stands for every index, only keys with a "*" pattern can access it
+    pub fn authorize(&self, role: Role, index: &str) -> Result<(), ErrorTypes> {
+        self.require(role)?;
+        self.check_index(index)
+    }
+
+    /// Authorizes a search on index, returns the filters of a secured key that the search has to apply
+    pub fn authorize_search(&self, index: &str) -> Result<Option<String>, ErrorTypes> {
+        match &self.secured {
+            Some(secured) => {
+                self.check_index(index)?;
+                Ok(secured.filters.clone())
+            },
+            None => self.authorize(Role::Search, index).map(|_| None)
+        }
+    }
+
+    fn check_index(&self, index: &str) -> Result<(), ErrorTypes> {
+        // Logged along with the request, whether or not the key can access it
+        request_context::record_index(index);
+
+        validate_index_name(index)?;
+
+        if index == API_KEYS_INDEX {
+            return Err(ErrorTypes::Forbidden(format!("index [{}] is reserved", index)));
+        }
+
+        match self.can_access(index) {
+            true => Ok(()),
+            false => Err(ErrorTypes::Forbidden(format!("API key [{}] can not access index [{}]", self.id, index)))
+        }
+    }
+
+    /// Whether one of the patterns of the key matches index, and one of the indices of its secured key if any, the reserved index never matches
+    pub fn can_access(&self, index: &str) -> bool {
+        let secured = self.secured
+            .as_ref()
+            .and_then(|x| x.indices.as_ref())
+            .map(|patterns| patterns.iter().any(|pattern| pattern_matches(pattern, index)))
+            .unwrap_or(true);
+
+        index != API_KEYS_INDEX && secured && self.indices.iter().any(|pattern| pattern_matches(pattern, index))
+    }
+
+    /// Whether every index matched by patterns is matched by the patterns of the key, so that admins only manage keys within their own indexes
+    pub fn covers(&self, patterns: &[String]) -> bool {
+        // The "*" of a covered pattern has to be taken by a "*" of a pattern of the key, which then matches whatever it stands for
+        patterns.iter().all(|covered| self.indices.iter().any(|pattern| pattern_matches(pattern, covered)))
+    }
+
+    /// Fails if the role of the key is below admin or if it does not cover the patterns of the key it creates or manages
+    pub fn authorize_key_management(&self, patterns: &[String]) -> Result<(), ErrorTypes> {
+        self.require(Role::Admin)?;
+
+        match self.covers(patterns) {
+            true => Ok(()),
+            false => Err(ErrorTypes::Forbidden(format!("API key [{}] can not manage keys over indexes [{}]", self.id, patterns.join(", "))))
+        }
+    }
+}
+
+/// An API key as it is stored, with the hash of its secret
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct StoredApiKey {
+    #[serde(flatten)]
+    pub key: ApiKey,
+    /// Hex SHA-256 of the secret
+    pub secret_hash: String,
+    /// Public key of the secured keys derived from this key, see models/secured_keys.rs
+    #[serde(default)]
+    pub verifying_key: String
+}
+
+impl StoredApiKey {
+    /// Creates a new key, returns it with the full key, "<id>.<secret>", which is never stored
+    pub fn generate(name: String, role: Role, indices: Vec<String>) -> Result<(Self, String), ErrorTypes> {
+        if name.trim().is_empty() {
+            return Err(ErrorTypes::InvalidApiKeyRequest("name can not be empty".to_string()));
+        }
+
+        if indices.is_empty() || indices.iter().any(|x| x.trim().is_empty()) {
+            return Err(ErrorTypes::InvalidApiKeyRequest("indices must hold at least one index pattern, \"*\" allows every index".to_string()));
+        }
+
+        validate_index_patterns(&indices)?;
+
+        let mut stored = Self {
+            key: ApiKey {
+                id: random_token(9),
+                name,
+                role,
+                indices,
+                created_at: now(),
+                rotated_at: None,
+                secured: None
+            },
+            secret_hash: String::new(),
+            verifying_key: String::new()
+        };
+
+        let full_key = stored.new_secret();
+        stored.key.rotated_at = None;
+
+        Ok((stored, full_key))
+    }
+
+    /// Replaces the secret, the previous secret stops working, returns the new full key
+    pub fn new_secret(&mut self) -> String {
+        let secret = random_token(32);
+
+        self.secret_hash = hash(&secret);
+        self.verifying_key = verifying_key(&secret);
+        self.key.rotated_at = Some(now());
+
+        format!("{}.{}", self.key.id, secret)
+    }
+
+    pub fn verify(&self, secret: &str) -> bool {
+        let hashed = hash(secret);
+
+        hashed.len() == self.secret_hash.len() && memcmp::eq(hashed.as_bytes(), self.secret_hash.as_bytes())
+    }
+}
+
+/// Splits a full key into its id and secret
+pub fn split_api_key(full_key: &str) -> Option<(&str, &str)> {
+    full_key.split_once('.').filter(|(id, secret)| !id.is_empty() && !secret.is_empty())
+}
+
+/// Checks that an index name targets a single index before it is authorized, "*" alone stands for every index
+///
+/// Elasticsearch reads commas and wildcards in the path as several indexes, which the patterns of the key would not be checked against
+pub fn validate_index_name(index: &str) -> Result<(), ErrorTypes> {
+    if index == "*" {
+        return Ok(());
+    }
+
+    check_name(index, false).map_err(|reason| ErrorTypes::BadDataRequest(format!("invalid index name [{}], {}", index, reason)))
+}
+
+/// Checks the index patterns of a key, they follow the rules of index names with "*" allowed anywhere
+pub fn validate_index_patterns(patterns: &[String]) -> Result<(), ErrorTypes> {
+    for pattern in patterns {
+        check_name(pattern, true).map_err(|reason| ErrorTypes::InvalidApiKeyRequest(format!("invalid index pattern [{}], {}", pattern, reason)))?;
+    }
+
+    Ok(())
+}
+
+/// The rules Elasticsearch applies to index names, along with no leading dot as those are system and hidden indexes
+fn check_name(name: &str, wildcards: bool) -> Result<(), &'static str> {
+    if name.is_empty() || name.len() > 255 {
+        return Err("must be between 1 and 255 bytes long");
+    }
+
+    if name.starts_with(['-', '_', '+', '.']) {
+        return Err("may not start with '-', '_', '+' or '.'");
+    }
+
+    if name.chars().any(|x| x.is_uppercase()) {
+        return Err("must be lowercase");
+    }
+
+    if name.contains(['\\', '/', '?', '"', '<', '>', '|', ',', '#', ':', ' ']) {
+        return Err("may not contain '\\', '/', '?', '\"', '<', '>', '|', ',', '#', ':' or spaces");
+    }
+
+    if !wildcards && name.contains('*') {
+        return Err("may not contain '*'");
+    }
+
+    Ok(())
+}
+
+/// Whether an index name matches a pattern where "*" matches any characters
+pub fn pattern_matches(pattern: &str, index: &str) -> bool {
+    let parts: Vec<&str> = pattern.split('*').collect();
+
+    let (first, last) = match parts.as_slice() {
+        [exact] => return *exact == index,
+        [first, .., last] => (*first, *last),
+        [] => return false
+    };
+
+    if index.len() < first.len() + last.len() || !index.starts_with(first) || !index.ends_with(last) {
+        return false;
+    }
+
+    let mut rest = &index[first.len()..index.len() - last.len()];
+
+    for part in &parts[1..parts.len() - 1] {
+        match rest.find(part) {
+            Some(position) => rest = &rest[position + part.len()..],
+            None => return false
+        }
+    }
+
+    true
+}
+
+fn random_token(bytes: usize) -> String {
+    let mut buf = vec![0; bytes];
+    rand_bytes(&mut buf).expect("Failed to generate random bytes");
+
+    URL_SAFE_NO_PAD.encode(buf)
+}
+
+pub(crate) fn hash(secret: &str) -> String {
+    sha256(secret.as_bytes()).iter().map(|x| format!("{:02x}", x)).collect()
+}
+
+pub(crate) fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|x| x.as_secs()).unwrap_or_default()
+}
+
+impl EClient {
+    /// Creates the API keys index if it does not exist yet, adds the fields of newer versions to the mapping otherwise
+    #[tracing::instrument(skip_all)]
+    async fn create_api_keys_index(&self) -> Result<(), ErrorTypes> {
+        let properties = json!({
+            "id": {"type": "keyword"},
+            "name": {"type": "keyword"},
+            "role": {"type": "keyword"},
+            "indices": {"type": "keyword"},
+            "created_at": {"type": "long"},
+            "rotated_at": {"type": "long"},
+            "secret_hash": {"type": "keyword", "index": false},
+            "verifying_key": {"type": "keyword", "index": false}
+        });
+
+        let exists = self.elastic
+            .send("exists", |es, traceparent| async move {
+                es.indices()
+                    .exists(IndicesExistsParts::Index(&[API_KEYS_INDEX]))
+                    .header(TRACEPARENT, traceparent)
+                    .send()
+                    .await
+            })
+            .await?;
+
+        if exists.status_code().is_success() {
+            let body = &json!({ "properties": properties });
+
+            let resp = self.elastic
+                .send("put_mapping", |es, traceparent| async move {
+                    es.indices()
+                        .put_mapping(IndicesPutMappingParts::Index(&[API_KEYS_INDEX]))
+                        .body(body)
+                        .header(TRACEPARENT, traceparent)
+                        .send()
+                        .await
+                })
+                .await?;
+
+            if !resp.status_code().is_success() {
+                return Err(ErrorTypes::from_response(resp).await);
+            }
+
+            return Ok(());
+        }
+
+        let body = &json!({
+            "settings": {
+                "index.hidden": true,
+                "index.number_of_shards": 1,
+                "index.auto_expand_replicas": "0-1"
+            },
+            "mappings": {
+                "dynamic": "strict",
+                "properties": properties
+            }
+        });
+
+        let resp = self.elastic
+            .send_once("create_index", |es, traceparent| async move {
+                es.indices()
+                    .create(IndicesCreateParts::Index(API_KEYS_INDEX))
+                    .body(body)
+                    .header(TRACEPARENT, traceparent)
+                    .send()
+                    .await
+            })
+            .await?;
+
+        // Another request may have created it in the meantime
+        if !resp.status_code().is_success() && resp.status_code() != StatusCode::BAD_REQUEST {
+            return Err(ErrorTypes::from_response(resp).await);
+        }
+
+        Ok(())
+    }
+
+    /// Returns an API key with the hash of its secret, None if it does not exist
+    #[tracing::instrument(skip_all, fields(id = id))]
+    pub async fn get_api_key(&self, id: &str) -> Result<Option<StoredApiKey>, ErrorTypes> {
+        let resp = self.elastic
+            .send("get", |es, traceparent| async move {
+                es.get(GetParts::IndexId(API_KEYS_INDEX, id))
+                    .header(TRACEPARENT, traceparent)
+                    .send()
+                    .await
+            })
+            .await?;
+
+        // Either the key or the whole index does not exist yet
+        if resp.status_code() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        if !resp.status_code().is_success() {
+            return Err(ErrorTypes::from_response(resp).await);
+        }
+
+        let mut json_resp = resp.json::<Value>().await?;
+
+        Ok(serde_json::from_value(json_resp["_source"].take()).ok())
+    }
+
+    /// Returns every API key, oldest first
+    #[tracing::instrument(skip_all)]
+    pub async fn list_api_keys(&self) -> Result<Vec<ApiKey>, ErrorTypes> {
+        let body = &json!({
+            "size": MAX_API_KEYS,
+            "sort": [{"created_at": "asc"}]
+        });
+
+        let resp = self.elastic
+            .send("search", |es, traceparent| async move {
+                es.search(SearchParts::Index(&[API_KEYS_INDEX]))
+                    .ignore_unavailable(true)
+                    .body(body)
+                    .header(TRACEPARENT, traceparent)
+                    .send()
+                    .await
+            })
+            .await?;
+
+        if !resp.status_code().is_success() {
+            return Err(ErrorTypes::from_response(resp).await);
+        }
+
+        let json_resp = resp.json::<Value>().await?;
+
+        Ok(json_resp["hits"]["hits"]
+            .as_array()
+            .map(|hits| hits.iter().filter_map(|x| serde_json::from_value::<StoredApiKey>(x["_source"].clone()).ok()).map(|x| x.key).collect())
+            .unwrap_or_default())
+    }
+
+    /// Creates or replaces an API key, it can be used as soon as this returns
+    #[tracing::instrument(skip_all, fields(id = key.key.id))]
+    pub async fn put_api_key(&self, key: &StoredApiKey) -> Result<(), ErrorTypes> {
+        self.create_api_keys_index().await?;
+
+        let id = key.key.id.as_str();
+
+        let resp = self.elastic
+            .send("index", |es, traceparent| async move {
+                es.index(IndexParts::IndexId(API_KEYS_INDEX, id))
+                    .refresh(Refresh::WaitFor)
+                    .body(key)
+                    .header(TRACEPARENT, traceparent)
+                    .send()
+                    .await
+            })
+            .await?;
+
+        // A rotated secret stops working on this instance right away
+        self.api_keys.invalidate(id);
+
+        if !resp.status_code().is_success() {
+            return Err(ErrorTypes::from_response(resp).await);
+        }
+
+        Ok(())
+    }
+
+    /// Deletes an API key, it stops working as soon as this returns
+    #[tracing::instrument(skip_all, fields(id = id))]
+    pub async fn delete_api_key(&self, id: &str) -> Result<(), ErrorTypes> {
+        let resp = self.elastic
+            .send("delete", |es, traceparent| async move {
+                es.delete(DeleteParts::IndexId(API_KEYS_INDEX, id))
+                    .refresh(Refresh::WaitFor)
+                    .header(TRACEPARENT, traceparent)
+                    .send()
+                    .await
+            })
+            .await?;
+
+        self.api_keys.invalidate(id);
+
+        let status_code = resp.status_code();
+
+        if !status_code.is_success() {
+            return Err(match status_code {
+                StatusCode::NOT_FOUND => ErrorTypes::ApiKeyNotFound(id.to_string()),
+                _ => ErrorTypes::from_response(resp).await
+            });
+        }
+
+        Ok(())
+    }
+}
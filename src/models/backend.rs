@@ -0,0 +1,164 @@
+use std::sync::Arc;
+
+use actix_web::web::Bytes;
+use async_trait::async_trait;
+use futures_util::stream::BoxStream;
+use serde_json::Value;
+
+use super::{EClient, ErrorTypes, documents::{BulkAction, BulkItemResult, BulkSummary, SearchQuery, SearchResult, FacetSearchResult}, export::ExportFormat, pool::NodeHealth, health::{ClusterHealth, HealthState}, schema::Schema, api_keys::{ApiKey, StoredApiKey}};
+
+/// Storage used by the HTTP API, every handler goes through it
+///
+/// Implemented by EClient for Elasticsearch, see models/memory.rs for the in-memory backend used by tests
+#[async_trait]
+pub trait SearchBackend: Send + Sync {
+    /// Creates a new index, mapped from its schema, or dynamically with geo_fields mapped as geo_point
+    async fn create_index(&self, index: &str, geo_fields: Vec<String>, schema: Option<Schema>) -> Result<(), ErrorTypes>;
+
+    async fn update_index_mappings(&self, index: &str, mappings: Value) -> Result<(), ErrorTypes>;
+
+    /// Returns every index if index is not supplied, or the specified index
+    async fn get_index(&self, index: Option<String>) -> Result<Value, ErrorTypes>;
+
+    async fn get_index_mappings(&self, index: &str) -> Result<Value, ErrorTypes>;
+
+    async fn delete_index(&self, index: &str) -> Result<(), ErrorTypes>;
+
+    /// Inserts a new document, returns the id of the document
+    async fn insert_document(&self, index: &str, data: Value, dynamic_mode: Option<String>) -> Result<String, ErrorTypes>;
+
+    /// Runs multiple document operations, returns the result of every operation in the same order
+    async fn bulk_documents(&self, index: &str, actions: Vec<BulkAction>, dynamic_mode: Option<String>) -> Result<BulkSummary, ErrorTypes>;
+
+    /// Runs a single batch of document operations as is, checking the index and normalizing the documents is left to the caller
+    async fn send_bulk_batch(&self, index: &str, batch: Vec<BulkAction>) -> Result<(i64, Vec<BulkItemResult>), ErrorTypes>;
+
+    async fn search_index(&self, index: &str, query: SearchQuery) -> Result<SearchResult, ErrorTypes>;
+
+    async fn search_facet_values(&self, index: &str, facet: &str, facet_query: Option<String>, query: SearchQuery) -> Result<FacetSearchResult, ErrorTypes>;
+
+    async fn get_document(&self, index: &str, document_id: &str, retrieve_fields: Option<String>) -> Result<Value, ErrorTypes>;
+
+    /// Partially updates a document, data holds the changes under "doc"
+    async fn update_document(&self, index: &str, document_id: &str, data: Value) -> Result<(), ErrorTypes>;
+
+    async fn delete_document(&self, index: &str, document_id: &str) -> Result<(), ErrorTypes>;
+
+    /// Returns a stream of every document of an index in the export format
+    async fn export_index(self: Arc<Self>, index: &str, format: ExportFormat, fields: Option<String>) -> Result<BoxStream<'static, Result<Bytes, ErrorTypes>>, ErrorTypes>;
+
+    /// Health of every node the backend connects to
+    fn node_health(&self) -> Vec<NodeHealth>;
+
+    /// Last known health of the cluster, with the unix time it was checked at
+    fn cached_health(&self) -> (HealthState, Option<u64>);
+
+    /// Asks the cluster for its health now
+    async fn check_health(&self) -> Result<ClusterHealth, ErrorTypes>;
+
+    /// Returns an API key with the hash of its secret, None if it does not exist
+    async fn get_api_key(&self, id: &str) -> Result<Option<StoredApiKey>, ErrorTypes>;
+
+    /// Returns the API key a request is authenticated with, from a cache when the backend keeps one
+    async fn resolve_api_key(&self, id: &str) -> Result<Option<StoredApiKey>, ErrorTypes>;
+
+    /// Returns every API key, oldest first
+    async fn list_api_keys(&self) -> Result<Vec<ApiKey>, ErrorTypes>;
+
+    /// Creates or replaces an API key
+    async fn put_api_key(&self, key: &StoredApiKey) -> Result<(), ErrorTypes>;
+
+    async fn delete_api_key(&self, id: &str) -> Result<(), ErrorTypes>;
+}
+
+#[async_trait]
+impl SearchBackend for EClient {
+    async fn create_index(&self, index: &str, geo_fields: Vec<String>, schema: Option<Schema>) -> Result<(), ErrorTypes> {
+        EClient::create_index(self, index, geo_fields, schema).await
+    }
+
+    async fn update_index_mappings(&self, index: &str, mappings: Value) -> Result<(), ErrorTypes> {
+        EClient::update_index_mappings(self, index, mappings).await
+    }
+
+    async fn get_index(&self, index: Option<String>) -> Result<Value, ErrorTypes> {
+        EClient::get_index(self, index).await
+    }
+
+    async fn get_index_mappings(&self, index: &str) -> Result<Value, ErrorTypes> {
+        EClient::get_index_mappings(self, index).await
+    }
+
+    async fn delete_index(&self, index: &str) -> Result<(), ErrorTypes> {
+        EClient::delete_index(self, index).await
+    }
+
+    async fn insert_document(&self, index: &str, data: Value, dynamic_mode: Option<String>) -> Result<String, ErrorTypes> {
+        EClient::insert_document(self, index, data, dynamic_mode).await
+    }
+
+    async fn bulk_documents(&self, index: &str, actions: Vec<BulkAction>, dynamic_mode: Option<String>) -> Result<BulkSummary, ErrorTypes> {
+        EClient::bulk_documents(self, index, actions, dynamic_mode).await
+    }
+
+    async fn send_bulk_batch(&self, index: &str, batch: Vec<BulkAction>) -> Result<(i64, Vec<BulkItemResult>), ErrorTypes> {
+        EClient::send_bulk_batch(self, index, batch).await
+    }
+
+    async fn search_index(&self, index: &str, query: SearchQuery) -> Result<SearchResult, ErrorTypes> {
+        EClient::search_index(self, index, query).await
+    }
+
+    async fn search_facet_values(&self, index: &str, facet: &str, facet_query: Option<String>, query: SearchQuery) -> Result<FacetSearchResult, ErrorTypes> {
+        EClient::search_facet_values(self, index, facet, facet_query, query).await
+    }
+
+    async fn get_document(&self, index: &str, document_id: &str, retrieve_fields: Option<String>) -> Result<Value, ErrorTypes> {
+        EClient::get_document(self, index, document_id, retrieve_fields).await
+    }
+
+    async fn update_document(&self, index: &str, document_id: &str, data: Value) -> Result<(), ErrorTypes> {
+        EClient::update_document(self, index, document_id, data).await
+    }
+
+    async fn delete_document(&self, index: &str, document_id: &str) -> Result<(), ErrorTypes> {
+        EClient::delete_document(self, index, document_id).await
+    }
+
+    async fn export_index(self: Arc<Self>, index: &str, format: ExportFormat, fields: Option<String>) -> Result<BoxStream<'static, Result<Bytes, ErrorTypes>>, ErrorTypes> {
+        let stream = EClient::export_index(self, index, format, fields).await?;
+        Ok(Box::pin(stream))
+    }
+
+    fn node_health(&self) -> Vec<NodeHealth> {
+        EClient::node_health(self)
+    }
+
+    fn cached_health(&self) -> (HealthState, Option<u64>) {
+        self.health.state()
+    }
+
+    async fn check_health(&self) -> Result<ClusterHealth, ErrorTypes> {
+        EClient::check_health(self).await
+    }
+
+    async fn get_api_key(&self, id: &str) -> Result<Option<StoredApiKey>, ErrorTypes> {
+        EClient::get_api_key(self, id).await
+    }
+
+    async fn resolve_api_key(&self, id: &str) -> Result<Option<StoredApiKey>, ErrorTypes> {
+        EClient::resolve_api_key(self, id).await
+    }
+
+    async fn list_api_keys(&self) -> Result<Vec<ApiKey>, ErrorTypes> {
+        EClient::list_api_keys(self).await
+    }
+
+    async fn put_api_key(&self, key: &StoredApiKey) -> Result<(), ErrorTypes> {
+        EClient::put_api_key(self, key).await
+    }
+
+    async fn delete_api_key(&self, id: &str) -> Result<(), ErrorTypes> {
+        EClient::delete_api_key(self, id).await
+    }
+}
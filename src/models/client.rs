@@ -1,33 +1,154 @@
+use std::{fs, net::TcpStream, sync::Arc, time::Duration};
+
 use elasticsearch::{
-    Elasticsearch,
-    http::{transport::{TransportBuilder,SingleNodeConnectionPool}},
+    auth::Credentials,
+    cert::Certificate,
 };
-use reqwest::{Url};
+use openssl::{hash::MessageDigest, ssl::{SslConnector, SslMethod, SslVerifyMode}};
+use reqwest::Url;
+
+use crate::config::{Config, ConfigError, ElasticConfig, IndexConfig};
+
+use super::{health::HealthMonitor, index_cache::IndexCache, key_cache::ApiKeyCache, pool::{NodePool, NodeHealth, Trust}};
+
+/// How long fetching the certificate chain to pin may take
+const PIN_TIMEOUT: Duration = Duration::from_secs(10);
 
 pub struct EClient {
-    pub elastic: Elasticsearch
+    pub elastic: NodePool,
+    /// Health of the cluster, kept up to date by monitor_health_every
+    pub health: HealthMonitor,
+    /// Existence, mappings and settings of the indexes, so that requests do not ask for them first
+    pub indices: IndexCache,
+    /// API keys that authenticated requests, so that requests do not fetch their key first
+    pub api_keys: ApiKeyCache,
+    /// Shards and replicas of new indexes
+    pub index_defaults: IndexConfig,
+    /// Amount of search results when count is not given
+    pub default_page_size: i64
 }
 
 impl EClient {
 
     /// Creates a new instance of EClient
-    /// 
-    /// Connects to an instance of ElasticSearch server
-    /// 
-    /// Url: IP with Port (ex: "http://192.168.0.1:9200")
-    pub fn new(url: &str) -> Self {
-        
-        let conn_url = Url::parse(url).unwrap();
-
-        // Elasticsearch
-
-        let conn_pool = SingleNodeConnectionPool::new(conn_url);
-        let builder = TransportBuilder::new(conn_pool);
-    
-        let transport = builder.build().unwrap();
-        
-        Self{
-            elastic: Elasticsearch::new(transport)
+    ///
+    /// Connects to the Elasticsearch nodes of the config, the config must already be validated
+    ///
+    /// Fails if the CA certificate cannot be loaded or no node presents a certificate with the fingerprint
+    pub fn new(config: &Config) -> Result<Self, ConfigError> {
+
+        let urls: Vec<Url> = config.elasticsearch.nodes.iter().map(|x| Url::parse(x).unwrap()).collect();
+
+        let trust = trust(&config.elasticsearch, &urls)?;
+
+        Ok(Self{
+            elastic: NodePool::new(urls, credentials(&config.elasticsearch), trust),
+            health: HealthMonitor::default(),
+            indices: IndexCache::new(Duration::from_secs(config.elasticsearch.index_cache_ttl)),
+            api_keys: ApiKeyCache::new(Duration::from_secs(config.elasticsearch.api_key_cache_ttl)),
+            index_defaults: config.index.clone(),
+            default_page_size: config.search.default_page_size
+        })
+    }
+
+    /// Sniffs the nodes of the cluster every interval, forever
+    pub async fn sniff_every(self: Arc<Self>, interval: Duration) {
+        loop {
+            match self.elastic.sniff().await {
+                Ok(found) => log::debug!("Sniffed {} Elasticsearch nodes", found),
+                Err(x) => log::warn!("Failed to sniff Elasticsearch nodes, {}", x)
+            }
+
+            actix_web::rt::time::sleep(interval).await;
+        }
+    }
+
+    /// Health of every Elasticsearch node
+    pub fn node_health(&self) -> Vec<NodeHealth> {
+        self.elastic.health()
+    }
+}
+
+/// The auth method given in the config, at most one is set
+fn credentials(config: &ElasticConfig) -> Option<Credentials> {
+    if let (Some(username), Some(password)) = (&config.username, &config.password) {
+        return Some(Credentials::Basic(username.clone(), password.clone()));
+    }
+
+    if let Some((id, api_key)) = config.api_key_parts() {
+        return Some(Credentials::ApiKey(id, api_key));
+    }
+
+    config.bearer_token.clone().map(Credentials::Bearer)
+}
+
+/// How the certificates of the nodes are checked
+///
+/// With a fingerprint, the nodes are tried in order until one presents a certificate with it.
+/// That certificate is then trusted as a CA for every node, as with ca_certificate, so the fingerprint should be the one of the CA of the cluster and not of a single node
+fn trust(config: &ElasticConfig, nodes: &[Url]) -> Result<Trust, ConfigError> {
+    if !config.tls.verify_certificates {
+        log::warn!("Certificates of Elasticsearch nodes are not verified, do not use this outside of local development");
+        return Ok(Trust::Insecure);
+    }
+
+    if let Some(path) = &config.tls.ca_certificate {
+        let pem = fs::read(path).map_err(|x| ConfigError::Certificate(path.clone(), x.to_string()))?;
+        Certificate::from_pem(&pem).map_err(|x| ConfigError::Certificate(path.clone(), x.to_string()))?;
+
+        return Ok(Trust::Ca(pem));
+    }
+
+    if let Some(fingerprint) = config.ca_fingerprint_bytes() {
+        let mut error = ConfigError::Fingerprint("any node".to_string(), "no node is configured".to_string());
+
+        for node in nodes {
+            match pinned_certificate(node, &fingerprint) {
+                Ok(pem) => return Ok(Trust::Ca(pem)),
+                Err(x) => {
+                    log::warn!("Failed to pin the certificate of {}, {}", node, x);
+                    error = ConfigError::Fingerprint(node.to_string(), x);
+                }
+            }
         }
+
+        return Err(error);
     }
-}
\ No newline at end of file
+
+    Ok(Trust::System)
+}
+
+/// Fetches the certificate chain of the node without verifying it, returns the PEM of the certificate with the fingerprint
+fn pinned_certificate(node: &Url, fingerprint: &[u8]) -> Result<Vec<u8>, String> {
+    let host = node.host_str().ok_or("missing host")?;
+    let addresses = node.socket_addrs(|| None).map_err(|x| x.to_string())?;
+
+    let stream = addresses
+        .iter()
+        .find_map(|x| TcpStream::connect_timeout(x, PIN_TIMEOUT).ok())
+        .ok_or("failed to connect")?;
+    stream.set_read_timeout(Some(PIN_TIMEOUT)).map_err(|x| x.to_string())?;
+
+    let mut connector = SslConnector::builder(SslMethod::tls()).map_err(|x| x.to_string())?;
+    connector.set_verify(SslVerifyMode::NONE);
+
+    let tls = connector
+        .build()
+        .configure()
+        .map_err(|x| x.to_string())?
+        .verify_hostname(false)
+        .connect(host, stream)
+        .map_err(|x| x.to_string())?;
+
+    let chain = tls.ssl().peer_cert_chain().ok_or("no certificate was presented")?;
+
+    for certificate in chain {
+        let digest = certificate.digest(MessageDigest::sha256()).map_err(|x| x.to_string())?;
+
+        if digest.as_ref() == fingerprint {
+            return certificate.to_pem().map_err(|x| x.to_string());
+        }
+    }
+
+    Err("no certificate of the chain matches the fingerprint".to_string())
+}
@@ -0,0 +1,85 @@
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use elasticsearch::OpenPointInTimeParts;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use super::{EClient, ErrorTypes, pool::TRACEPARENT};
+
+/// How long a point in time is kept open between two pages
+pub const CURSOR_KEEP_ALIVE: &str = "1m";
+
+/// Value of the cursor parameter that starts a new cursor
+pub const NEW_CURSOR: &str = "*";
+
+/// Position in a stream of search results, handed to clients as an opaque string
+#[derive(Serialize, Deserialize)]
+pub struct Cursor {
+    pub index: String,
+    pub pit_id: String,
+    /// Sort values of the last returned document
+    pub search_after: Vec<Value>
+}
+
+impl Cursor {
+    pub fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).unwrap())
+    }
+
+    /// Decodes a cursor, checking that it belongs to the index being searched
+    pub fn decode(cursor: &str, index: &str) -> Result<Self, ErrorTypes> {
+        let cursor: Cursor = URL_SAFE_NO_PAD
+            .decode(cursor)
+            .ok()
+            .and_then(|x| serde_json::from_slice(&x).ok())
+            .ok_or(ErrorTypes::InvalidCursor)?;
+
+        if cursor.index != index {
+            return Err(ErrorTypes::InvalidCursor);
+        }
+
+        Ok(cursor)
+    }
+}
+
+impl EClient {
+    /// Opens a point in time on an index, returns its id
+    #[tracing::instrument(skip_all, fields(index = index))]
+    pub async fn open_point_in_time(&self, index: &str) -> Result<String, ErrorTypes> {
+        let resp = self.elastic
+            .send("open_point_in_time", |es, traceparent| async move {
+                es.open_point_in_time(OpenPointInTimeParts::Index(&[index]))
+                    .keep_alive(CURSOR_KEEP_ALIVE)
+                    .header(TRACEPARENT, traceparent)
+                    .send()
+                    .await
+            })
+            .await?;
+
+        if !resp.status_code().is_success() {
+            return Err(ErrorTypes::from_response(resp).await);
+        }
+
+        let json_resp = resp.json::<Value>().await?;
+
+        match json_resp["id"].as_str() {
+            Some(id) => Ok(id.to_string()),
+            None => Err(ErrorTypes::Unknown)
+        }
+    }
+
+    /// Closes a point in time, failures are ignored as the point in time expires on its own
+    #[tracing::instrument(skip_all)]
+    pub async fn close_point_in_time(&self, pit_id: &str) {
+        let body = &json!({"id": pit_id});
+
+        let _ = self.elastic
+            .send("close_point_in_time", |es, traceparent| async move {
+                es.close_point_in_time()
+                    .body(body)
+                    .header(TRACEPARENT, traceparent)
+                    .send()
+                    .await
+            })
+            .await;
+    }
+}
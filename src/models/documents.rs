@@ -1,245 +1,889 @@
-use actix_web::HttpResponse;
-use elasticsearch::{IndexParts, UpdateParts, SearchParts, GetSourceParts, DeleteParts};
+use elasticsearch::{IndexParts, UpdateParts, SearchParts, GetSourceParts, DeleteParts, BulkParts, BulkOperation};
+use std::collections::HashMap;
+
 use reqwest::StatusCode;
-use serde_json::{Value, json};
+use serde::Serialize;
+use serde_json::{Value, json, Map};
+
+use super::{EClient, ErrorTypes, helpers::{mapping_field_types, is_numeric_type, exact_value_field}, filters::parse_search_filters, sort::build_sort, highlight::Highlight, geo::{GeoSearch, normalize_geo_points}, cursor::{Cursor, NEW_CURSOR, CURSOR_KEEP_ALIVE}, metrics::METRICS, schema::write_schema, pool::TRACEPARENT, request_context};
+
+/// Amount of operations sent to Elasticsearch in a single _bulk request
+pub const BULK_BATCH_SIZE: usize = 1000;
+
+/// Values returned per facet when max_values_per_facet is not supplied
+pub const DEFAULT_MAX_VALUES_PER_FACET: i64 = 10;
+
+/// Most values max_values_per_facet can ask for
+pub const MAX_VALUES_PER_FACET: i64 = 1000;
+
+/// A single operation of a bulk request
+pub enum BulkAction {
+    /// Indexes a document, replacing it if the id already exists
+    Index { document_id: Option<String>, data: Value },
+    /// Indexes a document, fails if the id already exists
+    Create { document_id: String, data: Value },
+    /// Partially updates an existing document
+    Update { document_id: String, data: Value },
+    /// Deletes an existing document
+    Delete { document_id: String }
+}
+
+impl BulkAction {
+    /// Name of the action as used by the _bulk API
+    pub fn name(&self) -> &'static str {
+        match self {
+            BulkAction::Index { .. } => "index",
+            BulkAction::Create { .. } => "create",
+            BulkAction::Update { .. } => "update",
+            BulkAction::Delete { .. } => "delete",
+        }
+    }
 
-use super::{EClient, ErrorTypes, helpers::{server_down_check, index_exists_check}};
+    fn document_id(&self) -> Option<&str> {
+        match self {
+            BulkAction::Index { document_id, .. } => document_id.as_deref(),
+            BulkAction::Create { document_id, .. } | BulkAction::Update { document_id, .. } | BulkAction::Delete { document_id } => Some(document_id),
+        }
+    }
 
-impl EClient {
-    /// Inserts a new document into index
-    pub async fn insert_document(&self, index: &str, data: Value, dynamic_mode: Option<String>) -> HttpResponse{
+    fn into_operation(self) -> BulkOperation<Value> {
+        match self {
+            BulkAction::Index { document_id: Some(id), data } => BulkOperation::index(data).id(id).into(),
+            BulkAction::Index { document_id: None, data } => BulkOperation::index(data).into(),
+            BulkAction::Create { document_id, data } => BulkOperation::create(document_id, data).into(),
+            // doc is required for updating, see update_document
+            BulkAction::Update { document_id, data } => BulkOperation::update(document_id, json!({"doc": data})).into(),
+            BulkAction::Delete { document_id } => BulkOperation::delete(document_id).into(),
+        }
+    }
+}
+
+/// Parameters of a search request
+#[derive(Default)]
+pub struct SearchQuery {
+    pub search_term: Option<String>,
+    /// Fields to search in, comma separated
+    pub search_in: Option<String>,
+    /// Fields to return, comma separated
+    pub return_fields: Option<String>,
+    pub from: Option<i64>,
+    pub count: Option<i64>,
+    /// Filter expression, see models/filters.rs for the syntax
+    pub filters: Option<String>,
+    /// Fields to return facet counts for, comma separated
+    pub facets: Option<String>,
+    pub max_values_per_facet: Option<i64>,
+    /// Sort entries or the name of a sort strategy, see models/sort.rs for the syntax
+    pub sort: Option<String>,
+    /// Fields to highlight, comma separated
+    pub highlight_fields: Option<String>,
+    /// Fields to snippet with an optional word count, comma separated (ex: "description:20,name")
+    pub snippet_fields: Option<String>,
+    pub highlight_pre_tag: Option<String>,
+    pub highlight_post_tag: Option<String>,
+    /// geo_point field used by the geo search, see models/geo.rs for the parameters
+    pub geo_field: Option<String>,
+    pub around_lat_lng: Option<String>,
+    /// Radius in meters
+    pub around_radius: Option<i64>,
+    pub inside_bounding_box: Option<String>,
+    pub inside_polygon: Option<String>,
+    /// "*" starts a cursor, otherwise the next_cursor of the previous page, see models/cursor.rs
+    pub cursor: Option<String>,
+    /// Filter expression of the secured API key of the request, never taken from the request itself
+    pub secured_filters: Option<String>
+}
+
+impl SearchQuery {
+    /// Most values returned per facet, BadDataRequest outside of 1 to MAX_VALUES_PER_FACET
+    pub fn max_values_per_facet(&self) -> Result<i64, ErrorTypes> {
+        match self.max_values_per_facet.unwrap_or(DEFAULT_MAX_VALUES_PER_FACET) {
+            x @ 1..=MAX_VALUES_PER_FACET => Ok(x),
+            x => Err(ErrorTypes::BadDataRequest(format!("max_values_per_facet must be between 1 and {}, got {}", MAX_VALUES_PER_FACET, x)))
+        }
+    }
+
+    /// Offset of the first result of the page along with the amount of results, BadDataRequest for negative values or offsets too large to count
+    pub fn page(&self, default_count: i64) -> Result<(i64, i64), ErrorTypes> {
+        let from = self.from.unwrap_or(0);
+        let count = self.count.unwrap_or(default_count);
 
-        match server_down_check(&self.elastic).await{
-            Ok(()) => (),
-            Err(x) => return x
+        if from < 0 || count < 0 {
+            return Err(ErrorTypes::BadDataRequest(format!("from and count can not be negative, got from {} and count {}", from, count)));
+        }
+
+        // The end of the page is counted as well, against the result window
+        from.checked_mul(count)
+            .filter(|x| x.checked_add(count).is_some())
+            .map(|x| (x, count))
+            .ok_or_else(|| ErrorTypes::BadDataRequest(format!("page {} of {} results is too large", from, count)))
+    }
+}
+
+pub fn split_fields(fields: &str) -> Vec<String> {
+    fields.split(',').map(|x| x.trim().to_string()).filter(|x| !x.is_empty()).collect()
+}
+
+/// Builds the aggregations used for facets, named by the position of the facet
+/// 
+/// Every facet gets a terms aggregation, numeric facets get a stats aggregation as well
+fn build_facet_aggregations(facets: &[String], fields: &HashMap<String, String>, max_values: i64) -> Result<Value, ErrorTypes> {
+    let mut aggregations = Map::new();
+
+    for (position, facet) in facets.iter().enumerate() {
+        let field_type = match fields.get(facet) {
+            Some(x) => x,
+            None => return Err(ErrorTypes::FieldNotFound(facet.to_string()))
         };
 
-        match index_exists_check(&self.elastic, index).await{
-            Ok(()) => (),
-            Err(x) => return x
+        let field = match exact_value_field(fields, facet) {
+            Some(x) => x,
+            None => return Err(ErrorTypes::FieldNotFacetable(facet.to_string()))
         };
-  
-        match dynamic_mode {
-            Some(mode) => {
-                let set_dynamic = json!({
-                    "dynamic": mode
-                });
-        
-                self.update_index_mappings(index, set_dynamic).await;
-            },
-            None => (),
+
+        aggregations.insert(format!("values_{}", position), json!({
+            "terms": {
+                "field": field,
+                "size": max_values
+            }
+        }));
+
+        if is_numeric_type(field_type) {
+            aggregations.insert(format!("stats_{}", position), json!({
+                "stats": {
+                    "field": field
+                }
+            }));
         }
+    }
+
+    Ok(Value::Object(aggregations))
+}
+
+/// Converts the aggregation results into a map of facet to its value counts and stats
+fn parse_facets(facets: &[String], aggregations: &Value) -> Value {
+    let mut result = Map::new();
+
+    for (position, facet) in facets.iter().enumerate() {
+        let values: Map<String, Value> = aggregations[format!("values_{}", position)]["buckets"]
+            .as_array()
+            .map(|buckets| buckets.iter().map(|bucket| {
+                let key = match bucket.get("key_as_string") {
+                    Some(Value::String(x)) => x.to_string(),
+                    _ => match &bucket["key"] {
+                        Value::String(x) => x.to_string(),
+                        x => x.to_string()
+                    }
+                };
+                (key, bucket["doc_count"].clone())
+            }).collect())
+            .unwrap_or_default();
+
+        let mut facet_result = json!({
+            "values": values
+        });
+
+        if let Some(stats) = aggregations.get(format!("stats_{}", position)) {
+            facet_result["stats"] = json!({
+                "min": stats["min"],
+                "max": stats["max"],
+                "avg": stats["avg"]
+            });
+        }
+
+        result.insert(facet.to_string(), facet_result);
+    }
+
+    Value::Object(result)
+}
+
+/// Builds a case insensitive regex that matches values having a word starting with the facet query
+/// 
+/// Used as the include pattern of a terms aggregation, every non alphanumeric character is escaped
+pub fn facet_query_pattern(facet_query: &str) -> String {
+    let mut pattern = "(.*[^a-zA-Z0-9])?".to_string();
+
+    for c in facet_query.chars() {
+        let mut lower = c.to_lowercase();
+        let mut upper = c.to_uppercase();
+
+        // Characters whose other case is several characters (ex: ß and SS) can not be put in a character class, they match as written
+        let cases = match (lower.next(), lower.next(), upper.next(), upper.next()) {
+            (Some(lower), None, Some(upper), None) if lower != upper => Some((lower, upper)),
+            _ => None
+        };
+
+        if let Some((lower, upper)) = cases {
+            pattern.push_str(&format!("[{}{}]", lower, upper));
+        } else if c.is_alphanumeric() {
+            pattern.push(c);
+        } else {
+            pattern.push('\\');
+            pattern.push(c);
+        }
+    }
+
+    pattern.push_str(".*");
+    pattern
+}
+
+/// Builds the query clause of a search body
+/// 
+/// Returns everything if there is no search term, filters are added as a non scoring bool filter
+fn build_search_query(search_term: Option<String>, search_in: Option<String>, filters: Vec<Value>) -> Value {
+    let fields_to_search: Option<Vec<String>> = search_in.map(|val| split_fields(&val));
+
+    let query = match search_term {
+        Some(term) => json!({
+            "query_string": {
+                "query": term,
+                "type": "cross_fields",
+                "fields": fields_to_search.unwrap_or(vec!["*".to_string()]),
+                "minimum_should_match": "75%"
+            }
+        }),
+        None => json!({
+            "match_all": {}
+        })
+    };
+
+    if filters.is_empty() {
+        return query;
+    }
+
+    json!({
+        "bool": {
+            "must": query,
+            "filter": filters
+        }
+    })
+}
+
+/// Result of a single bulk operation
+#[derive(Serialize)]
+pub struct BulkItemResult {
+    /// Position of the operation in the request
+    pub position: usize,
+    pub action: &'static str,
+    pub document_id: Option<String>,
+    pub status: u16,
+    /// Reason of the failure, None if the operation succeeded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Result of a successful operation (ex: created, updated, deleted)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<String>
+}
+
+/// Results of a bulk request
+#[derive(Serialize)]
+pub struct BulkSummary {
+    pub took: i64,
+    pub total: usize,
+    pub successful: usize,
+    pub failed: usize,
+    pub items: Vec<BulkItemResult>
+}
+
+/// A page of search results
+#[derive(Serialize)]
+pub struct SearchResult {
+    pub took: i64,
+    pub data: Vec<Value>,
+    pub total_data: i64,
+    /// "eq" if total_data is exact, "gte" if it is a lower bound
+    pub match_type: String,
+    /// Value counts of the requested facets
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub facets: Option<Value>,
+    /// Only returned when searching with a cursor, None once the last page is reached
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<Option<String>>
+}
+
+/// A value of a facet with the amount of documents having it
+#[derive(Serialize)]
+pub struct FacetHit {
+    pub value: Value,
+    pub count: i64
+}
+
+/// Result of a search in the values of a facet
+#[derive(Serialize)]
+pub struct FacetSearchResult {
+    pub took: i64,
+    pub facet_hits: Vec<FacetHit>
+}
+
+impl EClient {
+    /// Sends a single _bulk request, returns the time it took and the result of every operation in order
+    /// 
+    /// Documents are sent as is, checking the index and normalizing the documents is left to the caller
+    #[tracing::instrument(skip_all, fields(index = index, operations = batch.len()))]
+    pub async fn send_bulk_batch(&self, index: &str, batch: Vec<BulkAction>) -> Result<(i64, Vec<BulkItemResult>), ErrorTypes> {
+        let batch_info: Vec<(&'static str, Option<String>)> = batch.iter().map(|x| (x.name(), x.document_id().map(|id| id.to_string()))).collect();
+        let operations: Vec<BulkOperation<Value>> = batch.into_iter().map(|x| x.into_operation()).collect();
+
+        let operations = &operations;
 
         let resp = self.elastic
-            .index(IndexParts::Index(index))
-            .body(data)
-            .send()
-            .await
-            .unwrap();
+            .send_once("bulk", |es, traceparent| async move {
+                es.bulk(BulkParts::Index(index))
+                    .body(operations.iter().collect())
+                    .header(TRACEPARENT, traceparent)
+                    .send()
+                    .await
+            })
+            .await?;
 
         let status_code = resp.status_code();
 
+        // The whole batch was rejected, every operation in it is marked as failed
         if !status_code.is_success() {
-            let error = match status_code{
-                StatusCode::BAD_REQUEST => ErrorTypes::BadDataRequest.to_string(),
-                _ => ErrorTypes::Unknown.to_string()
+            let reason = match ErrorTypes::from_response(resp).await {
+                ErrorTypes::BadDataRequest(x) | ErrorTypes::Elasticsearch{ reason: x, .. } => x,
+                x => x.to_string()
             };
-            return HttpResponse::build(status_code).json(json!({"error": error}));
+            let results = batch_info.into_iter().enumerate().map(|(position, (action, document_id))| BulkItemResult {
+                position,
+                action,
+                document_id,
+                status: status_code.as_u16(),
+                error: Some(reason.clone()),
+                result: None
+            }).collect();
+            return Ok((0, results));
         }
 
-        let set_dynamic = json!({
-            "dynamic": "strict"
-        });
-            
-        self.update_index_mappings(index, set_dynamic).await;
+        let json_resp = resp.json::<Value>().await?;
+
+        let results = json_resp["items"].as_array().cloned().unwrap_or_default();
+        let results = batch_info.into_iter().zip(results).enumerate().map(|(position, ((action, document_id), result))| {
+            let result = &result[action];
+            BulkItemResult {
+                position,
+                action,
+                document_id: result["_id"].as_str().map(|x| x.to_string()).or(document_id),
+                status: result["status"].as_u64().unwrap_or_default() as u16,
+                error: result.get("error").map(|x| x["reason"].as_str().unwrap_or("Unknown error occured").to_string()),
+                result: result["result"].as_str().map(|x| x.to_string())
+            }
+        }).collect();
+
+        Ok((json_resp["took"].as_i64().unwrap_or(0), results))
+    }
+
+    /// Inserts a new document into index, returns the id of the document
+    #[tracing::instrument(skip_all, fields(index = index))]
+    pub async fn insert_document(&self, index: &str, mut data: Value, dynamic_mode: Option<String>) -> Result<String, ErrorTypes>{
+
+        self.health.ensure_reachable()?;
+
+        let metadata = self.index_metadata(index).await?;
+
+        let mappings = &metadata.mappings;
+        normalize_geo_points(&mut data, &mapping_field_types(mappings));
+
+        let schema = write_schema(mappings, dynamic_mode.as_deref())?;
+
+        if let Some(schema) = &schema {
+            schema.validate(&data).map_err(ErrorTypes::InvalidDocument)?;
+        }
+  
+        // The mode is set on the whole index until the write is done, writes made at the same time share it, see the README
+        if let Some(mode) = dynamic_mode {
+            let set_dynamic = json!({
+                "dynamic": mode
+            });
+        
+            self.update_index_mappings(index, set_dynamic).await?;
+        }
+
+        let data = &data;
+
+        let resp = self.elastic
+            .send_once("index", |es, traceparent| async move {
+                es.index(IndexParts::Index(index))
+                    .body(data)
+                    .header(TRACEPARENT, traceparent)
+                    .send()
+                    .await
+            })
+            .await?;
+
+        if !resp.status_code().is_success() {
+            return Err(ErrorTypes::from_response(resp).await);
+        }
+
+        let json_resp = resp.json::<Value>().await?;
+
+        // Indexes with a schema are strict from the start, without one this is skipped once this service made the index strict, see update_index_mappings
+        if schema.is_none() {
+            let set_dynamic = json!({
+                "dynamic": "strict"
+            });
+
+            self.update_index_mappings(index, set_dynamic).await?;
+        }
 
-        HttpResponse::build(status_code).finish()
+        Ok(json_resp["_id"].as_str().unwrap_or_default().to_string())
+    }
+
+    /// Sends multiple document operations through the _bulk API in batches of BULK_BATCH_SIZE
+    /// 
+    /// Returns the result of every operation, in the same order as it was supplied
+    #[tracing::instrument(skip_all, fields(index = index, operations = actions.len()))]
+    pub async fn bulk_documents(&self, index: &str, mut actions: Vec<BulkAction>, dynamic_mode: Option<String>) -> Result<BulkSummary, ErrorTypes>{
+        self.health.ensure_reachable()?;
+
+        let metadata = self.index_metadata(index).await?;
+
+        let fields = mapping_field_types(&metadata.mappings);
+
+        for action in actions.iter_mut() {
+            match action {
+                BulkAction::Index { data, .. } | BulkAction::Create { data, .. } | BulkAction::Update { data, .. } => normalize_geo_points(data, &fields),
+                BulkAction::Delete { .. } => ()
+            }
+        }
+
+        let schema = write_schema(&metadata.mappings, dynamic_mode.as_deref())?;
+
+        if let Some(schema) = &schema {
+            schema.validate_bulk(&actions)?;
+        }
+
+        // The mode is set on the whole index until the write is done, writes made at the same time share it, see the README
+        if let Some(mode) = dynamic_mode {
+            let set_dynamic = json!({
+                "dynamic": mode
+            });
+        
+            self.update_index_mappings(index, set_dynamic).await?;
+        }
+
+        let total = actions.len();
+        let mut took = 0;
+        let mut items: Vec<BulkItemResult> = Vec::with_capacity(total);
+        let mut actions = actions.into_iter().peekable();
+
+        while actions.peek().is_some() {
+            let batch: Vec<BulkAction> = actions.by_ref().take(BULK_BATCH_SIZE).collect();
+            let (batch_took, results) = self.send_bulk_batch(index, batch).await?;
+            took += batch_took;
+
+            for mut result in results {
+                result.position = items.len();
+                items.push(result);
+            }
+        }
+
+        // Indexes with a schema are strict from the start, without one this is skipped once this service made the index strict, see update_index_mappings
+        if schema.is_none() {
+            let set_dynamic = json!({
+                "dynamic": "strict"
+            });
+
+            self.update_index_mappings(index, set_dynamic).await?;
+        }
+
+        let failed = items.iter().filter(|x| x.error.is_some()).count();
+
+        Ok(BulkSummary {
+            took,
+            total,
+            successful: total - failed,
+            failed,
+            items
+        })
     }
 
     /// Finds document in index
-    pub async fn search_index(&self, index: &str, search_term: Option<String>, search_in: Option<String>, retrieve_field: Option<String>, from: Option<i64>, count: Option<i64>) -> HttpResponse{
-        match server_down_check(&self.elastic).await{
-            Ok(()) => (),
-            Err(x) => return x
-        };
+    #[tracing::instrument(skip_all, fields(index = index))]
+    pub async fn search_index(&self, index: &str, query: SearchQuery) -> Result<SearchResult, ErrorTypes>{
+        let filters = parse_search_filters(query.filters.as_deref(), query.secured_filters.as_deref())?;
+
+        let max_values_per_facet = query.max_values_per_facet()?;
+
+        // Gives the current page with the amount of count
+        let (from_page, count) = query.page(self.default_page_size)?;
+        let from = query.from.unwrap_or(0);
 
-        match index_exists_check(&self.elastic, index).await{
-            Ok(()) => (),
-            Err(x) => return x
+        let geo = GeoSearch::new(query.geo_field, query.around_lat_lng, query.around_radius, query.inside_bounding_box, query.inside_polygon)?;
+
+        // None paginates with from, Some(None) starts a new cursor, Some(Some(x)) continues a cursor
+        let cursor = match query.cursor.as_deref() {
+            None => None,
+            Some(NEW_CURSOR) => Some(None),
+            Some(x) => Some(Some(Cursor::decode(x, index)?))
         };
 
-        let fields_to_search: Option<Vec<String>> = search_in.map(|val| val.split(',').into_iter().map(|x| x.trim().to_string()).collect());
+        self.health.ensure_reachable()?;
 
-        let from = from.unwrap_or(0);
-        let count = count.unwrap_or(20);
+        let metadata = self.index_metadata(index).await?;
 
-        // Gives the current page with the amount of count
-        let from_page = from * count;
+        // Pages past the result window are refused by Elasticsearch, cursors page through every result instead
+        if cursor.is_none() && from_page + count > metadata.max_result_window() {
+            return Err(ErrorTypes::BadDataRequest(format!("page {} goes past the result window of {} results, use a cursor to page further", from, metadata.max_result_window())));
+        }
 
-        let fields_to_return = match retrieve_field {
-            Some(val) => val.split(',').into_iter().map(|x| x.trim().to_string()).collect(),
+        let fields_to_return = match query.return_fields {
+            Some(val) => split_fields(&val),
             None => vec!["*".to_string()],
         };
 
-        // Returns everything
         let mut body = json!({
             "_source": false,
-            "query": {
-                "match_all": {} 
-            },
             "fields": fields_to_return
         });
 
-        if let Some(term) = search_term {
-            body = json!({
-                "_source": false,
-                "query": {
-                        "query_string": {
-                            "query": term,
-                            "type": "cross_fields",
-                            "fields": fields_to_search.unwrap_or(vec!["*".to_string()]),
-                            "minimum_should_match": "75%"
-                        }
-                    },
-                "fields": fields_to_return
-                })   
+        let mut filter_clauses: Vec<Value> = filters.iter().map(|x| x.to_query()).collect();
+        let facets = query.facets.as_deref().map(split_fields).unwrap_or_default();
+        let sort = query.sort.filter(|x| !x.trim().is_empty());
+        let highlight = Highlight::new(query.highlight_fields, query.snippet_fields, query.highlight_pre_tag, query.highlight_post_tag);
+
+        if let Some(highlight) = &highlight {
+            body["highlight"] = highlight.to_highlight();
         }
 
-        let resp = self.elastic
-            .search(SearchParts::Index(&[index]))
-            .from(from_page)
-            .size(count)
-            .body(body)
-            .send()
-            .await
-            .unwrap();
+        if !facets.is_empty() || sort.is_some() || geo.is_some() {
+            let mappings = &metadata.mappings;
+
+            let fields = mapping_field_types(mappings);
+
+            let geo_field = geo.as_ref().map(|x| x.resolve_field(&fields)).transpose()?;
+
+            if let (Some(geo), Some(field)) = (&geo, &geo_field) {
+                filter_clauses.extend(geo.to_filters(field));
+
+                if let Some(distance) = geo.to_distance_field(field) {
+                    body["script_fields"] = json!({
+                        "_distance": distance
+                    });
+                }
+            }
+
+            if !facets.is_empty() {
+                body["aggs"] = build_facet_aggregations(&facets, &fields, max_values_per_facet)?;
+            }
+
+            if let Some(sort) = sort {
+                let around = match (&geo, &geo_field) {
+                    (Some(geo), Some(field)) => geo.around.map(|(lat, lng)| (field.as_str(), lat, lng)),
+                    _ => None
+                };
+
+                body["sort"] = json!(build_sort(&sort, mappings, around)?);
+            }
+        }
+
+        body["query"] = build_search_query(query.search_term, query.search_in, filter_clauses);
+
+        let resp = match &cursor {
+            Some(cursor) => {
+                let (pit_id, search_after) = match cursor {
+                    Some(x) => (x.pit_id.clone(), Some(x.search_after.clone())),
+                    None => (self.open_point_in_time(index).await?, None)
+                };
+
+                // search_after requires a sort, the shard doc is added to break ties between equal documents
+                if !body["sort"].is_array() {
+                    body["sort"] = json!([{"_score": {"order": "desc"}}]);
+                }
+                body["sort"].as_array_mut().unwrap().push(json!({"_shard_doc": "asc"}));
+                body["pit"] = json!({
+                    "id": pit_id,
+                    "keep_alive": CURSOR_KEEP_ALIVE
+                });
+                if let Some(search_after) = search_after {
+                    body["search_after"] = json!(search_after);
+                }
+
+                let body = &body;
+
+                self.elastic
+                    .send("search", |es, traceparent| async move {
+                        es.search(SearchParts::None)
+                            .size(count)
+                            .body(body)
+                            .header(TRACEPARENT, traceparent)
+                            .send()
+                            .await
+                    })
+                    .await?
+            },
+            None => {
+                let body = &body;
+
+                self.elastic
+                    .send("search", |es, traceparent| async move {
+                        es.search(SearchParts::Index(&[index]))
+                            .from(from_page)
+                            .size(count)
+                            .body(body)
+                            .header(TRACEPARENT, traceparent)
+                            .send()
+                            .await
+                    })
+                    .await?
+            }
+        };
 
         let status_code = resp.status_code();
 
-        let json_resp = 
-            resp.json::<Value>()
-            .await
-            .unwrap();
+        if !status_code.is_success() {
+            // A point in time that is no longer found has expired
+            if let Some(Some(cursor)) = &cursor {
+                if status_code == StatusCode::NOT_FOUND {
+                    self.close_point_in_time(&cursor.pit_id).await;
+                    return Err(ErrorTypes::CursorExpired);
+                }
+            }
+
+            return Err(match status_code{
+                StatusCode::NOT_FOUND => self.index_missing(index),
+                _ => ErrorTypes::from_response(resp).await
+            });
+        }
+
+        let mut json_resp = resp.json::<Value>().await?;
+        METRICS.observe_took("search", &json_resp);
+        request_context::record_took(&json_resp);
+
+        let mut hits = match json_resp["hits"]["hits"].take() {
+            Value::Array(x) => x,
+            _ => Vec::new()
+        };
+
+        if let Some(highlight) = &highlight {
+            hits.iter_mut().for_each(|hit| highlight.apply(hit));
+        }
+
+        // Script fields are returned along with the other fields, the distance is moved next to them
+        for hit in hits.iter_mut() {
+            if let Some(distance) = hit["fields"].as_object_mut().and_then(|x| x.remove("_distance")) {
+                hit["_distance"] = distance[0].clone();
+            }
+        }
+
+        let facets = match facets.is_empty() {
+            true => None,
+            false => Some(parse_facets(&facets, &json_resp["aggregations"]))
+        };
+
+        let next_cursor = match cursor {
+            Some(_) => {
+                let pit_id = json_resp["pit_id"].as_str().unwrap_or_default().to_string();
+
+                // A page smaller than count is the last page
+                match hits.last() {
+                    Some(last) if hits.len() as i64 >= count => Some(Some(Cursor {
+                        index: index.to_string(),
+                        pit_id,
+                        search_after: last["sort"].as_array().cloned().unwrap_or_default()
+                    }.encode())),
+                    _ => {
+                        self.close_point_in_time(&pit_id).await;
+                        Some(None)
+                    }
+                }
+            },
+            None => None
+        };
+
+        Ok(SearchResult {
+            took: json_resp["took"].as_i64().unwrap_or_default(),
+            data: hits,
+            total_data: json_resp["hits"]["total"]["value"].as_i64().unwrap_or_default(),
+            match_type: json_resp["hits"]["total"]["relation"].as_str().unwrap_or("eq").to_string(),
+            facets,
+            next_cursor
+        })
+    }
+
+    /// Returns the values of a facet that match the facet query, with their counts under the results of the search
+    #[tracing::instrument(skip_all, fields(index = index, facet = facet))]
+    pub async fn search_facet_values(&self, index: &str, facet: &str, facet_query: Option<String>, query: SearchQuery) -> Result<FacetSearchResult, ErrorTypes>{
+        let filters = parse_search_filters(query.filters.as_deref(), query.secured_filters.as_deref())?;
+
+        let max_values_per_facet = query.max_values_per_facet()?;
+
+        self.health.ensure_reachable()?;
+
+        let metadata = self.index_metadata(index).await?;
+
+        let mappings = &metadata.mappings;
+
+        let fields = mapping_field_types(mappings);
+
+        if !fields.contains_key(facet) {
+            return Err(ErrorTypes::FieldNotFound(facet.to_string()));
+        }
+
+        // Include patterns only work on string values
+        let field = match exact_value_field(&fields, facet) {
+            Some(x) if fields.get(&x).map(|x| x == "keyword").unwrap_or(false) => x,
+            Some(_) => return Err(ErrorTypes::FacetNotSearchable(facet.to_string())),
+            None => return Err(ErrorTypes::FieldNotFacetable(facet.to_string()))
+        };
+
+        let mut terms = json!({
+            "field": field,
+            "size": max_values_per_facet
+        });
+
+        if let Some(facet_query) = facet_query.filter(|x| !x.trim().is_empty()) {
+            terms["include"] = json!(facet_query_pattern(facet_query.trim()));
+        }
+
+        let body = json!({
+            "size": 0,
+            "query": build_search_query(query.search_term, query.search_in, filters.iter().map(|x| x.to_query()).collect()),
+            "aggs": {
+                "facet_values": {
+                    "terms": terms
+                }
+            }
+        });
+
+        let body = &body;
+
+        let resp = self.elastic
+            .send("search", |es, traceparent| async move {
+                es.search(SearchParts::Index(&[index]))
+                    .body(body)
+                    .header(TRACEPARENT, traceparent)
+                    .send()
+                    .await
+            })
+            .await?;
+
+        let status_code = resp.status_code();
 
         if !status_code.is_success() {
-            let error = match status_code{
-                StatusCode::NOT_FOUND => ErrorTypes::IndexNotFound(index.to_string()).to_string(),
-                StatusCode::BAD_REQUEST => ErrorTypes::BadDataRequest.to_string(),
-                _ => ErrorTypes::Unknown.to_string()
-            };
-            return HttpResponse::build(status_code).json(json!({"error": error}));
+            return Err(match status_code{
+                StatusCode::NOT_FOUND => self.index_missing(index),
+                _ => ErrorTypes::from_response(resp).await
+            });
         }
-        
-        HttpResponse::build(status_code).json(json!({
-            "took": json_resp["took"],
-            "data": json_resp["hits"]["hits"],
-            "total_data": json_resp["hits"]["total"]["value"],
-            "match_type": json_resp["hits"]["total"]["relation"]
-        }))
+
+        let json_resp = resp.json::<Value>().await?;
+        METRICS.observe_took("search", &json_resp);
+        request_context::record_took(&json_resp);
+
+        let facet_hits: Vec<FacetHit> = json_resp["aggregations"]["facet_values"]["buckets"]
+            .as_array()
+            .map(|buckets| buckets.iter().map(|bucket| FacetHit {
+                value: bucket["key"].clone(),
+                count: bucket["doc_count"].as_i64().unwrap_or_default()
+            }).collect())
+            .unwrap_or_default();
+
+        Ok(FacetSearchResult {
+            took: json_resp["took"].as_i64().unwrap_or_default(),
+            facet_hits
+        })
     }
 
     /// Returns a single document
-    pub async fn get_document(&self, index: String, doc_id: String, retrieve_fields: Option<String>) -> HttpResponse{
-        match server_down_check(&self.elastic).await{
-            Ok(()) => (),
-            Err(x) => return x
-        };
+    #[tracing::instrument(skip_all, fields(index = index, document_id = doc_id))]
+    pub async fn get_document(&self, index: &str, doc_id: &str, retrieve_fields: Option<String>) -> Result<Value, ErrorTypes>{
+        self.health.ensure_reachable()?;
 
-        match index_exists_check(&self.elastic, &index).await{
-            Ok(()) => (),
-            Err(x) => return x
-        };
+        self.index_metadata(index).await?;
 
         let fields_to_return = match retrieve_fields {
             Some(val) => val,
             None => "*".to_string(),
         };
 
+        let fields_to_return = fields_to_return.as_str();
+
         let resp = self.elastic
-            .get_source(GetSourceParts::IndexId(&index, &doc_id))
-            ._source_includes(&[&fields_to_return])
-            .send()
-            .await
-            .unwrap();
+            .send("get_source", |es, traceparent| async move {
+                es.get_source(GetSourceParts::IndexId(index, doc_id))
+                    ._source_includes(&[fields_to_return])
+                    .header(TRACEPARENT, traceparent)
+                    .send()
+                    .await
+            })
+            .await?;
 
         let status_code = resp.status_code();
         
         if !status_code.is_success() {
-            let error = match status_code{
-                StatusCode::NOT_FOUND => ErrorTypes::DocumentNotFound(doc_id.to_string()).to_string(),
-                _ => ErrorTypes::Unknown.to_string()
-            };
-            return HttpResponse::build(status_code).json(json!({"error": error}));
+            return Err(match status_code{
+                StatusCode::NOT_FOUND => ErrorTypes::DocumentNotFound(doc_id.to_string()),
+                _ => ErrorTypes::from_response(resp).await
+            });
         }
 
-        let json_resp = resp.json::<Value>().await.unwrap();
-
-
-        HttpResponse::build(status_code).json(json_resp)
+        Ok(resp.json::<Value>().await?)
     }
     
     /// Updates existing document on an index
-    pub async fn update_document(&self, index: &str, document_id: &str, data: Value) -> HttpResponse {//(StatusCode, Value){
-        match server_down_check(&self.elastic).await{
-            Ok(()) => (),
-            Err(x) => return x
-        };
+    #[tracing::instrument(skip_all, fields(index = index, document_id = document_id))]
+    pub async fn update_document(&self, index: &str, document_id: &str, mut data: Value) -> Result<(), ErrorTypes>{
+        self.health.ensure_reachable()?;
 
-        match index_exists_check(&self.elastic, index).await{
-            Ok(()) => (),
-            Err(x) => return x
-        };
+        let metadata = self.index_metadata(index).await?;
+
+        let mappings = &metadata.mappings;
+        normalize_geo_points(&mut data["doc"], &mapping_field_types(mappings));
+
+        if let Some(schema) = write_schema(mappings, None)? {
+            schema.validate(&data["doc"]).map_err(ErrorTypes::InvalidDocument)?;
+        }
+
+        let data = &data;
 
         let resp = self.elastic
-            .update(UpdateParts::IndexId(index, document_id))
-            .body(data)
-            .send()
-            .await
-            .unwrap();
+            .send("update", |es, traceparent| async move {
+                es.update(UpdateParts::IndexId(index, document_id))
+                    .body(data)
+                    .header(TRACEPARENT, traceparent)
+                    .send()
+                    .await
+            })
+            .await?;
         
         let status_code = resp.status_code();
         
         if !status_code.is_success() {
-            let error = match status_code{
-                StatusCode::NOT_FOUND => ErrorTypes::DocumentNotFound(document_id.to_string()).to_string(),
-                StatusCode::BAD_REQUEST => ErrorTypes::BadDataRequest.to_string(),
-                _ => ErrorTypes::Unknown.to_string()
-            };
-            return HttpResponse::build(status_code).json(json!({"error": error}));
+            return Err(match status_code{
+                StatusCode::NOT_FOUND => ErrorTypes::DocumentNotFound(document_id.to_string()),
+                _ => ErrorTypes::from_response(resp).await
+            });
         }
 
-        HttpResponse::build(status_code).finish()
+        Ok(())
     }
 
     /// Deletes document on an index
-    pub async fn delete_document(&self, index: &str, document_id: &str) -> HttpResponse{
-        match server_down_check(&self.elastic).await{
-            Ok(()) => (),
-            Err(x) => return x
-        };
+    #[tracing::instrument(skip_all, fields(index = index, document_id = document_id))]
+    pub async fn delete_document(&self, index: &str, document_id: &str) -> Result<(), ErrorTypes>{
+        self.health.ensure_reachable()?;
 
-        match index_exists_check(&self.elastic, index).await{
-            Ok(()) => (),
-            Err(x) => return x
-        };
+        self.index_metadata(index).await?;
 
-        let resp = 
-            self.elastic
-            .delete(DeleteParts::IndexId(index, document_id))
-            .send()
-            .await
-            .unwrap();
+        let resp = self.elastic
+            .send("delete", |es, traceparent| async move {
+                es.delete(DeleteParts::IndexId(index, document_id))
+                    .header(TRACEPARENT, traceparent)
+                    .send()
+                    .await
+            })
+            .await?;
     
         let status_code = resp.status_code();
 
         if !status_code.is_success() {
-            let error = match status_code{
-                StatusCode::NOT_FOUND => ErrorTypes::DocumentNotFound(document_id.to_string()).to_string(),
-                _ => ErrorTypes::Unknown.to_string()
-            };
-            return HttpResponse::build(status_code).json(json!({"error": error}));
+            return Err(match status_code{
+                StatusCode::NOT_FOUND => ErrorTypes::DocumentNotFound(document_id.to_string()),
+                _ => ErrorTypes::from_response(resp).await
+            });
         }
 
-        HttpResponse::build(status_code).finish()
+        Ok(())
     }
-}
\ No newline at end of file
+}
@@ -1,3 +1,6 @@
+use actix_web::{HttpResponse, ResponseError, http::StatusCode};
+use elasticsearch::http::response::Response;
+use serde_json::{json, Value};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -6,12 +9,165 @@ pub enum ErrorTypes {
     IndexNotFound(String),
     #[error("Document ID [{0}] not found")]
     DocumentNotFound(String),
-    #[error("Bad data request")]
-    BadDataRequest,
+    #[error("Bad data request, {0}")]
+    BadDataRequest(String),
     #[error("Failed to create new index, index [{0}] already exists")]
     IndexExists(String),
+    #[error("Invalid filter expression, {0}")]
+    InvalidFilter(String),
+    #[error("Field [{0}] not found")]
+    FieldNotFound(String),
+    #[error("Field [{0}] can not be used as a facet, text fields require a keyword subfield")]
+    FieldNotFacetable(String),
+    #[error("Values of facet [{0}] can not be searched, only string facets are searchable")]
+    FacetNotSearchable(String),
+    #[error("Invalid sort, {0}")]
+    InvalidSort(String),
+    #[error("Invalid geo search, {0}")]
+    InvalidGeo(String),
+    #[error("Invalid cursor")]
+    InvalidCursor,
+    #[error("Cursor expired, start a new search")]
+    CursorExpired,
+    #[error("Unknown export format [{0}], expected ndjson, csv or json")]
+    InvalidExportFormat(String),
+    #[error("Invalid import, {0}")]
+    InvalidImport(String),
+    #[error("Invalid schema, {0}")]
+    InvalidSchema(String),
+    #[error("Document does not match the schema of the index, {0}")]
+    InvalidDocument(String),
+    #[error("Invalid bulk operation at position {0}, action must be index, create, update or delete, create, update and delete require document_id, index, create and update require data")]
+    InvalidBulkOperation(usize),
+    #[error("Missing API key, send it in the X-API-Key header")]
+    MissingApiKey,
+    #[error("Invalid API key")]
+    InvalidApiKey,
+    #[error("API key expired")]
+    ApiKeyExpired,
+    #[error("Forbidden, {0}")]
+    Forbidden(String),
+    #[error("API key [{0}] not found")]
+    ApiKeyNotFound(String),
+    #[error("Invalid API key request, {0}")]
+    InvalidApiKeyRequest(String),
+    /// Seconds until the request can be made again
+    #[error("Too many requests, retry in {0}s")]
+    RateLimited(u64),
+    #[error("Monthly quota of {quota} requests used up")]
+    QuotaExceeded{ quota: u64, retry_after: u64 },
+    /// Elasticsearch answered with an error status, status is the one returned by Elasticsearch
+    #[error("Elasticsearch error, {reason}")]
+    Elasticsearch{ status: u16, reason: String },
     #[error("Server currently unavailable")]
     ServerDown,
     #[error("Unknown error occured")]
     Unknown
-}
\ No newline at end of file
+}
+
+impl ErrorTypes {
+    /// Builds the error of a failed Elasticsearch response from its status and the reason in its body
+    pub async fn from_response(resp: Response) -> Self {
+        let status = resp.status_code();
+        let body = resp.json::<Value>().await.unwrap_or_default();
+
+        let reason = match &body["error"] {
+            Value::String(x) => x.to_string(),
+            x => x["reason"].as_str().unwrap_or("Unknown error occured").to_string()
+        };
+
+        match status.as_u16() {
+            400 => ErrorTypes::BadDataRequest(reason),
+            status => ErrorTypes::Elasticsearch{ status, reason }
+        }
+    }
+
+    /// Name of the variant, the error label of the metrics
+    pub fn name(&self) -> &'static str {
+        match self {
+            ErrorTypes::IndexNotFound(_) => "IndexNotFound",
+            ErrorTypes::DocumentNotFound(_) => "DocumentNotFound",
+            ErrorTypes::BadDataRequest(_) => "BadDataRequest",
+            ErrorTypes::IndexExists(_) => "IndexExists",
+            ErrorTypes::InvalidFilter(_) => "InvalidFilter",
+            ErrorTypes::FieldNotFound(_) => "FieldNotFound",
+            ErrorTypes::FieldNotFacetable(_) => "FieldNotFacetable",
+            ErrorTypes::FacetNotSearchable(_) => "FacetNotSearchable",
+            ErrorTypes::InvalidSort(_) => "InvalidSort",
+            ErrorTypes::InvalidGeo(_) => "InvalidGeo",
+            ErrorTypes::InvalidCursor => "InvalidCursor",
+            ErrorTypes::CursorExpired => "CursorExpired",
+            ErrorTypes::InvalidExportFormat(_) => "InvalidExportFormat",
+            ErrorTypes::InvalidImport(_) => "InvalidImport",
+            ErrorTypes::InvalidSchema(_) => "InvalidSchema",
+            ErrorTypes::InvalidDocument(_) => "InvalidDocument",
+            ErrorTypes::InvalidBulkOperation(_) => "InvalidBulkOperation",
+            ErrorTypes::MissingApiKey => "MissingApiKey",
+            ErrorTypes::InvalidApiKey => "InvalidApiKey",
+            ErrorTypes::ApiKeyExpired => "ApiKeyExpired",
+            ErrorTypes::Forbidden(_) => "Forbidden",
+            ErrorTypes::ApiKeyNotFound(_) => "ApiKeyNotFound",
+            ErrorTypes::InvalidApiKeyRequest(_) => "InvalidApiKeyRequest",
+            ErrorTypes::RateLimited(_) => "RateLimited",
+            ErrorTypes::QuotaExceeded{ .. } => "QuotaExceeded",
+            ErrorTypes::Elasticsearch{ .. } => "Elasticsearch",
+            ErrorTypes::ServerDown => "ServerDown",
+            ErrorTypes::Unknown => "Unknown"
+        }
+    }
+}
+
+/// Errors while sending a request or reading its response, an unreadable body is not the server being down
+impl From<elasticsearch::Error> for ErrorTypes {
+    fn from(error: elasticsearch::Error) -> Self {
+        match error.is_json() {
+            true => ErrorTypes::Unknown,
+            false => ErrorTypes::ServerDown
+        }
+    }
+}
+
+impl ResponseError for ErrorTypes {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ErrorTypes::IndexNotFound(_) | ErrorTypes::DocumentNotFound(_) | ErrorTypes::ApiKeyNotFound(_) => StatusCode::NOT_FOUND,
+            ErrorTypes::MissingApiKey | ErrorTypes::InvalidApiKey | ErrorTypes::ApiKeyExpired => StatusCode::UNAUTHORIZED,
+            ErrorTypes::RateLimited(_) | ErrorTypes::QuotaExceeded{ .. } => StatusCode::TOO_MANY_REQUESTS,
+            ErrorTypes::Forbidden(_) => StatusCode::FORBIDDEN,
+            ErrorTypes::IndexExists(_) => StatusCode::CONFLICT,
+            ErrorTypes::CursorExpired => StatusCode::GONE,
+            ErrorTypes::BadDataRequest(_)
+            | ErrorTypes::InvalidFilter(_)
+            | ErrorTypes::FieldNotFound(_)
+            | ErrorTypes::FieldNotFacetable(_)
+            | ErrorTypes::FacetNotSearchable(_)
+            | ErrorTypes::InvalidSort(_)
+            | ErrorTypes::InvalidGeo(_)
+            | ErrorTypes::InvalidCursor
+            | ErrorTypes::InvalidExportFormat(_)
+            | ErrorTypes::InvalidImport(_)
+            | ErrorTypes::InvalidSchema(_)
+            | ErrorTypes::InvalidDocument(_)
+            | ErrorTypes::InvalidBulkOperation(_)
+            | ErrorTypes::InvalidApiKeyRequest(_) => StatusCode::BAD_REQUEST,
+            // Other errors come from the cluster or from the credentials of the server, they are not the caller's to fix
+            ErrorTypes::Elasticsearch{ status, .. } => match *status {
+                404 | 409 | 413 => StatusCode::from_u16(*status).unwrap_or(StatusCode::BAD_GATEWAY),
+                429 => StatusCode::SERVICE_UNAVAILABLE,
+                _ => StatusCode::BAD_GATEWAY
+            },
+            ErrorTypes::ServerDown => StatusCode::SERVICE_UNAVAILABLE,
+            ErrorTypes::Unknown => StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut resp = HttpResponse::build(self.status_code());
+
+        if let ErrorTypes::RateLimited(retry_after) | ErrorTypes::QuotaExceeded{ retry_after, .. } = self {
+            resp.insert_header(("Retry-After", retry_after.to_string()));
+        }
+
+        resp.json(json!({"error": self.to_string()}))
+    }
+}
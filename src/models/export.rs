@@ -0,0 +1,281 @@
+use std::{str::FromStr, sync::Arc};
+
+use actix_web::web::Bytes;
+use elasticsearch::SearchParts;
+use futures_util::{stream, Stream};
+use serde_json::{json, Value};
+
+use super::{EClient, ErrorTypes, cursor::CURSOR_KEEP_ALIVE, helpers::mapping_document_fields, metrics::METRICS, pool::TRACEPARENT, request_context};
+
+/// Amount of documents fetched from Elasticsearch per page of an export
+pub const EXPORT_PAGE_SIZE: i64 = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Ndjson,
+    Csv,
+    Json
+}
+
+impl FromStr for ExportFormat {
+    type Err = ErrorTypes;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "ndjson" => Ok(ExportFormat::Ndjson),
+            "csv" => Ok(ExportFormat::Csv),
+            "json" => Ok(ExportFormat::Json),
+            _ => Err(ErrorTypes::InvalidExportFormat(s.to_string()))
+        }
+    }
+}
+
+impl ExportFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Ndjson => "application/x-ndjson",
+            ExportFormat::Csv => "text/csv",
+            ExportFormat::Json => "application/json",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Ndjson => "ndjson",
+            ExportFormat::Csv => "csv",
+            ExportFormat::Json => "json",
+        }
+    }
+}
+
+/// Quotes a CSV cell if it contains a separator, quote or line break
+fn csv_cell(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        return format!("\"{}\"", value.replace('"', "\"\""));
+    }
+    value.to_string()
+}
+
+/// Returns the value of a dotted field of a document as a CSV cell, objects and arrays are written as JSON
+fn csv_value(document: &Value, field: &str) -> String {
+    let value = field.split('.').try_fold(document, |x, name| x.get(name));
+
+    match value {
+        None | Some(Value::Null) => String::new(),
+        Some(Value::String(x)) => csv_cell(x),
+        Some(x) => csv_cell(&x.to_string())
+    }
+}
+
+/// Writes documents in an export format, one chunk at a time
+pub struct ExportWriter {
+    format: ExportFormat,
+    /// CSV columns, _id first
+    columns: Vec<String>,
+    /// Amount of documents written so far
+    written: usize,
+    /// Whether the CSV header or the opening bracket is written
+    started: bool
+}
+
+impl ExportWriter {
+    /// columns are the fields written as CSV columns, _id is always the first column
+    pub fn new(format: ExportFormat, columns: Vec<String>) -> Self {
+        let mut columns_with_id = vec!["_id".to_string()];
+        columns_with_id.extend(columns);
+
+        Self {
+            format,
+            columns: columns_with_id,
+            written: 0,
+            started: false
+        }
+    }
+
+    /// Writes a page of documents, the first page starts with the CSV header or the opening bracket
+    pub fn write_page(&mut self, documents: &[Value]) -> String {
+        let mut chunk = String::new();
+
+        if !self.started {
+            self.started = true;
+            match self.format {
+                ExportFormat::Csv => {
+                    chunk.push_str(&self.columns.iter().map(|x| csv_cell(x)).collect::<Vec<String>>().join(","));
+                    chunk.push('\n');
+                },
+                ExportFormat::Json => chunk.push('['),
+                ExportFormat::Ndjson => ()
+            }
+        }
+
+        for document in documents {
+            match self.format {
+                ExportFormat::Ndjson => {
+                    chunk.push_str(&document.to_string());
+                    chunk.push('\n');
+                },
+                ExportFormat::Json => {
+                    if self.written > 0 {
+                        chunk.push(',');
+                    }
+                    chunk.push_str(&document.to_string());
+                },
+                ExportFormat::Csv => {
+                    chunk.push_str(&self.columns.iter().map(|x| csv_value(document, x)).collect::<Vec<String>>().join(","));
+                    chunk.push('\n');
+                }
+            }
+
+            self.written += 1;
+        }
+
+        chunk
+    }
+
+    /// Ends the export, returns what is left to write
+    pub fn finish(&mut self) -> String {
+        let mut chunk = match self.started {
+            true => String::new(),
+            false => self.write_page(&[])
+        };
+
+        if self.format == ExportFormat::Json {
+            chunk.push(']');
+        }
+
+        chunk
+    }
+}
+
+/// Parses the comma separated fields of an export, None if no field is supplied
+pub fn export_fields(fields: Option<String>) -> Option<Vec<String>> {
+    fields
+        .map(|x| x.split(',').map(|x| x.trim().to_string()).filter(|x| !x.is_empty()).collect())
+        .filter(|x: &Vec<String>| !x.is_empty())
+}
+
+/// State of an export stream between pages
+struct ExportState {
+    client: Arc<EClient>,
+    writer: ExportWriter,
+    source: Vec<String>,
+    pit_id: String,
+    search_after: Option<Value>,
+    done: bool
+}
+
+impl ExportState {
+    /// Fetches the next page and writes it in the export format, returns None once every document has been written
+    ///
+    /// The status code is already sent when a page fails, the error ends the stream so that the response is cut short instead of looking complete
+    async fn next_chunk(&mut self) -> Option<Result<Bytes, ErrorTypes>> {
+        if self.done {
+            return None;
+        }
+
+        let chunk = self.fetch_page().await;
+
+        if chunk.is_err() {
+            self.done = true;
+            self.client.close_point_in_time(&self.pit_id).await;
+        }
+
+        Some(chunk)
+    }
+
+    async fn fetch_page(&mut self) -> Result<Bytes, ErrorTypes> {
+        let mut body = json!({
+            "_source": self.source,
+            "sort": [{"_shard_doc": "asc"}],
+            "pit": {
+                "id": self.pit_id,
+                "keep_alive": CURSOR_KEEP_ALIVE
+            }
+        });
+
+        if let Some(search_after) = &self.search_after {
+            body["search_after"] = search_after.clone();
+        }
+
+        let body = &body;
+
+        let resp = self.client.elastic
+            .send("search", |es, traceparent| async move {
+                es.search(SearchParts::None)
+                    .size(EXPORT_PAGE_SIZE)
+                    .body(body)
+                    .header(TRACEPARENT, traceparent)
+                    .send()
+                    .await
+            })
+            .await?;
+
+        if !resp.status_code().is_success() {
+            return Err(ErrorTypes::from_response(resp).await);
+        }
+
+        let json_resp = resp.json::<Value>().await?;
+
+        METRICS.observe_took("search", &json_resp);
+        request_context::record_took(&json_resp);
+
+        let hits = json_resp["hits"]["hits"].as_array().cloned().unwrap_or_default();
+
+        if let Some(pit_id) = json_resp["pit_id"].as_str() {
+            self.pit_id = pit_id.to_string();
+        }
+
+        let documents: Vec<Value> = hits.iter().map(|hit| {
+            let mut document = hit["_source"].clone();
+            document["_id"] = hit["_id"].clone();
+            document
+        }).collect();
+
+        let mut chunk = self.writer.write_page(&documents);
+
+        if (hits.len() as i64) < EXPORT_PAGE_SIZE {
+            chunk.push_str(&self.writer.finish());
+            self.done = true;
+            self.client.close_point_in_time(&self.pit_id).await;
+        } else {
+            self.search_after = hits.last().map(|x| x["sort"].clone());
+        }
+
+        Ok(Bytes::from(chunk))
+    }
+}
+
+impl EClient {
+    /// Returns a stream of every document of an index in the export format, fetched page by page through a point in time
+    ///
+    /// fields limits the exported fields, comma separated, CSV columns default to every field of the mapping
+    #[tracing::instrument(skip_all, fields(index = index))]
+    pub async fn export_index(self: Arc<Self>, index: &str, format: ExportFormat, fields: Option<String>) -> Result<impl Stream<Item = Result<Bytes, ErrorTypes>>, ErrorTypes>{
+        self.health.ensure_reachable()?;
+
+        let metadata = self.index_metadata(index).await?;
+
+        let fields = export_fields(fields);
+
+        let columns = match (&fields, format) {
+            (Some(fields), _) => fields.clone(),
+            (None, ExportFormat::Csv) => mapping_document_fields(&metadata.mappings),
+            (None, _) => Vec::new()
+        };
+
+        let pit_id = self.open_point_in_time(index).await?;
+
+        let state = ExportState {
+            client: self.clone(),
+            writer: ExportWriter::new(format, columns),
+            source: fields.unwrap_or_else(|| vec!["*".to_string()]),
+            pit_id,
+            search_after: None,
+            done: false
+        };
+
+        Ok(stream::unfold(state, |mut state| async move {
+            state.next_chunk().await.map(|chunk| (chunk, state))
+        }))
+    }
+}
@@ -0,0 +1,487 @@
+use std::fmt;
+
+use serde_json::{json, Value};
+
+use super::ErrorTypes;
+
+/*
+Filter expression syntax:
+    country:Indonesia
+    country = "United States"
+    country != Indonesia
+    links_count >= 10
+    links_count:10 TO 20
+    city IN [Jakarta, "Kuala Lumpur", Singapore]
+    NOT country:Indonesia
+    (country:Indonesia OR country:Malaysia) AND links_count > 5
+
+Keywords (AND, OR, NOT, TO, IN) are uppercase, NOT binds tighter than AND, AND binds tighter than OR
+Parentheses and NOT can be nested up to 64 levels deep
+*/
+
+/// A parsed filter expression
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterExpr {
+    And(Vec<FilterExpr>),
+    Or(Vec<FilterExpr>),
+    Not(Box<FilterExpr>),
+    Condition(Condition)
+}
+
+/// A single comparison against a field
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    Eq(String, FilterValue),
+    Ne(String, FilterValue),
+    In(String, Vec<FilterValue>),
+    Range{ field: String, op: RangeOp, value: FilterValue },
+    Between{ field: String, from: FilterValue, to: FilterValue }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RangeOp {
+    Gt,
+    Gte,
+    Lt,
+    Lte
+}
+
+impl RangeOp {
+    fn key(&self) -> &'static str {
+        match self {
+            RangeOp::Gt => "gt",
+            RangeOp::Gte => "gte",
+            RangeOp::Lt => "lt",
+            RangeOp::Lte => "lte",
+        }
+    }
+}
+
+/// Value on the right hand side of a condition
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterValue {
+    String(String),
+    /// A finite number as written, so that ids such as 00123 and integers above 2^53 are kept exactly
+    Number(String),
+    Bool(bool)
+}
+
+impl FilterValue {
+    fn to_json(&self) -> Value {
+        match self {
+            FilterValue::String(x) => json!(x),
+            FilterValue::Number(x) => match (x.parse::<i64>(), x.parse::<u64>()) {
+                (Ok(x), _) => json!(x),
+                (_, Ok(x)) => json!(x),
+                _ => json!(x.parse::<f64>().unwrap_or_default())
+            },
+            FilterValue::Bool(x) => json!(x),
+        }
+    }
+
+    /// The value as the keyword subfield of a dynamically mapped string holds it, None for booleans
+    fn to_keyword(&self) -> Option<Value> {
+        match self {
+            FilterValue::String(x) | FilterValue::Number(x) => Some(json!(x)),
+            FilterValue::Bool(_) => None
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    Comma,
+    Colon,
+    Eq,
+    Ne,
+    Lt,
+    Lte,
+    Gt,
+    Gte,
+    And,
+    Or,
+    Not,
+    To,
+    In,
+    /// Quoted string
+    Str(String),
+    /// Unquoted word, either a field name, number, boolean or string
+    Word(String)
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::LParen => write!(f, "("),
+            Token::RParen => write!(f, ")"),
+            Token::LBracket => write!(f, "["),
+            Token::RBracket => write!(f, "]"),
+            Token::Comma => write!(f, ","),
+            Token::Colon => write!(f, ":"),
+            Token::Eq => write!(f, "="),
+            Token::Ne => write!(f, "!="),
+            Token::Lt => write!(f, "<"),
+            Token::Lte => write!(f, "<="),
+            Token::Gt => write!(f, ">"),
+            Token::Gte => write!(f, ">="),
+            Token::And => write!(f, "AND"),
+            Token::Or => write!(f, "OR"),
+            Token::Not => write!(f, "NOT"),
+            Token::To => write!(f, "TO"),
+            Token::In => write!(f, "IN"),
+            Token::Str(x) => write!(f, "\"{}\"", x),
+            Token::Word(x) => write!(f, "{}", x),
+        }
+    }
+}
+
+fn filter_error(token: Option<&(Token, usize)>, expected: &str) -> ErrorTypes {
+    match token {
+        Some((token, position)) => ErrorTypes::InvalidFilter(format!("unexpected token [{}] at position {}, expected {}", token, position, expected)),
+        None => ErrorTypes::InvalidFilter(format!("unexpected end of expression, expected {}", expected))
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '.' | '-' | '@' | '+' | '*')
+}
+
+/// Splits the filter expression into tokens along with their position
+fn tokenize(input: &str) -> Result<Vec<(Token, usize)>, ErrorTypes> {
+    let mut tokens = Vec::new();
+    let mut chars = input.char_indices().peekable();
+
+    while let Some(&(position, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+
+        let token = match c {
+            '(' => { chars.next(); Token::LParen },
+            ')' => { chars.next(); Token::RParen },
+            '[' => { chars.next(); Token::LBracket },
+            ']' => { chars.next(); Token::RBracket },
+            ',' => { chars.next(); Token::Comma },
+            ':' => { chars.next(); Token::Colon },
+            '=' => { chars.next(); Token::Eq },
+            '!' => {
+                chars.next();
+                match chars.next() {
+                    Some((_, '=')) => Token::Ne,
+                    _ => return Err(ErrorTypes::InvalidFilter(format!("unexpected token [!] at position {}, expected !=", position)))
+                }
+            },
+            '<' | '>' => {
+                chars.next();
+                let equals = matches!(chars.peek(), Some((_, '=')));
+                if equals {
+                    chars.next();
+                }
+                match (c, equals) {
+                    ('<', false) => Token::Lt,
+                    ('<', true) => Token::Lte,
+                    ('>', false) => Token::Gt,
+                    _ => Token::Gte,
+                }
+            },
+            '"' | '\'' => {
+                chars.next();
+                let mut value = String::new();
+                let mut closed = false;
+                while let Some((_, x)) = chars.next() {
+                    match x {
+                        '\\' => {
+                            if let Some((_, escaped)) = chars.next() {
+                                value.push(escaped);
+                            }
+                        },
+                        x if x == c => {
+                            closed = true;
+                            break;
+                        },
+                        x => value.push(x)
+                    }
+                }
+                if !closed {
+                    return Err(ErrorTypes::InvalidFilter(format!("unterminated string starting at position {}", position)));
+                }
+                Token::Str(value)
+            },
+            c if is_word_char(c) => {
+                let mut word = String::new();
+                while let Some(&(_, x)) = chars.peek() {
+                    if !is_word_char(x) {
+                        break;
+                    }
+                    word.push(x);
+                    chars.next();
+                }
+                match word.as_str() {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    "NOT" => Token::Not,
+                    "TO" => Token::To,
+                    "IN" => Token::In,
+                    _ => Token::Word(word)
+                }
+            },
+            c => return Err(ErrorTypes::InvalidFilter(format!("unexpected character [{}] at position {}", c, position)))
+        };
+
+        tokens.push((token, position));
+    }
+
+    Ok(tokens)
+}
+
+/// Most parentheses and NOT nested in one another, each level is a recursive call of the parser
+const MAX_FILTER_DEPTH: usize = 64;
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    position: usize,
+    /// Parentheses and NOT the parser is currently inside of
+    depth: usize
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position).map(|(token, _)| token)
+    }
+
+    fn next(&mut self) -> Option<(Token, usize)> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), ErrorTypes> {
+        match self.next() {
+            Some((token, _)) if token == expected => Ok(()),
+            token => Err(filter_error(token.as_ref(), &format!("[{}]", expected)))
+        }
+    }
+
+    /// Parses a nested expression, fails past MAX_FILTER_DEPTH rather than overflowing the stack
+    fn nested(&mut self, parse: fn(&mut Self) -> Result<FilterExpr, ErrorTypes>) -> Result<FilterExpr, ErrorTypes> {
+        if self.depth >= MAX_FILTER_DEPTH {
+            return Err(ErrorTypes::InvalidFilter("filter nested too deeply".to_string()));
+        }
+
+        self.depth += 1;
+        let expression = parse(self);
+        self.depth -= 1;
+
+        expression
+    }
+
+    fn parse_or(&mut self) -> Result<FilterExpr, ErrorTypes> {
+        let mut expressions = vec![self.parse_and()?];
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            expressions.push(self.parse_and()?);
+        }
+
+        Ok(match expressions.len() {
+            1 => expressions.remove(0),
+            _ => FilterExpr::Or(expressions)
+        })
+    }
+
+    fn parse_and(&mut self) -> Result<FilterExpr, ErrorTypes> {
+        let mut expressions = vec![self.parse_not()?];
+        while self.peek() == Some(&Token::And) {
+            self.next();
+            expressions.push(self.parse_not()?);
+        }
+
+        Ok(match expressions.len() {
+            1 => expressions.remove(0),
+            _ => FilterExpr::And(expressions)
+        })
+    }
+
+    fn parse_not(&mut self) -> Result<FilterExpr, ErrorTypes> {
+        if self.peek() == Some(&Token::Not) {
+            self.next();
+            return Ok(FilterExpr::Not(Box::new(self.nested(Self::parse_not)?)));
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<FilterExpr, ErrorTypes> {
+        match self.next() {
+            Some((Token::LParen, _)) => {
+                let expression = self.nested(Self::parse_or)?;
+                self.expect(Token::RParen)?;
+                Ok(expression)
+            },
+            Some((Token::Word(field), _)) | Some((Token::Str(field), _)) => Ok(FilterExpr::Condition(self.parse_condition(field)?)),
+            token => Err(filter_error(token.as_ref(), "a field name, NOT or ("))
+        }
+    }
+
+    fn parse_condition(&mut self, field: String) -> Result<Condition, ErrorTypes> {
+        match self.next() {
+            Some((Token::Colon, _)) => {
+                let value = self.parse_value()?;
+                if self.peek() == Some(&Token::To) {
+                    self.next();
+                    let to = self.parse_value()?;
+                    return Ok(Condition::Between{ field, from: value, to });
+                }
+                Ok(Condition::Eq(field, value))
+            },
+            Some((Token::Eq, _)) => Ok(Condition::Eq(field, self.parse_value()?)),
+            Some((Token::Ne, _)) => Ok(Condition::Ne(field, self.parse_value()?)),
+            Some((Token::Lt, _)) => Ok(Condition::Range{ field, op: RangeOp::Lt, value: self.parse_value()? }),
+            Some((Token::Lte, _)) => Ok(Condition::Range{ field, op: RangeOp::Lte, value: self.parse_value()? }),
+            Some((Token::Gt, _)) => Ok(Condition::Range{ field, op: RangeOp::Gt, value: self.parse_value()? }),
+            Some((Token::Gte, _)) => Ok(Condition::Range{ field, op: RangeOp::Gte, value: self.parse_value()? }),
+            Some((Token::In, _)) => Ok(Condition::In(field, self.parse_list()?)),
+            token => Err(filter_error(token.as_ref(), "an operator (:, =, !=, <, <=, >, >=, IN)"))
+        }
+    }
+
+    fn parse_list(&mut self) -> Result<Vec<FilterValue>, ErrorTypes> {
+        let close = match self.next() {
+            Some((Token::LBracket, _)) => Token::RBracket,
+            Some((Token::LParen, _)) => Token::RParen,
+            token => return Err(filter_error(token.as_ref(), "[ or ("))
+        };
+
+        let mut values = vec![self.parse_value()?];
+        loop {
+            match self.next() {
+                Some((Token::Comma, _)) => values.push(self.parse_value()?),
+                Some((token, _)) if token == close => return Ok(values),
+                token => return Err(filter_error(token.as_ref(), &format!(", or {}", close)))
+            }
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<FilterValue, ErrorTypes> {
+        match self.next() {
+            Some((Token::Str(value), _)) => Ok(FilterValue::String(value)),
+            Some((Token::Word(value), _)) => {
+                // Words such as nan or infinity are parsed as floats, only finite numbers are numbers
+                if value.parse::<f64>().is_ok_and(|x| x.is_finite()) {
+                    return Ok(FilterValue::Number(value));
+                }
+                Ok(match value.as_str() {
+                    "true" => FilterValue::Bool(true),
+                    "false" => FilterValue::Bool(false),
+                    _ => FilterValue::String(value)
+                })
+            },
+            token => Err(filter_error(token.as_ref(), "a value"))
+        }
+    }
+}
+
+/// Parses a filter expression into a FilterExpr
+pub fn parse_filter(input: &str) -> Result<FilterExpr, ErrorTypes> {
+    let mut parser = Parser {
+        tokens: tokenize(input)?,
+        position: 0,
+        depth: 0
+    };
+
+    let expression = parser.parse_or()?;
+
+    match parser.next() {
+        None => Ok(expression),
+        token => Err(filter_error(token.as_ref(), "AND, OR or end of expression"))
+    }
+}
+
+/// Parses the filters of a search along with the filters of its secured API key, documents have to match both
+///
+/// Combined after parsing so that the filters of the search can not escape the secured ones
+pub fn parse_search_filters(filters: Option<&str>, secured_filters: Option<&str>) -> Result<Option<FilterExpr>, ErrorTypes> {
+    let filters = filters.map(parse_filter).transpose()?;
+    let secured_filters = secured_filters.map(parse_filter).transpose()?;
+
+    Ok(match (secured_filters, filters) {
+        (Some(secured), Some(filters)) => Some(FilterExpr::And(vec![secured, filters])),
+        (secured, filters) => secured.or(filters)
+    })
+}
+
+/// Matches the exact value of a field
+///
+/// Strings and numbers are also matched as written against the keyword subfield, as dynamically mapped strings are analyzed text
+fn exact_clause(query: &str, field: &str, value: Value, keyword: Option<Value>) -> Value {
+    match keyword {
+        Some(keyword) => json!({
+            "bool": {
+                "should": [
+                    { query: { field: value } },
+                    { query: { format!("{}.keyword", field): keyword } }
+                ],
+                "minimum_should_match": 1
+            }
+        }),
+        None => json!({ query: { field: value } })
+    }
+}
+
+impl FilterExpr {
+    /// Compiles the expression into an Elasticsearch query clause, to be used inside a bool filter
+    pub fn to_query(&self) -> Value {
+        match self {
+            FilterExpr::And(expressions) => json!({
+                "bool": {
+                    "filter": expressions.iter().map(|x| x.to_query()).collect::<Vec<Value>>()
+                }
+            }),
+            FilterExpr::Or(expressions) => json!({
+                "bool": {
+                    "should": expressions.iter().map(|x| x.to_query()).collect::<Vec<Value>>(),
+                    "minimum_should_match": 1
+                }
+            }),
+            FilterExpr::Not(expression) => json!({
+                "bool": {
+                    "must_not": [expression.to_query()]
+                }
+            }),
+            FilterExpr::Condition(condition) => condition.to_query()
+        }
+    }
+}
+
+impl Condition {
+    fn to_query(&self) -> Value {
+        match self {
+            Condition::Eq(field, value) => exact_clause("term", field, value.to_json(), value.to_keyword()),
+            Condition::Ne(field, value) => json!({
+                "bool": {
+                    "must_not": [exact_clause("term", field, value.to_json(), value.to_keyword())]
+                }
+            }),
+            Condition::In(field, values) => {
+                let keywords: Vec<Value> = values.iter().filter_map(FilterValue::to_keyword).collect();
+                exact_clause("terms", field, values.iter().map(|x| x.to_json()).collect(), Some(json!(keywords)).filter(|_| !keywords.is_empty()))
+            },
+            Condition::Range{ field, op, value } => json!({
+                "range": {
+                    field: { op.key(): value.to_json() }
+                }
+            }),
+            Condition::Between{ field, from, to } => json!({
+                "range": {
+                    field: {
+                        "gte": from.to_json(),
+                        "lte": to.to_json()
+                    }
+                }
+            })
+        }
+    }
+}
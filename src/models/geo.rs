@@ -0,0 +1,207 @@
+use std::collections::HashMap;
+
+use serde_json::{json, Value};
+
+use super::ErrorTypes;
+
+/// Field used for geo search when the index has more than one geo_point field
+pub const DEFAULT_GEO_FIELD: &str = "_geoloc";
+
+/*
+Geo search parameters, coordinates are comma separated latitude and longitude pairs:
+    around_lat_lng: "-6.12,106.65"
+    around_radius: 50000 (meters)
+    inside_bounding_box: "lat1,lng1,lat2,lng2" (top left then bottom right corner, a left longitude above the right one crosses the antimeridian)
+    inside_polygon: "lat1,lng1,lat2,lng2,lat3,lng3,..." (at least three points)
+*/
+
+/// Geo search settings of a search
+#[derive(Debug, Default)]
+pub struct GeoSearch {
+    pub field: Option<String>,
+    pub around: Option<(f64, f64)>,
+    pub around_radius: Option<i64>,
+    /// Top left and bottom right corners
+    pub bounding_box: Option<[(f64, f64); 2]>,
+    pub polygon: Option<Vec<(f64, f64)>>
+}
+
+/// Parses comma separated coordinates into latitude and longitude pairs
+fn parse_points(parameter: &str, value: &str) -> Result<Vec<(f64, f64)>, ErrorTypes> {
+    let numbers = value
+        .split(',')
+        .map(|x| x.trim().parse::<f64>())
+        .collect::<Result<Vec<f64>, _>>()
+        .map_err(|_| ErrorTypes::InvalidGeo(format!("{} must be comma separated numbers", parameter)))?;
+
+    if numbers.len() % 2 != 0 {
+        return Err(ErrorTypes::InvalidGeo(format!("{} must be pairs of latitude and longitude", parameter)));
+    }
+
+    let points: Vec<(f64, f64)> = numbers.chunks(2).map(|x| (x[0], x[1])).collect();
+
+    match points.iter().find(|(lat, lng)| !(-90.0..=90.0).contains(lat) || !(-180.0..=180.0).contains(lng)) {
+        Some((lat, lng)) => Err(ErrorTypes::InvalidGeo(format!("{} has an out of range coordinate [{}, {}]", parameter, lat, lng))),
+        None => Ok(points)
+    }
+}
+
+impl GeoSearch {
+    /// Parses the geo search parameters, returns None if none is supplied
+    pub fn new(field: Option<String>, around_lat_lng: Option<String>, around_radius: Option<i64>, inside_bounding_box: Option<String>, inside_polygon: Option<String>) -> Result<Option<Self>, ErrorTypes> {
+        let around = match around_lat_lng {
+            Some(x) => match parse_points("around_lat_lng", &x)?.as_slice() {
+                [point] => Some(*point),
+                _ => return Err(ErrorTypes::InvalidGeo("around_lat_lng must be a single latitude and longitude".to_string()))
+            },
+            None => None
+        };
+
+        if around_radius.is_some() && around.is_none() {
+            return Err(ErrorTypes::InvalidGeo("around_radius requires around_lat_lng".to_string()));
+        }
+
+        if around_radius.map(|x| x <= 0).unwrap_or(false) {
+            return Err(ErrorTypes::InvalidGeo("around_radius must be more than 0".to_string()));
+        }
+
+        let bounding_box = match inside_bounding_box {
+            Some(x) => match parse_points("inside_bounding_box", &x)?.as_slice() {
+                [top_left, bottom_right] if top_left.0 < bottom_right.0 => return Err(ErrorTypes::InvalidGeo(format!("inside_bounding_box top left corner [{}, {}] is below its bottom right corner [{}, {}]", top_left.0, top_left.1, bottom_right.0, bottom_right.1))),
+                [top_left, bottom_right] => Some([*top_left, *bottom_right]),
+                _ => return Err(ErrorTypes::InvalidGeo("inside_bounding_box must be two corners".to_string()))
+            },
+            None => None
+        };
+
+        let polygon = match inside_polygon {
+            Some(x) => {
+                let points = parse_points("inside_polygon", &x)?;
+                if points.len() < 3 {
+                    return Err(ErrorTypes::InvalidGeo("inside_polygon must have at least three points".to_string()));
+                }
+                Some(points)
+            },
+            None => None
+        };
+
+        if around.is_none() && bounding_box.is_none() && polygon.is_none() {
+            return Ok(None);
+        }
+
+        Ok(Some(Self {
+            field,
+            around,
+            around_radius,
+            bounding_box,
+            polygon
+        }))
+    }
+
+    /// Returns the geo_point field to search in
+    ///
+    /// Defaults to the only geo_point field of the index, or _geoloc if there are several
+    pub fn resolve_field(&self, fields: &HashMap<String, String>) -> Result<String, ErrorTypes> {
+        let geo_fields: Vec<&String> = fields.iter().filter(|(_, x)| x.as_str() == "geo_point").map(|(x, _)| x).collect();
+
+        let field = match (&self.field, geo_fields.as_slice()) {
+            (Some(field), _) => field.to_string(),
+            (None, [field]) => field.to_string(),
+            (None, []) => return Err(ErrorTypes::InvalidGeo("index has no geo_point field".to_string())),
+            (None, _) => DEFAULT_GEO_FIELD.to_string()
+        };
+
+        match fields.get(&field).map(|x| x.as_str()) {
+            Some("geo_point") => Ok(field),
+            Some(_) => Err(ErrorTypes::InvalidGeo(format!("field [{}] is not a geo_point", field))),
+            None => Err(ErrorTypes::InvalidGeo(format!("field [{}] not found", field)))
+        }
+    }
+
+    /// Builds the filter clauses of the geo search
+    pub fn to_filters(&self, field: &str) -> Vec<Value> {
+        let mut filters = Vec::new();
+
+        if let (Some((lat, lng)), Some(radius)) = (self.around, self.around_radius) {
+            filters.push(json!({
+                "geo_distance": {
+                    "distance": format!("{}m", radius),
+                    field: { "lat": lat, "lon": lng }
+                }
+            }));
+        }
+
+        // Kept as given, Elasticsearch wraps the box around the antimeridian when the left longitude is above the right one
+        if let Some([(top, left), (bottom, right)]) = self.bounding_box {
+            filters.push(json!({
+                "geo_bounding_box": {
+                    field: {
+                        "top_left": { "lat": top, "lon": left },
+                        "bottom_right": { "lat": bottom, "lon": right }
+                    }
+                }
+            }));
+        }
+
+        if let Some(polygon) = &self.polygon {
+            // GeoJSON uses longitude first and requires the ring to be closed
+            let mut ring: Vec<[f64; 2]> = polygon.iter().map(|(lat, lng)| [*lng, *lat]).collect();
+            ring.push(ring[0]);
+
+            filters.push(json!({
+                "geo_shape": {
+                    field: {
+                        "shape": {
+                            "type": "polygon",
+                            "coordinates": [ring]
+                        },
+                        "relation": "intersects"
+                    }
+                }
+            }));
+        }
+
+        filters
+    }
+
+    /// Builds a script field returning the distance in meters from around_lat_lng, returns None without around_lat_lng
+    pub fn to_distance_field(&self, field: &str) -> Option<Value> {
+        let (lat, lng) = self.around?;
+
+        Some(json!({
+            "script": {
+                "source": "doc[params.field].size() == 0 ? null : doc[params.field].arcDistance(params.lat, params.lng)",
+                "params": {
+                    "field": field,
+                    "lat": lat,
+                    "lng": lng
+                }
+            }
+        }))
+    }
+}
+
+/// Converts {lat, lng} objects of geo_point fields into {lat, lon}, which is the format Elasticsearch accepts
+pub fn normalize_geo_points(data: &mut Value, fields: &HashMap<String, String>) {
+    fn normalize(value: &mut Value, path: &[&str]) {
+        match (value, path) {
+            (Value::Array(values), _) => values.iter_mut().for_each(|x| normalize(x, path)),
+            (Value::Object(point), []) if !point.contains_key("lon") => {
+                if let Some(lng) = point.remove("lng") {
+                    point.insert("lon".to_string(), lng);
+                }
+            },
+            (Value::Object(object), [name, rest @ ..]) => {
+                if let Some(x) = object.get_mut(*name) {
+                    normalize(x, rest);
+                }
+            },
+            _ => ()
+        }
+    }
+
+    for (field, _) in fields.iter().filter(|(_, x)| x.as_str() == "geo_point") {
+        let path: Vec<&str> = field.split('.').collect();
+        normalize(data, &path);
+    }
+}
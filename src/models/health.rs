@@ -0,0 +1,131 @@
+use std::{sync::{Arc, RwLock}, time::Duration};
+
+use elasticsearch::cluster::ClusterHealthParts;
+use serde::Serialize;
+use serde_json::Value;
+
+use super::{EClient, ErrorTypes, api_keys::now, pool::TRACEPARENT};
+
+/// Health of the cluster as reported by the cluster health API
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct ClusterHealth {
+    pub cluster_name: String,
+    /// green, yellow or red
+    pub status: String,
+    pub number_of_nodes: u64,
+    pub number_of_data_nodes: u64,
+    pub unassigned_shards: u64,
+    pub pending_tasks: u64,
+    pub active_shards_percent: f64
+}
+
+impl ClusterHealth {
+    /// Whether requests can be served, a red cluster is missing primary shards
+    pub fn is_ready(&self) -> bool {
+        self.status != "red"
+    }
+
+    fn from_response(body: &Value) -> Self {
+        Self {
+            cluster_name: body["cluster_name"].as_str().unwrap_or_default().to_string(),
+            status: body["status"].as_str().unwrap_or("red").to_string(),
+            number_of_nodes: body["number_of_nodes"].as_u64().unwrap_or_default(),
+            number_of_data_nodes: body["number_of_data_nodes"].as_u64().unwrap_or_default(),
+            unassigned_shards: body["unassigned_shards"].as_u64().unwrap_or_default(),
+            pending_tasks: body["number_of_pending_tasks"].as_u64().unwrap_or_default(),
+            active_shards_percent: body["active_shards_percent_as_number"].as_f64().unwrap_or_default()
+        }
+    }
+}
+
+/// Last known health of the cluster
+#[derive(Clone, Debug, Default)]
+pub enum HealthState {
+    /// Not checked yet
+    #[default]
+    Unknown,
+    Up(ClusterHealth),
+    /// The cluster answered but its health could not be read, such as without the monitor privilege, with the reason
+    Degraded(String),
+    /// The cluster could not be reached, with the reason
+    Down(String)
+}
+
+/// The health of the cluster cached by the monitor, so that requests fail fast without asking the cluster first
+#[derive(Default)]
+pub struct HealthMonitor {
+    state: RwLock<(HealthState, Option<u64>)>
+}
+
+impl HealthMonitor {
+    /// Last known health, with the unix time it was checked at
+    pub fn state(&self) -> (HealthState, Option<u64>) {
+        self.state.read().unwrap().clone()
+    }
+
+    /// Only transport failures mark the cluster down, any other error means it answered
+    pub fn record(&self, result: &Result<ClusterHealth, ErrorTypes>) {
+        let state = match result {
+            Ok(x) => HealthState::Up(x.clone()),
+            Err(ErrorTypes::ServerDown) => HealthState::Down(ErrorTypes::ServerDown.to_string()),
+            Err(x) => HealthState::Degraded(x.to_string())
+        };
+
+        *self.state.write().unwrap() = (state, Some(now()));
+    }
+
+    /// Fails with ServerDown when the last check could not reach the cluster, requests are let through until the first check and while degraded
+    pub fn ensure_reachable(&self) -> Result<(), ErrorTypes> {
+        match self.state.read().unwrap().0 {
+            HealthState::Down(_) => Err(ErrorTypes::ServerDown),
+            _ => Ok(())
+        }
+    }
+}
+
+impl EClient {
+    /// Asks the cluster for its health and caches the answer
+    #[tracing::instrument(skip_all)]
+    pub async fn check_health(&self) -> Result<ClusterHealth, ErrorTypes> {
+        let result = self.cluster_health().await;
+        self.health.record(&result);
+
+        result
+    }
+
+    async fn cluster_health(&self) -> Result<ClusterHealth, ErrorTypes> {
+        let resp = self.elastic
+            .send("cluster_health", |es, traceparent| async move {
+                es.cluster()
+                    .health(ClusterHealthParts::None)
+                    .header(TRACEPARENT, traceparent)
+                    .send()
+                    .await
+            })
+            .await?;
+
+        if !resp.status_code().is_success() {
+            return Err(ErrorTypes::from_response(resp).await);
+        }
+
+        Ok(ClusterHealth::from_response(&resp.json::<Value>().await?))
+    }
+
+    /// Checks the health of the cluster now and every interval, forever
+    pub async fn monitor_health_every(self: Arc<Self>, interval: Duration) {
+        loop {
+            let was_down = matches!(self.health.state().0, HealthState::Down(_));
+
+            match (self.check_health().await, was_down) {
+                (Ok(_), true) => log::info!("Elasticsearch cluster is reachable again"),
+                (Err(ErrorTypes::ServerDown), false) => log::warn!("Elasticsearch cluster is unreachable, requests fail until it is back"),
+                (Err(ErrorTypes::ServerDown), true) => (),
+                // Requests are still served, only the health is unknown
+                (Err(x), _) => log::warn!("Elasticsearch cluster health could not be read, health is degraded, {}", x),
+                _ => ()
+            }
+
+            actix_web::rt::time::sleep(interval).await;
+        }
+    }
+}
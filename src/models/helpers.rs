@@ -1,43 +1,83 @@
-use actix_web::HttpResponse;
-use elasticsearch::{indices::IndicesExistsParts, Elasticsearch};
-use reqwest::StatusCode;
-use serde_json::json;
-
-use crate::models::ErrorTypes;
-
-pub async fn server_down_check(server: &Elasticsearch) -> Result<(), HttpResponse> {
-    let server = server
-        .indices()
-        .exists(IndicesExistsParts::Index(&["test"]))
-        .send()
-        .await;        
-        
-    match server {
-        Ok(_) => return Ok(()),
-        Err(_) => {
-            return Err(HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR).json(json!({"error": ErrorTypes::ServerDown.to_string()})))
-        }
-    };
-}
+use std::collections::HashMap;
+
+use serde_json::Value;
+
+/// Flattens the properties of a mapping into a map of field name and its type
+/// 
+/// Object fields are joined with dots (ex: address.city), multi fields are included as well (ex: name.keyword)
+pub fn mapping_field_types(mappings: &Value) -> HashMap<String, String> {
+    fn flatten(properties: &Value, prefix: &str, fields: &mut HashMap<String, String>) {
+        let Some(properties) = properties.as_object() else {
+            return;
+        };
 
-pub async fn index_exists_check(server: &Elasticsearch, index: &str) -> Result<(), HttpResponse> {
-    let index_check = server
-        .indices()
-        .exists(IndicesExistsParts::Index(&[index]))
-        .send()
-        .await       
-        .unwrap();
+        for (name, property) in properties {
+            let path = format!("{}{}", prefix, name);
+
+            if let Some(field_type) = property["type"].as_str() {
+                fields.insert(path.clone(), field_type.to_string());
+            }
+
+            if let Some(multi_fields) = property["fields"].as_object() {
+                for (sub_name, sub_property) in multi_fields {
+                    if let Some(field_type) = sub_property["type"].as_str() {
+                        fields.insert(format!("{}.{}", path, sub_name), field_type.to_string());
+                    }
+                }
+            }
+
+            flatten(&property["properties"], &format!("{}.", path), fields);
+        }
+    }
 
-    let status_code = index_check.status_code();
+    let mut fields = HashMap::new();
+    flatten(&mappings["properties"], "", &mut fields);
+    fields
+}
 
-    if !status_code.is_success(){
-        let error =  match status_code{
-            StatusCode::NOT_FOUND => ErrorTypes::IndexNotFound(index.to_string()).to_string(),
-            _ => ErrorTypes::Unknown.to_string()
+/// Returns the fields of documents in a mapping, joined with dots for object fields, sorted by name
+/// 
+/// Unlike mapping_field_types, multi fields are not included as they are not part of the document
+pub fn mapping_document_fields(mappings: &Value) -> Vec<String> {
+    fn flatten(properties: &Value, prefix: &str, fields: &mut Vec<String>) {
+        let Some(properties) = properties.as_object() else {
+            return;
         };
 
-        return Err(HttpResponse::build(status_code).json(json!({"error": error})));
+        for (name, property) in properties {
+            let path = format!("{}{}", prefix, name);
+
+            match property.get("properties") {
+                Some(x) => flatten(x, &format!("{}.", path), fields),
+                None => fields.push(path)
+            }
+        }
     }
 
-    Ok(())
+    let mut fields = Vec::new();
+    flatten(&mappings["properties"], "", &mut fields);
+    fields.sort();
+    fields
+}
+
+/// Returns true if the type is any of Elasticsearch's numeric field types
+pub fn is_numeric_type(field_type: &str) -> bool {
+    matches!(field_type, "long" | "integer" | "short" | "byte" | "double" | "float" | "half_float" | "scaled_float" | "unsigned_long")
+}
+
+/// Returns the field that holds exact values of a field, used for aggregations and sorting
+/// 
+/// Text fields are analyzed, so their keyword subfield is used instead, returns None if there is none
+pub fn exact_value_field(fields: &HashMap<String, String>, field: &str) -> Option<String> {
+    match fields.get(field).map(|x| x.as_str()) {
+        Some("text") | Some("match_only_text") => {
+            let keyword = format!("{}.keyword", field);
+            match fields.get(&keyword).map(|x| x.as_str()) {
+                Some("keyword") => Some(keyword),
+                _ => None
+            }
+        },
+        Some(_) => Some(field.to_string()),
+        None => None
+    }
 }
\ No newline at end of file
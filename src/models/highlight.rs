@@ -0,0 +1,157 @@
+use serde_json::{json, Map, Value};
+
+/// Default amount of words in a snippet
+pub const DEFAULT_SNIPPET_WORDS: usize = 10;
+
+/// Highlight and snippet settings of a search
+pub struct Highlight {
+    pub highlight_fields: Vec<String>,
+    /// Fields to snippet with the amount of words in the snippet
+    pub snippet_fields: Vec<(String, usize)>,
+    pub pre_tag: String,
+    pub post_tag: String
+}
+
+impl Highlight {
+    /// Creates highlight settings from comma separated fields, snippet fields may specify a word count (ex: "description:20,name")
+    ///
+    /// Returns None if there are no fields to highlight or snippet
+    pub fn new(highlight_fields: Option<String>, snippet_fields: Option<String>, pre_tag: Option<String>, post_tag: Option<String>) -> Option<Self> {
+        let highlight_fields: Vec<String> = highlight_fields
+            .map(|x| x.split(',').map(|x| x.trim().to_string()).filter(|x| !x.is_empty()).collect())
+            .unwrap_or_default();
+
+        let snippet_fields: Vec<(String, usize)> = snippet_fields
+            .map(|x| x.split(',').map(|x| x.trim()).filter(|x| !x.is_empty()).map(|field| {
+                match field.rsplit_once(':') {
+                    Some((name, words)) => match words.trim().parse::<usize>() {
+                        Ok(words) => (name.trim().to_string(), words.max(1)),
+                        Err(_) => (field.to_string(), DEFAULT_SNIPPET_WORDS)
+                    },
+                    None => (field.to_string(), DEFAULT_SNIPPET_WORDS)
+                }
+            }).collect())
+            .unwrap_or_default();
+
+        if highlight_fields.is_empty() && snippet_fields.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            highlight_fields,
+            snippet_fields,
+            pre_tag: pre_tag.unwrap_or_else(|| "<em>".to_string()),
+            post_tag: post_tag.unwrap_or_else(|| "</em>".to_string())
+        })
+    }
+
+    /// Builds the highlight clause of a search body
+    ///
+    /// Fields are highlighted whole, snippets are cut from the highlighted value afterwards
+    pub fn to_highlight(&self) -> Value {
+        let mut fields = Map::new();
+        for field in self.highlight_fields.iter().chain(self.snippet_fields.iter().map(|(field, _)| field)) {
+            fields.insert(field.to_string(), json!({
+                "number_of_fragments": 0
+            }));
+        }
+
+        json!({
+            "pre_tags": [self.pre_tag],
+            "post_tags": [self.post_tag],
+            "require_field_match": false,
+            "fields": fields
+        })
+    }
+
+    /// Replaces the highlight of a hit returned by Elasticsearch with _highlight and _snippet
+    pub fn apply(&self, hit: &mut Value) {
+        let highlight = hit
+            .as_object_mut()
+            .and_then(|x| x.remove("highlight"))
+            .unwrap_or_else(|| json!({}));
+
+        if !self.highlight_fields.is_empty() {
+            let mut highlighted = Map::new();
+            for field in &self.highlight_fields {
+                if let Some(value) = highlighted_value(&highlight[field]) {
+                    highlighted.insert(field.to_string(), value);
+                }
+            }
+            hit["_highlight"] = Value::Object(highlighted);
+        }
+
+        if !self.snippet_fields.is_empty() {
+            let mut snippets = Map::new();
+            for (field, words) in &self.snippet_fields {
+                // Fields without a match are snippeted from the start of the returned value
+                let text = highlight[field][0].as_str().or_else(|| hit["fields"][field][0].as_str());
+                if let Some(text) = text {
+                    snippets.insert(field.to_string(), json!(self.snippet(text, *words)));
+                }
+            }
+            hit["_snippet"] = Value::Object(snippets);
+        }
+    }
+
+    /// Cuts a window of words around the first highlighted word, marking cut ends with an ellipsis
+    fn snippet(&self, text: &str, words: usize) -> String {
+        let tokens = self.words(text);
+        let matched = tokens.iter().position(|x| x.contains(&self.pre_tag)).unwrap_or(0);
+
+        let start = matched.saturating_sub(words / 2).min(tokens.len().saturating_sub(words));
+        let end = (start + words).min(tokens.len());
+
+        let mut snippet = tokens[start..end].join(" ");
+        if start > 0 {
+            snippet = format!("… {}", snippet);
+        }
+        if end < tokens.len() {
+            snippet = format!("{} …", snippet);
+        }
+        snippet
+    }
+
+    /// Splits a highlighted value on whitespace, from a pre tag to its post tag is a single word so that the window never cuts a pair of tags, or tags holding spaces
+    fn words<'a>(&self, text: &'a str) -> Vec<&'a str> {
+        // Byte ranges of the highlighted parts, tags included
+        let mut highlighted = Vec::new();
+        let mut from = 0;
+        while let Some(start) = text[from..].find(&self.pre_tag).map(|x| from + x).filter(|_| !self.pre_tag.is_empty()) {
+            let after_pre_tag = start + self.pre_tag.len();
+            let end = text[after_pre_tag..]
+                .find(&self.post_tag)
+                .map(|x| after_pre_tag + x + self.post_tag.len())
+                .unwrap_or(text.len());
+
+            highlighted.push(start..end);
+            from = end;
+        }
+
+        let mut tokens = Vec::new();
+        let mut token_start = None;
+        for (position, x) in text.char_indices() {
+            if x.is_whitespace() && !highlighted.iter().any(|range| range.contains(&position)) {
+                if let Some(start) = token_start.take() {
+                    tokens.push(&text[start..position]);
+                }
+            } else if token_start.is_none() {
+                token_start = Some(position);
+            }
+        }
+        if let Some(start) = token_start {
+            tokens.push(&text[start..]);
+        }
+
+        tokens
+    }
+}
+
+/// Single valued fields are returned as string, multi valued fields as an array of the matched values
+fn highlighted_value(fragments: &Value) -> Option<Value> {
+    match fragments.as_array() {
+        Some(values) if values.len() == 1 => Some(values[0].clone()),
+        Some(values) if !values.is_empty() => Some(fragments.clone()),
+        _ => None
+    }
+}
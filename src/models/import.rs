@@ -0,0 +1,628 @@
+use std::{collections::HashMap, fmt::Display, str::FromStr};
+
+use actix_web::web::Bytes;
+use csv_core::{Reader, ReadRecordResult};
+use futures_util::{Stream, StreamExt};
+use serde::{Serialize, Serializer};
+use serde_json::{json, Map, Value};
+
+use super::{ErrorTypes, backend::SearchBackend, documents::{BulkAction, BULK_BATCH_SIZE}, geo::normalize_geo_points, helpers::mapping_field_types, schema::{FieldType, write_schema}};
+
+/// Maximum amount of rejected rows listed in the import summary, the count is always exact
+pub const MAX_IMPORT_ERRORS: usize = 1000;
+
+/*
+CSV files require a header row, columns are imported as strings unless a type is given,
+either in the header (ex: "links_count:integer") or through the types parameter (ex: "links_count:integer,active:boolean")
+On an index with a schema, columns of its number and boolean fields are typed as such, and documents are checked against it
+
+Types: string, integer, float, boolean, json
+Dotted column names are imported as objects (ex: "address.city"), empty cells are left out
+
+For every format, an _id column or field is used as the id of the document
+*/
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImportFormat {
+    Csv,
+    Ndjson,
+    Json
+}
+
+impl FromStr for ImportFormat {
+    type Err = ErrorTypes;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "csv" => Ok(ImportFormat::Csv),
+            "ndjson" | "jsonl" => Ok(ImportFormat::Ndjson),
+            "json" => Ok(ImportFormat::Json),
+            _ => Err(ErrorTypes::InvalidImport(format!("unknown format [{}], expected csv, ndjson or json", s)))
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColumnType {
+    String,
+    Integer,
+    Float,
+    Boolean,
+    Json
+}
+
+impl FromStr for ColumnType {
+    type Err = ErrorTypes;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "string" | "text" | "keyword" => Ok(ColumnType::String),
+            "integer" | "int" | "long" => Ok(ColumnType::Integer),
+            "float" | "double" | "number" => Ok(ColumnType::Float),
+            "boolean" | "bool" => Ok(ColumnType::Boolean),
+            "json" => Ok(ColumnType::Json),
+            _ => Err(ErrorTypes::InvalidImport(format!("unknown column type [{}], expected string, integer, float, boolean or json", s)))
+        }
+    }
+}
+
+impl ColumnType {
+    fn convert(&self, column: &str, cell: &str) -> Result<Value, String> {
+        let invalid = |kind: &str| format!("column [{}] value [{}] is not a valid {}", column, cell, kind);
+
+        match self {
+            ColumnType::String => Ok(json!(cell)),
+            ColumnType::Integer => cell.trim().parse::<i64>().map(|x| json!(x)).map_err(|_| invalid("integer")),
+            // NaN and infinity have no json value
+            ColumnType::Float => cell.trim().parse::<f64>().ok().filter(|x| x.is_finite()).map(|x| json!(x)).ok_or_else(|| invalid("float")),
+            ColumnType::Boolean => match cell.trim().to_lowercase().as_str() {
+                "true" | "1" | "yes" => Ok(json!(true)),
+                "false" | "0" | "no" => Ok(json!(false)),
+                _ => Err(invalid("boolean"))
+            },
+            ColumnType::Json => serde_json::from_str(cell).map_err(|_| invalid("json"))
+        }
+    }
+}
+
+/// Parses comma separated column types (ex: "links_count:integer,active:boolean")
+pub fn parse_column_types(types: &str) -> Result<HashMap<String, ColumnType>, ErrorTypes> {
+    types
+        .split(',')
+        .filter(|x| !x.trim().is_empty())
+        .map(|x| match x.rsplit_once(':') {
+            Some((column, column_type)) => Ok((column.trim().to_string(), column_type.parse()?)),
+            None => Err(ErrorTypes::InvalidImport(format!("column type [{}] must be written as column:type", x.trim())))
+        })
+        .collect()
+}
+
+/// A single parsed row, either a document with its optional id or the reason it was rejected
+struct ImportRecord {
+    line: u64,
+    document: Result<(Option<String>, Value), String>
+}
+
+/// Takes the _id field out of a document
+fn into_document(line: u64, value: Value) -> ImportRecord {
+    let document = match value {
+        Value::Object(mut object) => {
+            let id = match object.remove("_id") {
+                Some(Value::String(x)) => Some(x),
+                Some(Value::Number(x)) => Some(x.to_string()),
+                _ => None
+            };
+            Ok((id, Value::Object(object)))
+        },
+        _ => Err("document must be a json object".to_string())
+    };
+
+    ImportRecord { line, document }
+}
+
+/// Inserts a value into an object at a dotted path, creating the objects in between
+fn insert_path(object: &mut Map<String, Value>, path: &str, value: Value) {
+    match path.split_once('.') {
+        Some((name, rest)) => {
+            let child = object.entry(name.to_string()).or_insert_with(|| json!({}));
+            if !child.is_object() {
+                *child = json!({});
+            }
+            insert_path(child.as_object_mut().unwrap(), rest, value);
+        },
+        None => {
+            object.insert(path.to_string(), value);
+        }
+    }
+}
+
+struct CsvParser {
+    reader: Reader,
+    output: Vec<u8>,
+    ends: Vec<usize>,
+    output_len: usize,
+    ends_len: usize,
+    types: HashMap<String, ColumnType>,
+    header: Option<Vec<(String, ColumnType)>>,
+    /// Line where the record being read started
+    record_line: u64
+}
+
+impl CsvParser {
+    fn new(types: HashMap<String, ColumnType>) -> Self {
+        Self {
+            reader: Reader::new(),
+            output: vec![0; 4096],
+            ends: vec![0; 64],
+            output_len: 0,
+            ends_len: 0,
+            types,
+            header: None,
+            record_line: 1
+        }
+    }
+
+    /// Reads every complete record of the input, an empty input marks the end of the file
+    fn read(&mut self, mut input: &[u8], records: &mut Vec<ImportRecord>) -> Result<(), ErrorTypes> {
+        let eof = input.is_empty();
+
+        loop {
+            let (result, read, written, ends_written) = self.reader.read_record(input, &mut self.output[self.output_len..], &mut self.ends[self.ends_len..]);
+            input = &input[read..];
+            self.output_len += written;
+            self.ends_len += ends_written;
+
+            match result {
+                ReadRecordResult::InputEmpty if !eof => return Ok(()),
+                ReadRecordResult::InputEmpty => (),
+                ReadRecordResult::OutputFull => self.output.resize(self.output.len() * 2, 0),
+                ReadRecordResult::OutputEndsFull => self.ends.resize(self.ends.len() * 2, 0),
+                ReadRecordResult::Record => {
+                    self.record(records)?;
+                    self.output_len = 0;
+                    self.ends_len = 0;
+                    self.record_line = self.reader.line();
+                },
+                ReadRecordResult::End => return Ok(())
+            }
+        }
+    }
+
+    fn record(&mut self, records: &mut Vec<ImportRecord>) -> Result<(), ErrorTypes> {
+        let line = self.record_line;
+        let mut cells = Vec::with_capacity(self.ends_len);
+        let mut start = 0;
+
+        for end in &self.ends[..self.ends_len] {
+            match std::str::from_utf8(&self.output[start..*end]) {
+                Ok(x) => cells.push(x.to_string()),
+                Err(_) => {
+                    records.push(ImportRecord { line, document: Err("row is not valid utf-8".to_string()) });
+                    return Ok(());
+                }
+            }
+            start = *end;
+        }
+
+        let header = match &self.header {
+            Some(x) => x,
+            None => {
+                let mut header = Vec::with_capacity(cells.len());
+                for cell in cells {
+                    let (column, column_type) = match cell.rsplit_once(':') {
+                        Some((column, column_type)) => (column.trim().to_string(), column_type.parse::<ColumnType>()?),
+                        None => (cell.trim().to_string(), ColumnType::String)
+                    };
+                    let column_type = self.types.get(&column).copied().unwrap_or(column_type);
+                    header.push((column, column_type));
+                }
+                self.header = Some(header);
+                return Ok(());
+            }
+        };
+
+        if cells.len() != header.len() {
+            records.push(ImportRecord { line, document: Err(format!("row has {} columns, header has {}", cells.len(), header.len())) });
+            return Ok(());
+        }
+
+        let mut id = None;
+        let mut document = Map::new();
+
+        for ((column, column_type), cell) in header.iter().zip(cells) {
+            if cell.is_empty() {
+                continue;
+            }
+
+            if column == "_id" {
+                id = Some(cell);
+                continue;
+            }
+
+            match column_type.convert(column, &cell) {
+                Ok(value) => insert_path(&mut document, column, value),
+                Err(reason) => {
+                    records.push(ImportRecord { line, document: Err(reason) });
+                    return Ok(());
+                }
+            }
+        }
+
+        records.push(ImportRecord { line, document: Ok((id, Value::Object(document))) });
+        Ok(())
+    }
+}
+
+struct NdjsonParser {
+    buffer: Vec<u8>,
+    /// Bytes of the buffer already searched for the end of the line, so that long lines are not searched again with every chunk
+    scanned: usize,
+    line: u64
+}
+
+impl NdjsonParser {
+    fn read_line(line_number: &mut u64, line: &[u8], records: &mut Vec<ImportRecord>) {
+        *line_number += 1;
+
+        if line.iter().all(|x| x.is_ascii_whitespace()) {
+            return;
+        }
+
+        match serde_json::from_slice::<Value>(line) {
+            Ok(value) => records.push(into_document(*line_number, value)),
+            Err(x) => records.push(ImportRecord { line: *line_number, document: Err(format!("invalid json, {}", x)) })
+        }
+    }
+
+    fn read(&mut self, input: &[u8], records: &mut Vec<ImportRecord>) {
+        if input.is_empty() {
+            Self::read_line(&mut self.line, &self.buffer, records);
+            self.buffer.clear();
+            self.scanned = 0;
+            return;
+        }
+
+        self.buffer.extend_from_slice(input);
+
+        let mut start = 0;
+        while let Some(end) = self.buffer[self.scanned..].iter().position(|x| *x == b'\n') {
+            let end = self.scanned + end;
+            Self::read_line(&mut self.line, &self.buffer[start..end], records);
+            start = end + 1;
+            self.scanned = start;
+        }
+
+        self.buffer.drain(..start);
+        self.scanned = self.buffer.len();
+    }
+}
+
+#[derive(PartialEq)]
+enum JsonArrayState {
+    /// Before the [
+    Start,
+    /// After the [, either the first element or ]
+    First,
+    /// After an element, either , or ]
+    Separator,
+    /// After a comma, an element
+    Element,
+    Done
+}
+
+/// Where an element of the array ends, kept across chunks so that an element is only read once
+#[derive(Default)]
+struct ElementScan {
+    /// Bytes of the element already scanned
+    scanned: usize,
+    depth: usize,
+    in_string: bool,
+    escaped: bool
+}
+
+impl ElementScan {
+    /// Length of the element starting at the beginning of bytes, None if it continues past them
+    fn end(&mut self, bytes: &[u8]) -> Option<usize> {
+        for (i, x) in bytes.iter().enumerate().skip(self.scanned) {
+            if self.in_string {
+                match x {
+                    _ if self.escaped => self.escaped = false,
+                    b'\\' => self.escaped = true,
+                    b'"' => {
+                        self.in_string = false;
+                        if self.depth == 0 {
+                            return Some(i + 1);
+                        }
+                    },
+                    _ => ()
+                }
+                continue;
+            }
+
+            match x {
+                b'"' => self.in_string = true,
+                b'{' | b'[' => self.depth += 1,
+                // Closes the array after a number, true, false or null
+                b'}' | b']' if self.depth == 0 => return Some(i),
+                b'}' | b']' => {
+                    self.depth -= 1;
+                    if self.depth == 0 {
+                        return Some(i + 1);
+                    }
+                },
+                x if self.depth == 0 && (*x == b',' || x.is_ascii_whitespace()) => return Some(i),
+                _ => ()
+            }
+        }
+
+        self.scanned = bytes.len();
+        None
+    }
+}
+
+struct JsonArrayParser {
+    buffer: Vec<u8>,
+    state: JsonArrayState,
+    scan: ElementScan,
+    line: u64
+}
+
+impl JsonArrayParser {
+    /// Reads every complete element of the array, an empty input marks the end of the file
+    fn read(&mut self, input: &[u8], records: &mut Vec<ImportRecord>) -> Result<(), ErrorTypes> {
+        let eof = input.is_empty();
+        self.buffer.extend_from_slice(input);
+
+        let mut position = 0;
+
+        loop {
+            while let Some(x) = self.buffer.get(position).filter(|x| x.is_ascii_whitespace()) {
+                if *x == b'\n' {
+                    self.line += 1;
+                }
+                position += 1;
+            }
+
+            let Some(next) = self.buffer.get(position).copied() else {
+                break;
+            };
+
+            let unexpected = |expected: &str| ErrorTypes::InvalidImport(format!("expected {} at line {}, found [{}]", expected, self.line, next as char));
+
+            match self.state {
+                JsonArrayState::Start if next == b'[' => {
+                    self.state = JsonArrayState::First;
+                    position += 1;
+                },
+                JsonArrayState::Start => return Err(unexpected("[")),
+                JsonArrayState::First | JsonArrayState::Separator if next == b']' => {
+                    self.state = JsonArrayState::Done;
+                    position += 1;
+                },
+                JsonArrayState::Separator if next == b',' => {
+                    self.state = JsonArrayState::Element;
+                    position += 1;
+                },
+                JsonArrayState::Separator => return Err(unexpected(", or ]")),
+                JsonArrayState::First | JsonArrayState::Element if next == b',' || next == b']' => return Err(unexpected("an element")),
+                JsonArrayState::First | JsonArrayState::Element => {
+                    let element = &self.buffer[position..];
+                    let length = match self.scan.end(element) {
+                        Some(x) => x,
+                        // A value at the very end of the file, left for serde to tell what is missing
+                        None if eof => element.len(),
+                        // The element continues in the next chunk
+                        None => break
+                    };
+
+                    let element = &element[..length];
+                    self.scan = ElementScan::default();
+
+                    match serde_json::from_slice::<Value>(element) {
+                        Ok(value) => records.push(into_document(self.line, value)),
+                        Err(x) => return Err(ErrorTypes::InvalidImport(format!("invalid json at line {}, {}", self.line, x)))
+                    }
+
+                    self.line += element.iter().filter(|x| **x == b'\n').count() as u64;
+                    self.state = JsonArrayState::Separator;
+                    position += length;
+                },
+                JsonArrayState::Done => {
+                    position = self.buffer.len();
+                    break;
+                }
+            }
+        }
+
+        self.buffer.drain(..position);
+
+        if eof && self.state != JsonArrayState::Done {
+            return Err(ErrorTypes::InvalidImport("unexpected end of file, expected ]".to_string()));
+        }
+
+        Ok(())
+    }
+}
+
+enum ImportParser {
+    Csv(Box<CsvParser>),
+    Ndjson(NdjsonParser),
+    Json(JsonArrayParser)
+}
+
+impl ImportParser {
+    fn new(format: ImportFormat, types: HashMap<String, ColumnType>) -> Self {
+        match format {
+            ImportFormat::Csv => ImportParser::Csv(Box::new(CsvParser::new(types))),
+            ImportFormat::Ndjson => ImportParser::Ndjson(NdjsonParser { buffer: Vec::new(), scanned: 0, line: 0 }),
+            ImportFormat::Json => ImportParser::Json(JsonArrayParser { buffer: Vec::new(), state: JsonArrayState::Start, scan: ElementScan::default(), line: 1 }),
+        }
+    }
+
+    /// Parses a chunk of the file into records, an empty chunk marks the end of the file
+    fn read(&mut self, input: &[u8], records: &mut Vec<ImportRecord>) -> Result<(), ErrorTypes> {
+        match self {
+            ImportParser::Csv(x) => x.read(input, records),
+            ImportParser::Ndjson(x) => {
+                x.read(input, records);
+                Ok(())
+            },
+            ImportParser::Json(x) => x.read(input, records)
+        }
+    }
+}
+
+/// A rejected row of an import
+#[derive(Serialize)]
+pub struct ImportError {
+    pub line: u64,
+    pub reason: String
+}
+
+/// Results of an import
+#[derive(Default, Serialize)]
+pub struct ImportSummary {
+    pub took: i64,
+    pub imported: usize,
+    pub rejected: usize,
+    /// The first MAX_IMPORT_ERRORS rejected rows
+    pub errors: Vec<ImportError>,
+    /// Error that stopped the import early, documents read before it are still imported
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "serialize_failure")]
+    pub error: Option<ErrorTypes>
+}
+
+fn serialize_failure<S: Serializer>(error: &Option<ErrorTypes>, serializer: S) -> Result<S::Ok, S::Error> {
+    match error {
+        Some(x) => serializer.collect_str(x),
+        None => serializer.serialize_none()
+    }
+}
+
+impl ImportSummary {
+    fn reject(&mut self, line: u64, reason: String) {
+        self.rejected += 1;
+        if self.errors.len() < MAX_IMPORT_ERRORS {
+            self.errors.push(ImportError { line, reason });
+        }
+    }
+}
+
+/// Sends the pending documents through the bulk api, recording rejected documents with their line
+async fn flush_import(backend: &dyn SearchBackend, index: &str, pending: &mut Vec<(u64, BulkAction)>, summary: &mut ImportSummary) -> Result<(), ErrorTypes> {
+    if pending.is_empty() {
+        return Ok(());
+    }
+
+    let (lines, batch): (Vec<u64>, Vec<BulkAction>) = std::mem::take(pending).into_iter().unzip();
+    let (took, results) = backend.send_bulk_batch(index, batch).await?;
+    summary.took += took;
+
+    for (line, result) in lines.into_iter().zip(results) {
+        match result.error {
+            Some(reason) => summary.reject(line, reason),
+            None => summary.imported += 1
+        }
+    }
+
+    Ok(())
+}
+
+/// Imports a file into an index, documents are parsed as the file is received and sent in batches through the bulk api
+///
+/// types are comma separated column types used for csv files (ex: "links_count:integer,active:boolean")
+///
+/// An error met once the import started is returned in the summary, along with the counts of what was imported before it
+pub async fn import_documents<S, E>(backend: &dyn SearchBackend, index: &str, format: ImportFormat, types: Option<String>, dynamic_mode: Option<String>, mut file: S) -> Result<ImportSummary, ErrorTypes>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+    E: Display
+{
+    let mut types = types.as_deref().map(parse_column_types).transpose()?.unwrap_or_default();
+
+    let mappings = backend.get_index_mappings(index).await?;
+    let fields = mapping_field_types(&mappings);
+    let schema = write_schema(&mappings, dynamic_mode.as_deref())?;
+
+    // Columns of the schema are typed like its fields unless a type is given
+    if let Some(schema) = &schema {
+        for (name, field) in &schema.0 {
+            types.entry(name.to_string()).or_insert(match field.field_type {
+                FieldType::Number => ColumnType::Float,
+                FieldType::Boolean => ColumnType::Boolean,
+                _ => ColumnType::String
+            });
+        }
+    }
+
+    if let Some(mode) = dynamic_mode {
+        let set_dynamic = json!({
+            "dynamic": mode
+        });
+
+        backend.update_index_mappings(index, set_dynamic).await?;
+    }
+
+    let mut parser = ImportParser::new(format, types);
+    let mut summary = ImportSummary::default();
+    let mut records = Vec::new();
+    let mut pending: Vec<(u64, BulkAction)> = Vec::with_capacity(BULK_BATCH_SIZE);
+
+    loop {
+        let chunk = match file.next().await {
+            Some(Ok(x)) if x.is_empty() => continue,
+            Some(Ok(x)) => x,
+            Some(Err(x)) => {
+                summary.error = Some(ErrorTypes::InvalidImport(format!("failed to read file, {}", x)));
+                break;
+            },
+            None => Bytes::new()
+        };
+
+        if let Err(x) = parser.read(&chunk, &mut records) {
+            summary.error = Some(x);
+            break;
+        }
+
+        for record in records.drain(..) {
+            match record.document {
+                Ok((document_id, mut data)) => {
+                    normalize_geo_points(&mut data, &fields);
+
+                    match schema.as_ref().map(|x| x.validate(&data)).unwrap_or(Ok(())) {
+                        Ok(_) => pending.push((record.line, BulkAction::Index { document_id, data })),
+                        Err(reason) => summary.reject(record.line, reason)
+                    }
+                },
+                Err(reason) => summary.reject(record.line, reason)
+            }
+        }
+
+        if pending.len() >= BULK_BATCH_SIZE {
+            if let Err(x) = flush_import(backend, index, &mut pending, &mut summary).await {
+                summary.error = Some(x);
+                break;
+            }
+        }
+
+        if chunk.is_empty() {
+            break;
+        }
+    }
+
+    // Documents parsed before a failure are still imported
+    if let Err(x) = flush_import(backend, index, &mut pending, &mut summary).await {
+        summary.error.get_or_insert(x);
+    }
+
+    // Indexes with a schema are strict from the start
+    if schema.is_none() {
+        let set_dynamic = json!({
+            "dynamic": "strict"
+        });
+
+        backend.update_index_mappings(index, set_dynamic).await?;
+    }
+
+    Ok(summary)
+}
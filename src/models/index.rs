@@ -1,115 +1,126 @@
-use actix_web::HttpResponse;
-use elasticsearch::{indices::{IndicesExistsParts, IndicesCreateParts, IndicesPutMappingParts, IndicesGetMappingParts, IndicesDeleteParts}, cat::CatIndicesParts};
+use elasticsearch::{indices::{IndicesExistsParts, IndicesCreateParts, IndicesPutMappingParts, IndicesDeleteParts}, cat::CatIndicesParts};
 use reqwest::StatusCode;
 use serde_json::{json, Value};
 
 use crate::models::ErrorTypes;
 
-use super::{EClient, helpers::{server_down_check, index_exists_check}};
+use super::{EClient, pool::TRACEPARENT, schema::{Schema, new_index_mappings}};
 
 
 
 impl EClient{
     /// Creates a new index
-    pub async fn create_index(&self, index: &str) -> HttpResponse{
+    ///
+    /// Fields are mapped from the schema when one is given, otherwise geo_fields are mapped as geo_point and other fields are mapped dynamically
+    #[tracing::instrument(skip_all, fields(index = index))]
+    pub async fn create_index(&self, index: &str, geo_fields: Vec<String>, schema: Option<Schema>) -> Result<(), ErrorTypes>{
 
-        match server_down_check(&self.elastic).await{
-            Ok(()) => (),
-            Err(x) => return x
-        };
+        let mappings = new_index_mappings(geo_fields, schema.as_ref())?;
+
+        self.health.ensure_reachable()?;
 
         // Check if index exists
         let exists = self.elastic
-            .indices()
-            .exists(IndicesExistsParts::Index(&[index]))
-            .send()
-            .await
-            .unwrap();
+            .send("exists", |es, traceparent| async move {
+                es.indices()
+                    .exists(IndicesExistsParts::Index(&[index]))
+                    .header(TRACEPARENT, traceparent)
+                    .send()
+                    .await
+            })
+            .await?;
 
         if exists.status_code().is_success() {
-            return HttpResponse::Conflict().json(json!({"error": ErrorTypes::IndexExists(index.to_string()).to_string()}));
+            return Err(ErrorTypes::IndexExists(index.to_string()));
         }
 
-        if exists.status_code() == StatusCode::NOT_FOUND {
-            let resp = self.elastic
-                .indices()
-                .create(IndicesCreateParts::Index(index))
-                .body(json!(
-                    {
-                      "mappings": { 	
-                        "dynamic":"true"
-                      },
-                      "settings": {
-                        "index.number_of_shards": 3,
-                        "index.number_of_replicas": 0,
-                      }
-                    }
-                ))
-                .send()
-                .await
-                .unwrap();
-    
-            if resp.status_code() == StatusCode::OK {
-                return HttpResponse::Created().finish();
+        if exists.status_code() != StatusCode::NOT_FOUND {
+            return Err(ErrorTypes::from_response(exists).await);
+        }
+
+        let body = &json!(
+            {
+              "mappings": mappings,
+              "settings": {
+                "index.number_of_shards": self.index_defaults.shards,
+                "index.number_of_replicas": self.index_defaults.replicas,
+              }
             }
-            return HttpResponse::build(resp.status_code()).finish();
+        );
+
+        let resp = self.elastic
+            .send_once("create_index", |es, traceparent| async move {
+                es.indices()
+                    .create(IndicesCreateParts::Index(index))
+                    .body(body)
+                    .header(TRACEPARENT, traceparent)
+                    .send()
+                    .await
+            })
+            .await?;
+
+        if !resp.status_code().is_success() {
+            return Err(ErrorTypes::from_response(resp).await);
         }
-    
-        HttpResponse::build(exists.status_code()).finish()
+
+        self.indices.invalidate(index);
+
+        Ok(())
     }
 
     // Updates the mappings of an index
-    pub async fn update_index_mappings(&self, index: &str, mappings: Value) -> HttpResponse{
+    //
+    // Changing only the dynamic mode, as writes to indexes without a schema do, is applied to the cached mappings, and skipped when they already have it
+    #[tracing::instrument(skip_all, fields(index = index))]
+    pub async fn update_index_mappings(&self, index: &str, mappings: Value) -> Result<(), ErrorTypes>{
 
-        match server_down_check(&self.elastic).await{
-            Ok(()) => (),
-            Err(x) => return x
-        };
+        self.health.ensure_reachable()?;
 
-        match index_exists_check(&self.elastic, index).await{
-            Ok(()) => (),
-            Err(x) => return x
-        };
+        let metadata = self.index_metadata(index).await?;
 
-        let resp = self.elastic
-            .indices()
-            .put_mapping(IndicesPutMappingParts::Index(&[index]))
-            .body(mappings)
-            .send()
-            .await
-            .unwrap();
+        let dynamic = mappings.as_object().filter(|x| x.len() == 1).and_then(|x| x.get("dynamic")).cloned();
 
-        let status_code = resp.status_code();
+        // Skipped only when this service set the same mode since the metadata was fetched, another instance may have changed a fetched mode
+        if dynamic.is_some() && dynamic == metadata.written_dynamic {
+            return Ok(());
+        }
 
-        if !status_code.is_success() {
-            let error = match status_code{
-                StatusCode::BAD_REQUEST => ErrorTypes::BadDataRequest.to_string(),
-                _ => ErrorTypes::Unknown.to_string()
-            };
-            return HttpResponse::build(status_code).json(json!({
-                "error": error
-            }));
+        let mappings = &mappings;
+
+        let resp = self.elastic
+            .send("put_mapping", |es, traceparent| async move {
+                es.indices()
+                    .put_mapping(IndicesPutMappingParts::Index(&[index]))
+                    .body(mappings)
+                    .header(TRACEPARENT, traceparent)
+                    .send()
+                    .await
+            })
+            .await?;
+
+        if !resp.status_code().is_success() {
+            return Err(ErrorTypes::from_response(resp).await);
         }
 
-        // println!("{:#?}", resp);
-        // println!("{:#?}", resp.json::<Value>().await.unwrap());
+        match dynamic {
+            Some(dynamic) => self.indices.update(index, |x| {
+                x.mappings["dynamic"] = dynamic.clone();
+                x.written_dynamic = Some(dynamic);
+            }),
+            None => self.indices.invalidate(index)
+        }
 
-        HttpResponse::build(status_code).finish()
+        Ok(())
     }
 
     /// Returns either a list of index if index is not supplied, or the specified index
-    pub async fn get_index(&self, index: Option<String>) -> HttpResponse{
+    #[tracing::instrument(skip_all, fields(index = index.as_deref()))]
+    pub async fn get_index(&self, index: Option<String>) -> Result<Value, ErrorTypes>{
 
-        match server_down_check(&self.elastic).await{
-            Ok(()) => (),
-            Err(x) => return x
-        };
+        self.health.ensure_reachable()?;
 
-        if index.is_some() {
-            match index_exists_check(&self.elastic, &index.clone().unwrap()).await{
-                Ok(()) => (),
-                Err(x) => return x
-            };
+        if let Some(index) = &index {
+            self.index_metadata(index).await?;
         }
 
         let idx = match index {
@@ -117,101 +128,63 @@ impl EClient{
             None => "*".to_string()
         };
 
-        let resp = self.elastic
-            .cat()
-            .indices(CatIndicesParts::Index(&[&idx]))
-            .format("json")
-            .send()
-            .await
-            .unwrap();
-
-        let status_code = resp.status_code();
+        let idx = idx.as_str();
 
-        if !status_code.is_success() {
-            return HttpResponse::build(status_code).json(json!({
-                "error": ErrorTypes::Unknown.to_string()
-            }));
+        let resp = self.elastic
+            .send("cat_indices", |es, traceparent| async move {
+                es.cat()
+                    .indices(CatIndicesParts::Index(&[idx]))
+                    .format("json")
+                    .header(TRACEPARENT, traceparent)
+                    .send()
+                    .await
+            })
+            .await?;
+
+        if !resp.status_code().is_success() {
+            return Err(ErrorTypes::from_response(resp).await);
         }
-        
-        let json_resp = resp.json::<Value>().await.unwrap();
 
-        HttpResponse::build(status_code).json(json_resp)
+        Ok(resp.json::<Value>().await?)
     }
 
     /// Returns the mappings of an index
-    pub async fn get_index_mappings(&self, index: String) -> HttpResponse{
-        match server_down_check(&self.elastic).await{
-            Ok(()) => (),
-            Err(x) => return x
-        };
-
-        match index_exists_check(&self.elastic, &index).await{
-            Ok(()) => (),
-            Err(x) => return x
-        };
+    #[tracing::instrument(skip_all, fields(index = index))]
+    pub async fn get_index_mappings(&self, index: &str) -> Result<Value, ErrorTypes>{
+        self.health.ensure_reachable()?;
 
-        let resp = self.elastic
-            .indices()
-            .get_mapping(IndicesGetMappingParts::Index(&[&index]))
-            .send()
-            .await
-            .unwrap();
-
-        let status_code = resp.status_code();
-
-        if !status_code.is_success() {
-            return HttpResponse::build(status_code).json(json!({
-                "error": ErrorTypes::Unknown.to_string()
-            }));
-        }
-
-        let json_resp = 
-            resp.json::<Value>()
-            .await
-            .unwrap();
-
-
-        let mappings = json_resp
-            .get(&index)
-            .unwrap()
-            .get("mappings")
-            .unwrap();
-
-        HttpResponse::build(status_code).json(mappings)
+        Ok(self.index_metadata(index).await?.mappings.clone())
     }
-    
+
     /// Deletes an index
-    pub async fn delete_index(&self, index: &str) -> HttpResponse{
-        match server_down_check(&self.elastic).await{
-            Ok(()) => (),
-            Err(x) => return x
-        };
+    #[tracing::instrument(skip_all, fields(index = index))]
+    pub async fn delete_index(&self, index: &str) -> Result<(), ErrorTypes>{
+        self.health.ensure_reachable()?;
 
-        match index_exists_check(&self.elastic, index).await{
-            Ok(()) => (),
-            Err(x) => return x
-        };
+        self.index_metadata(index).await?;
 
-        let resp = 
-            self.elastic
-            .indices()
-            .delete(IndicesDeleteParts::Index(&[index]))
-            .send()
-            .await;
+        let resp = self.elastic
+            .send("delete_index", |es, traceparent| async move {
+                es.indices()
+                    .delete(IndicesDeleteParts::Index(&[index]))
+                    .header(TRACEPARENT, traceparent)
+                    .send()
+                    .await
+            })
+            .await?;
 
-        let resp = resp.unwrap();
-    
         let status_code = resp.status_code();
 
         if !status_code.is_success(){
-            return HttpResponse::build(status_code).json(
-                    json!({
-                        "error": ErrorTypes::IndexNotFound(index.to_string()).to_string()
-                    })
-                );
+            return Err(match status_code{
+                StatusCode::NOT_FOUND => self.index_missing(index),
+                _ => ErrorTypes::from_response(resp).await
+            });
         }
 
-        HttpResponse::build(status_code).finish()
+        self.indices.invalidate(index);
+
+        Ok(())
     }
 
-}
\ No newline at end of file
+}
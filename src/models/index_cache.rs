@@ -0,0 +1,146 @@
+use std::{collections::HashMap, sync::{Arc, RwLock}, time::{Duration, Instant}};
+
+use elasticsearch::indices::IndicesGetParts;
+use reqwest::StatusCode;
+use serde_json::Value;
+
+use super::{EClient, ErrorTypes, pool::TRACEPARENT};
+
+/// Default of index.max_result_window
+const DEFAULT_MAX_RESULT_WINDOW: i64 = 10000;
+
+/// Mappings and settings of an index that exists
+#[derive(Debug, Clone)]
+pub struct IndexMetadata {
+    pub mappings: Value,
+    pub settings: Value,
+    /// Dynamic mode this service last set on the index since the metadata was fetched, None until it sets one
+    ///
+    /// The fetched mode may have been changed elsewhere since, only a mode set here is known to still hold
+    pub written_dynamic: Option<Value>
+}
+
+impl IndexMetadata {
+    /// Largest from + size of a search on the index
+    pub fn max_result_window(&self) -> i64 {
+        // Settings are returned as strings
+        self.settings["index"]["max_result_window"]
+            .as_str()
+            .and_then(|x| x.parse().ok())
+            .unwrap_or(DEFAULT_MAX_RESULT_WINDOW)
+    }
+}
+
+struct CacheEntry {
+    fetched: Instant,
+    /// None when the index does not exist
+    metadata: Option<Arc<IndexMetadata>>
+}
+
+/// Metadata of the indexes, fetched the first time it is needed and kept for the ttl
+///
+/// Changes made through this service drop or update the entry of the index right away, changes made elsewhere are seen once the ttl has passed
+pub struct IndexCache {
+    ttl: Duration,
+    entries: RwLock<HashMap<String, CacheEntry>>
+}
+
+impl IndexCache {
+    /// A ttl of zero fetches the metadata on every request
+    pub fn new(ttl: Duration) -> Self {
+        Self { ttl, entries: RwLock::default() }
+    }
+
+    /// The cached metadata of an index if it is not older than the ttl, Some(None) when the index was missing
+    pub fn get(&self, index: &str) -> Option<Option<Arc<IndexMetadata>>> {
+        self.entries
+            .read()
+            .unwrap()
+            .get(index)
+            .filter(|x| x.fetched.elapsed() < self.ttl)
+            .map(|x| x.metadata.clone())
+    }
+
+    pub fn insert(&self, index: &str, metadata: Option<Arc<IndexMetadata>>) {
+        let mut entries = self.entries.write().unwrap();
+
+        // Indexes that are no longer asked for are dropped along the way
+        entries.retain(|_, x| x.fetched.elapsed() < self.ttl);
+        entries.insert(index.to_string(), CacheEntry { fetched: Instant::now(), metadata });
+    }
+
+    /// Changes the cached metadata of an index in place, for changes made through this service that are known without fetching it again
+    pub fn update(&self, index: &str, f: impl FnOnce(&mut IndexMetadata)) {
+        if let Some(metadata) = self.entries.write().unwrap().get_mut(index).and_then(|x| x.metadata.as_mut()) {
+            f(Arc::make_mut(metadata));
+        }
+    }
+
+    /// Drops the metadata of an index, the next request fetches it again
+    pub fn invalidate(&self, index: &str) {
+        self.entries.write().unwrap().remove(index);
+    }
+}
+
+impl EClient {
+    /// Metadata of an index, from the cache when it is fresh, IndexNotFound if the index does not exist
+    pub async fn index_metadata(&self, index: &str) -> Result<Arc<IndexMetadata>, ErrorTypes> {
+        let metadata = match self.indices.get(index) {
+            Some(x) => x,
+            None => {
+                let x = self.fetch_index_metadata(index).await?;
+                self.indices.insert(index, x.clone());
+                x
+            }
+        };
+
+        metadata.ok_or_else(|| ErrorTypes::IndexNotFound(index.to_string()))
+    }
+
+    /// IndexNotFound for an index Elasticsearch no longer finds, its cached metadata is dropped as it was deleted since
+    pub fn index_missing(&self, index: &str) -> ErrorTypes {
+        self.indices.invalidate(index);
+        ErrorTypes::IndexNotFound(index.to_string())
+    }
+
+    /// Existence, mappings and settings of an index in a single request, None if the index does not exist
+    #[tracing::instrument(skip_all, fields(index = index))]
+    async fn fetch_index_metadata(&self, index: &str) -> Result<Option<Arc<IndexMetadata>>, ErrorTypes> {
+        let resp = self.elastic
+            .send("get_index", |es, traceparent| async move {
+                es.indices()
+                    .get(IndicesGetParts::Index(&[index]))
+                    .header(TRACEPARENT, traceparent)
+                    .send()
+                    .await
+            })
+            .await?;
+
+        let status_code = resp.status_code();
+
+        if status_code == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        if !status_code.is_success() {
+            return Err(ErrorTypes::from_response(resp).await);
+        }
+
+        let mut json_resp = resp.json::<Value>().await?;
+
+        // Keyed by the name of the index, which differs from the one asked for with an alias, an alias over several indexes is not a single index
+        let mut index_resp = match json_resp.get_mut(index) {
+            Some(x) => x.take(),
+            None => match json_resp.as_object_mut() {
+                Some(x) if x.len() == 1 => x.values_mut().next().map(Value::take).unwrap_or_default(),
+                _ => return Err(ErrorTypes::BadDataRequest(format!("[{}] does not name a single index", index)))
+            }
+        };
+
+        Ok(Some(Arc::new(IndexMetadata {
+            mappings: index_resp["mappings"].take(),
+            settings: index_resp["settings"].take(),
+            written_dynamic: None
+        })))
+    }
+}
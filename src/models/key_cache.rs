@@ -0,0 +1,63 @@
+use std::{collections::HashMap, sync::RwLock, time::{Duration, Instant}};
+
+use super::{EClient, ErrorTypes, api_keys::StoredApiKey};
+
+/// API keys that authenticated requests, fetched the first time a key is used and kept for the ttl
+///
+/// Keys created, rotated or deleted through this service are updated right away, changes made through another instance are seen once the ttl has passed.
+/// Missing keys are not kept, so that unknown ids can not fill the cache
+pub struct ApiKeyCache {
+    ttl: Duration,
+    entries: RwLock<HashMap<String, (Instant, StoredApiKey)>>
+}
+
+impl ApiKeyCache {
+    /// A ttl of zero fetches the key on every request
+    pub fn new(ttl: Duration) -> Self {
+        Self { ttl, entries: RwLock::default() }
+    }
+
+    /// The cached key if it is not older than the ttl
+    pub fn get(&self, id: &str) -> Option<StoredApiKey> {
+        self.entries
+            .read()
+            .unwrap()
+            .get(id)
+            .filter(|(fetched, _)| fetched.elapsed() < self.ttl)
+            .map(|(_, key)| key.clone())
+    }
+
+    pub fn insert(&self, key: StoredApiKey) {
+        if self.ttl.is_zero() {
+            return;
+        }
+
+        let mut entries = self.entries.write().unwrap();
+
+        // Keys that are no longer used are dropped along the way
+        entries.retain(|_, (fetched, _)| fetched.elapsed() < self.ttl);
+        entries.insert(key.key.id.clone(), (Instant::now(), key));
+    }
+
+    /// Drops a key, the next request using it fetches it again
+    pub fn invalidate(&self, id: &str) {
+        self.entries.write().unwrap().remove(id);
+    }
+}
+
+impl EClient {
+    /// An API key to authenticate a request with, from the cache when it is fresh
+    pub async fn resolve_api_key(&self, id: &str) -> Result<Option<StoredApiKey>, ErrorTypes> {
+        if let Some(key) = self.api_keys.get(id) {
+            return Ok(Some(key));
+        }
+
+        let key = self.get_api_key(id).await?;
+
+        if let Some(key) = &key {
+            self.api_keys.insert(key.clone());
+        }
+
+        Ok(key)
+    }
+}
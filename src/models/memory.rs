@@ -0,0 +1,1054 @@
+use std::{cmp::Ordering, collections::{BTreeMap, HashMap}, ops::Bound, sync::{Arc, RwLock}};
+
+use actix_web::web::Bytes;
+use async_trait::async_trait;
+use futures_util::stream::{self, BoxStream};
+use serde_json::{json, Map, Value};
+
+use super::{
+    ErrorTypes,
+    backend::SearchBackend,
+    documents::{BulkAction, BulkItemResult, BulkSummary, SearchQuery, SearchResult, FacetHit, FacetSearchResult, BULK_BATCH_SIZE, split_fields},
+    export::{ExportFormat, ExportWriter, EXPORT_PAGE_SIZE, export_fields},
+    filters::{parse_search_filters, FilterExpr, Condition, FilterValue, RangeOp},
+    geo::{GeoSearch, normalize_geo_points},
+    helpers::{mapping_field_types, mapping_document_fields, is_numeric_type, exact_value_field},
+    highlight::Highlight,
+    pool::NodeHealth,
+    health::{ClusterHealth, HealthState},
+    schema::{Schema, new_index_mappings, write_schema},
+    api_keys::{ApiKey, StoredApiKey, now},
+    sort::{parse_sort, SortEntry, SortOrder}
+};
+
+/*
+In-memory backend, lets the HTTP API run without Elasticsearch
+
+Follows the semantics of Elasticsearch that the API relies on:
+    dynamic mappings (true, false, strict) with type checks of mapped fields
+    search terms matched as lowercase words, with at least 75% of the words matching
+    filters, field sorting, facets and from / count pagination
+
+Geo search, highlighting and cursors are not supported
+*/
+
+/// An index and its documents, ordered by id
+struct MemoryIndex {
+    mappings: Value,
+    documents: BTreeMap<String, Value>,
+    /// Id given to the next document inserted without one
+    next_id: u64
+}
+
+impl MemoryIndex {
+    fn generate_id(&mut self) -> String {
+        self.next_id += 1;
+        format!("{:020}", self.next_id)
+    }
+
+    /// Checks a document against the mappings, new fields are mapped according to the dynamic mode of the index
+    fn map_document(&mut self, document: &Value) -> Result<(), String> {
+        let Some(object) = document.as_object() else {
+            return Err("failed to parse, document must be an object".to_string());
+        };
+
+        let dynamic = match &self.mappings["dynamic"] {
+            Value::String(x) => x.to_string(),
+            Value::Bool(x) => x.to_string(),
+            _ => "true".to_string()
+        };
+
+        // Mappings are only updated once the whole document is valid
+        let mut mappings = self.mappings.clone();
+        if !mappings["properties"].is_object() {
+            mappings["properties"] = json!({});
+        }
+
+        map_object(mappings["properties"].as_object_mut().unwrap(), object, "", &dynamic)?;
+        self.mappings = mappings;
+        Ok(())
+    }
+}
+
+/// Returns the mapping of a new field, guessed from its value like Elasticsearch dynamic mappings
+fn infer_property(value: &Value) -> Option<Value> {
+    match value {
+        Value::String(_) => Some(json!({
+            "type": "text",
+            "fields": {
+                "keyword": { "type": "keyword", "ignore_above": 256 }
+            }
+        })),
+        Value::Number(x) if x.is_f64() => Some(json!({ "type": "float" })),
+        Value::Number(_) => Some(json!({ "type": "long" })),
+        Value::Bool(_) => Some(json!({ "type": "boolean" })),
+        Value::Object(_) => Some(json!({ "properties": {} })),
+        Value::Array(values) => values.iter().find_map(infer_property),
+        Value::Null => None
+    }
+}
+
+fn map_object(properties: &mut Map<String, Value>, object: &Map<String, Value>, prefix: &str, dynamic: &str) -> Result<(), String> {
+    for (name, value) in object {
+        let path = format!("{}{}", prefix, name);
+
+        if value.is_null() {
+            continue;
+        }
+
+        match properties.get_mut(name) {
+            Some(property) => check_value(property, value, &path, dynamic)?,
+            None => match dynamic {
+                "strict" => return Err(format!("mapping set to strict, dynamic introduction of [{}] within [_doc] is not allowed", name)),
+                "false" => (),
+                _ => if let Some(mut property) = infer_property(value) {
+                    check_value(&mut property, value, &path, dynamic)?;
+                    properties.insert(name.to_string(), property);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks a value against the mapping of its field
+fn check_value(property: &mut Value, value: &Value, path: &str, dynamic: &str) -> Result<(), String> {
+    let invalid = |field_type: &str| format!("failed to parse field [{}] of type [{}]", path, field_type);
+
+    if let Value::Array(values) = value {
+        // Geo points may be written as [lon, lat]
+        if property["type"] == "geo_point" && values.len() == 2 && values.iter().all(|x| x.is_number()) {
+            return Ok(());
+        }
+        return values.iter().try_for_each(|x| check_value(property, x, path, dynamic));
+    }
+
+    if value.is_null() {
+        return Ok(());
+    }
+
+    match property["type"].as_str() {
+        None | Some("object") | Some("nested") => match value {
+            Value::Object(object) => {
+                if !property["properties"].is_object() {
+                    property["properties"] = json!({});
+                }
+                map_object(property["properties"].as_object_mut().unwrap(), object, &format!("{}.", path), dynamic)
+            },
+            _ => Err(format!("object mapping for [{}] tried to parse field [{}] as object, but found a concrete value", path, path))
+        },
+        Some("geo_point") => match value {
+            Value::Object(x) if x.contains_key("lat") && x.contains_key("lon") => Ok(()),
+            Value::String(_) => Ok(()),
+            _ => Err(invalid("geo_point"))
+        },
+        Some(field_type) if is_numeric_type(field_type) => match value {
+            Value::Number(_) => Ok(()),
+            Value::String(x) if x.trim().parse::<f64>().is_ok() => Ok(()),
+            _ => Err(invalid(field_type))
+        },
+        Some("boolean") => match value {
+            Value::Bool(_) => Ok(()),
+            Value::String(x) if x == "true" || x == "false" => Ok(()),
+            _ => Err(invalid("boolean"))
+        },
+        Some(field_type) => match value {
+            Value::Object(_) => Err(invalid(field_type)),
+            _ => Ok(())
+        }
+    }
+}
+
+/// Merges mappings like the put mapping api, the type of an existing field can not be changed
+fn merge_properties(current: &mut Map<String, Value>, update: &Map<String, Value>) -> Result<(), String> {
+    for (name, property) in update {
+        let Some(existing) = current.get_mut(name) else {
+            current.insert(name.to_string(), property.clone());
+            continue;
+        };
+
+        let (current_type, new_type) = (existing["type"].as_str().unwrap_or("object"), property["type"].as_str());
+        if let Some(new_type) = new_type.filter(|x| *x != current_type) {
+            return Err(format!("mapper [{}] cannot be changed from type [{}] to [{}]", name, current_type, new_type));
+        }
+
+        for key in ["properties", "fields"] {
+            if let Some(update) = property[key].as_object() {
+                if !existing[key].is_object() {
+                    existing[key] = json!({});
+                }
+                merge_properties(existing[key].as_object_mut().unwrap(), update)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Deep merges a partial document into a document, like partial updates
+fn merge_document(document: &mut Value, update: &Value) {
+    match (document, update) {
+        (Value::Object(document), Value::Object(update)) => {
+            for (name, value) in update {
+                match document.get_mut(name) {
+                    Some(x) if x.is_object() && value.is_object() => merge_document(x, value),
+                    _ => {
+                        document.insert(name.to_string(), value.clone());
+                    }
+                }
+            }
+        },
+        (document, update) => *document = update.clone()
+    }
+}
+
+/// Lowercase words of a text, split on anything that is not alphanumeric
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric()).filter(|x| !x.is_empty()).map(|x| x.to_lowercase()).collect()
+}
+
+/// Returns true if a field matches a field pattern (ex: "*", "address.*", "name")
+fn field_matches(pattern: &str, field: &str) -> bool {
+    let pattern = pattern.strip_suffix(".keyword").unwrap_or(pattern);
+
+    match pattern.strip_suffix('*') {
+        Some(prefix) => field.starts_with(prefix),
+        None => field == pattern || field.starts_with(&format!("{}.", pattern))
+    }
+}
+
+/// Flattens a document into its leaf fields joined with dots, values of arrays are listed under the same field
+fn leaf_values<'a>(value: &'a Value, path: &str, leaves: &mut Vec<(String, &'a Value)>) {
+    match value {
+        Value::Object(object) => {
+            for (name, value) in object {
+                let path = match path.is_empty() {
+                    true => name.to_string(),
+                    false => format!("{}.{}", path, name)
+                };
+                leaf_values(value, &path, leaves);
+            }
+        },
+        Value::Array(values) => values.iter().for_each(|x| leaf_values(x, path, leaves)),
+        Value::Null => (),
+        x => leaves.push((path.to_string(), x))
+    }
+}
+
+/// Returns the values of a dotted field, going through arrays
+fn field_values<'a>(document: &'a Value, field: &str) -> Vec<&'a Value> {
+    let field = field.strip_suffix(".keyword").unwrap_or(field);
+    let mut values = vec![document];
+
+    for name in field.split('.') {
+        values = values
+            .into_iter()
+            .filter_map(|x| x.get(name))
+            .flat_map(|x| match x {
+                Value::Array(x) => x.iter().collect(),
+                x => vec![x]
+            })
+            .collect();
+    }
+
+    values.into_iter().filter(|x| !x.is_null()).collect()
+}
+
+/// Keeps the fields of a document matching any of the patterns
+fn filter_source(document: &Value, patterns: &[String], path: &str) -> Value {
+    let Some(object) = document.as_object() else {
+        return document.clone();
+    };
+
+    let mut filtered = Map::new();
+    for (name, value) in object {
+        let field = match path.is_empty() {
+            true => name.to_string(),
+            false => format!("{}.{}", path, name)
+        };
+
+        if patterns.iter().any(|x| field_matches(x, &field)) {
+            filtered.insert(name.to_string(), value.clone());
+        } else if value.is_object() && patterns.iter().any(|x| x.starts_with(&format!("{}.", field))) {
+            let value = filter_source(value, patterns, &field);
+            if value.as_object().map(|x| !x.is_empty()).unwrap_or(false) {
+                filtered.insert(name.to_string(), value);
+            }
+        }
+    }
+
+    Value::Object(filtered)
+}
+
+fn as_number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(x) => x.as_f64(),
+        Value::String(x) => x.trim().parse().ok(),
+        _ => None
+    }
+}
+
+/// Compares a document value with the value of a filter
+fn compare_filter_value(value: &Value, filter_value: &FilterValue) -> Option<Ordering> {
+    match filter_value {
+        // Strings are compared as written first, as the keyword subfield is, and integers exactly
+        FilterValue::Number(x) => match value {
+            Value::String(value) if value == x => Some(Ordering::Equal),
+            value => match (value.as_i64(), x.parse::<i64>()) {
+                (Some(value), Ok(x)) => Some(value.cmp(&x)),
+                _ => as_number(value)?.partial_cmp(&x.parse().ok()?)
+            }
+        },
+        FilterValue::String(x) => match value {
+            Value::String(value) => Some(value.as_str().cmp(x)),
+            value => as_number(value)?.partial_cmp(&x.parse::<f64>().ok()?)
+        },
+        FilterValue::Bool(x) => value.as_bool().map(|value| value.cmp(x))
+    }
+}
+
+fn condition_matches(condition: &Condition, document: &Value) -> bool {
+    let equals = |value: &Value, filter_value: &FilterValue| compare_filter_value(value, filter_value) == Some(Ordering::Equal);
+
+    match condition {
+        Condition::Eq(field, filter_value) => field_values(document, field).iter().any(|x| equals(x, filter_value)),
+        Condition::Ne(field, filter_value) => !field_values(document, field).iter().any(|x| equals(x, filter_value)),
+        Condition::In(field, filter_values) => field_values(document, field).iter().any(|x| filter_values.iter().any(|y| equals(x, y))),
+        Condition::Range{ field, op, value } => field_values(document, field).iter().any(|x| {
+            match compare_filter_value(x, value) {
+                Some(ordering) => match op {
+                    RangeOp::Gt => ordering.is_gt(),
+                    RangeOp::Gte => ordering.is_ge(),
+                    RangeOp::Lt => ordering.is_lt(),
+                    RangeOp::Lte => ordering.is_le()
+                },
+                None => false
+            }
+        }),
+        Condition::Between{ field, from, to } => field_values(document, field).iter().any(|x| {
+            compare_filter_value(x, from).map(|x| x.is_ge()).unwrap_or(false) && compare_filter_value(x, to).map(|x| x.is_le()).unwrap_or(false)
+        })
+    }
+}
+
+fn filter_matches(expression: &FilterExpr, document: &Value) -> bool {
+    match expression {
+        FilterExpr::And(expressions) => expressions.iter().all(|x| filter_matches(x, document)),
+        FilterExpr::Or(expressions) => expressions.iter().any(|x| filter_matches(x, document)),
+        FilterExpr::Not(expression) => !filter_matches(expression, document),
+        FilterExpr::Condition(condition) => condition_matches(condition, document)
+    }
+}
+
+/// Orders two values of a sort, numbers are compared as numbers
+fn compare_values(a: &Value, b: &Value) -> Ordering {
+    match (as_number(a).filter(|_| !a.is_string()), as_number(b).filter(|_| !b.is_string())) {
+        (Some(a), Some(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+        _ => match (a, b) {
+            (Value::String(a), Value::String(b)) => a.cmp(b),
+            (Value::Bool(a), Value::Bool(b)) => a.cmp(b),
+            (a, b) => a.to_string().cmp(&b.to_string())
+        }
+    }
+}
+
+/// Key of a facet value, as Elasticsearch returns it
+fn facet_key(value: &Value) -> String {
+    match value {
+        Value::String(x) => x.to_string(),
+        x => x.to_string()
+    }
+}
+
+/// Returns true if a word of the value starts with the facet query, ignoring case
+fn facet_query_matches(value: &str, facet_query: &str) -> bool {
+    let value = value.to_lowercase();
+    let facet_query = facet_query.to_lowercase();
+    let mut previous: Option<char> = None;
+
+    for (position, c) in value.char_indices() {
+        if previous.map(|x| !x.is_ascii_alphanumeric()).unwrap_or(true) && value[position..].starts_with(&facet_query) {
+            return true;
+        }
+        previous = Some(c);
+    }
+
+    false
+}
+
+fn unsupported(feature: &str) -> ErrorTypes {
+    ErrorTypes::BadDataRequest(format!("{} is not supported by the in-memory backend", feature))
+}
+
+/// The in-memory backend is a single node that is always green
+fn memory_health() -> ClusterHealth {
+    ClusterHealth {
+        cluster_name: "memory".to_string(),
+        status: "green".to_string(),
+        number_of_nodes: 1,
+        number_of_data_nodes: 1,
+        unassigned_shards: 0,
+        pending_tasks: 0,
+        active_shards_percent: 100.0
+    }
+}
+
+/// Search backend keeping every index in memory, used to run the HTTP API in tests
+#[derive(Default)]
+pub struct InMemoryBackend {
+    indices: RwLock<HashMap<String, MemoryIndex>>,
+    api_keys: RwLock<BTreeMap<String, StoredApiKey>>
+}
+
+impl InMemoryBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs a function on an index, IndexNotFound if it does not exist
+    fn with_index<T>(&self, index: &str, f: impl FnOnce(&mut MemoryIndex) -> Result<T, ErrorTypes>) -> Result<T, ErrorTypes> {
+        let mut indices = self.indices.write().unwrap();
+        match indices.get_mut(index) {
+            Some(x) => f(x),
+            None => Err(ErrorTypes::IndexNotFound(index.to_string()))
+        }
+    }
+
+    fn mappings(&self, index: &str) -> Result<Value, ErrorTypes> {
+        self.with_index(index, |x| Ok(x.mappings.clone()))
+    }
+
+    /// Returns the documents matching the search term and filters, with their score
+    fn matching_documents(&self, index: &str, search_term: Option<&str>, search_in: Option<&str>, filters: Option<&FilterExpr>) -> Result<Vec<(String, f64, Value)>, ErrorTypes> {
+        let terms = search_term.map(words).unwrap_or_default();
+        let search_in = search_in.map(split_fields).unwrap_or_else(|| vec!["*".to_string()]);
+
+        // Like minimum_should_match 75%, rounded down
+        let required = (terms.len() * 3 / 4).max(1);
+
+        self.with_index(index, |x| {
+            let mut documents = Vec::new();
+
+            for (id, document) in &x.documents {
+                if !filters.map(|x| filter_matches(x, document)).unwrap_or(true) {
+                    continue;
+                }
+
+                if terms.is_empty() {
+                    documents.push((id.to_string(), 1.0, document.clone()));
+                    continue;
+                }
+
+                let mut leaves = Vec::new();
+                leaf_values(document, "", &mut leaves);
+
+                let document_words: Vec<String> = leaves
+                    .iter()
+                    .filter(|(field, _)| search_in.iter().any(|x| field_matches(x, field)))
+                    .flat_map(|(_, value)| match value {
+                        Value::String(x) => words(x),
+                        x => words(&x.to_string())
+                    })
+                    .collect();
+
+                let matched = terms.iter().filter(|x| document_words.contains(x)).count();
+                if matched >= required {
+                    documents.push((id.to_string(), matched as f64, document.clone()));
+                }
+            }
+
+            Ok(documents)
+        })
+    }
+
+    /// Runs a single bulk operation, returns its status with either its result or the reason of the failure
+    fn run_bulk_action(index: &mut MemoryIndex, action: BulkAction) -> (Option<String>, u16, Result<&'static str, String>) {
+        match action {
+            BulkAction::Index { document_id: None, data } => {
+                let id = index.generate_id();
+                match index.map_document(&data) {
+                    Ok(()) => {
+                        index.documents.insert(id.clone(), data);
+                        (Some(id), 201, Ok("created"))
+                    },
+                    Err(x) => (None, 400, Err(x))
+                }
+            },
+            BulkAction::Index { document_id: Some(id), data } => {
+                if let Err(x) = index.map_document(&data) {
+                    return (Some(id), 400, Err(x));
+                }
+                match index.documents.insert(id.clone(), data) {
+                    Some(_) => (Some(id), 200, Ok("updated")),
+                    None => (Some(id), 201, Ok("created"))
+                }
+            },
+            BulkAction::Create { document_id: id, data } => {
+                if index.documents.contains_key(&id) {
+                    let reason = format!("[{}]: version conflict, document already exists", id);
+                    return (Some(id), 409, Err(reason));
+                }
+                if let Err(x) = index.map_document(&data) {
+                    return (Some(id), 400, Err(x));
+                }
+                index.documents.insert(id.clone(), data);
+                (Some(id), 201, Ok("created"))
+            },
+            BulkAction::Update { document_id, data } => {
+                let Some(mut document) = index.documents.get(&document_id).cloned() else {
+                    let reason = format!("[{}]: document missing", document_id);
+                    return (Some(document_id), 404, Err(reason));
+                };
+
+                merge_document(&mut document, &data);
+
+                if index.documents.get(&document_id) == Some(&document) {
+                    return (Some(document_id), 200, Ok("noop"));
+                }
+                if let Err(x) = index.map_document(&document) {
+                    return (Some(document_id), 400, Err(x));
+                }
+                index.documents.insert(document_id.clone(), document);
+                (Some(document_id), 200, Ok("updated"))
+            },
+            BulkAction::Delete { document_id } => match index.documents.remove(&document_id) {
+                Some(_) => (Some(document_id), 200, Ok("deleted")),
+                None => (Some(document_id), 404, Ok("not_found"))
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl SearchBackend for InMemoryBackend {
+    async fn create_index(&self, index: &str, geo_fields: Vec<String>, schema: Option<Schema>) -> Result<(), ErrorTypes> {
+        if index.is_empty() || index != index.to_lowercase() || index.starts_with(['_', '-', '+']) {
+            return Err(ErrorTypes::BadDataRequest(format!("Invalid index name [{}], must be lowercase and may not start with '_', '-', '+'", index)));
+        }
+
+        let mappings = new_index_mappings(geo_fields, schema.as_ref())?;
+
+        let mut indices = self.indices.write().unwrap();
+
+        if indices.contains_key(index) {
+            return Err(ErrorTypes::IndexExists(index.to_string()));
+        }
+
+        indices.insert(index.to_string(), MemoryIndex {
+            mappings,
+            documents: BTreeMap::new(),
+            next_id: 0
+        });
+
+        Ok(())
+    }
+
+    async fn update_index_mappings(&self, index: &str, mappings: Value) -> Result<(), ErrorTypes> {
+        let Some(update) = mappings.as_object() else {
+            return Err(ErrorTypes::BadDataRequest("mappings must be an object".to_string()));
+        };
+
+        self.with_index(index, |x| {
+            let mut current = x.mappings.clone();
+
+            for (key, value) in update {
+                match (key.as_str(), value.as_object()) {
+                    ("properties", Some(properties)) => {
+                        if !current["properties"].is_object() {
+                            current["properties"] = json!({});
+                        }
+                        merge_properties(current["properties"].as_object_mut().unwrap(), properties).map_err(ErrorTypes::BadDataRequest)?;
+                    },
+                    ("properties", None) => return Err(ErrorTypes::BadDataRequest("properties must be an object".to_string())),
+                    _ => current[key] = value.clone()
+                }
+            }
+
+            x.mappings = current;
+            Ok(())
+        })
+    }
+
+    async fn get_index(&self, index: Option<String>) -> Result<Value, ErrorTypes> {
+        let indices = self.indices.read().unwrap();
+
+        if let Some(index) = &index {
+            if !indices.contains_key(index) {
+                return Err(ErrorTypes::IndexNotFound(index.to_string()));
+            }
+        }
+
+        let mut names: Vec<&String> = indices.keys().filter(|x| index.as_ref().map(|index| index == *x).unwrap_or(true)).collect();
+        names.sort();
+
+        Ok(Value::Array(names.into_iter().map(|name| json!({
+            "health": "green",
+            "status": "open",
+            "index": name,
+            "pri": "1",
+            "rep": "0",
+            "docs.count": indices[name].documents.len().to_string()
+        })).collect()))
+    }
+
+    async fn get_index_mappings(&self, index: &str) -> Result<Value, ErrorTypes> {
+        self.mappings(index)
+    }
+
+    async fn delete_index(&self, index: &str) -> Result<(), ErrorTypes> {
+        match self.indices.write().unwrap().remove(index) {
+            Some(_) => Ok(()),
+            None => Err(ErrorTypes::IndexNotFound(index.to_string()))
+        }
+    }
+
+    async fn insert_document(&self, index: &str, mut data: Value, dynamic_mode: Option<String>) -> Result<String, ErrorTypes> {
+        let mappings = self.mappings(index)?;
+        normalize_geo_points(&mut data, &mapping_field_types(&mappings));
+
+        let schema = write_schema(&mappings, dynamic_mode.as_deref())?;
+
+        if let Some(schema) = &schema {
+            schema.validate(&data).map_err(ErrorTypes::InvalidDocument)?;
+        }
+
+        if let Some(mode) = dynamic_mode {
+            self.update_index_mappings(index, json!({"dynamic": mode})).await?;
+        }
+
+        let document_id = self.with_index(index, |x| {
+            x.map_document(&data).map_err(ErrorTypes::BadDataRequest)?;
+            let id = x.generate_id();
+            x.documents.insert(id.clone(), data);
+            Ok(id)
+        })?;
+
+        if schema.is_none() {
+            self.update_index_mappings(index, json!({"dynamic": "strict"})).await?;
+        }
+
+        Ok(document_id)
+    }
+
+    async fn bulk_documents(&self, index: &str, mut actions: Vec<BulkAction>, dynamic_mode: Option<String>) -> Result<BulkSummary, ErrorTypes> {
+        let mappings = self.mappings(index)?;
+        let fields = mapping_field_types(&mappings);
+
+        for action in actions.iter_mut() {
+            match action {
+                BulkAction::Index { data, .. } | BulkAction::Create { data, .. } | BulkAction::Update { data, .. } => normalize_geo_points(data, &fields),
+                BulkAction::Delete { .. } => ()
+            }
+        }
+
+        let schema = write_schema(&mappings, dynamic_mode.as_deref())?;
+
+        if let Some(schema) = &schema {
+            schema.validate_bulk(&actions)?;
+        }
+
+        if let Some(mode) = dynamic_mode {
+            self.update_index_mappings(index, json!({"dynamic": mode})).await?;
+        }
+
+        let total = actions.len();
+        let mut items = Vec::with_capacity(total);
+        let mut actions = actions.into_iter().peekable();
+
+        while actions.peek().is_some() {
+            let batch: Vec<BulkAction> = actions.by_ref().take(BULK_BATCH_SIZE).collect();
+            for mut result in self.send_bulk_batch(index, batch).await?.1 {
+                result.position = items.len();
+                items.push(result);
+            }
+        }
+
+        if schema.is_none() {
+            self.update_index_mappings(index, json!({"dynamic": "strict"})).await?;
+        }
+
+        let failed = items.iter().filter(|x: &&BulkItemResult| x.error.is_some()).count();
+
+        Ok(BulkSummary {
+            took: 0,
+            total,
+            successful: total - failed,
+            failed,
+            items
+        })
+    }
+
+    async fn send_bulk_batch(&self, index: &str, batch: Vec<BulkAction>) -> Result<(i64, Vec<BulkItemResult>), ErrorTypes> {
+        self.with_index(index, |x| {
+            let results = batch.into_iter().enumerate().map(|(position, action)| {
+                let name = action.name();
+                let (document_id, status, result) = Self::run_bulk_action(x, action);
+
+                BulkItemResult {
+                    position,
+                    action: name,
+                    document_id,
+                    status,
+                    error: result.as_ref().err().cloned(),
+                    result: result.ok().map(|x| x.to_string())
+                }
+            }).collect();
+
+            Ok((0, results))
+        })
+    }
+
+    async fn search_index(&self, index: &str, query: SearchQuery) -> Result<SearchResult, ErrorTypes> {
+        let filters = parse_search_filters(query.filters.as_deref(), query.secured_filters.as_deref())?;
+
+        let max_values = query.max_values_per_facet()? as usize;
+        let (from_page, count) = query.page(20)?;
+
+        if GeoSearch::new(query.geo_field, query.around_lat_lng, query.around_radius, query.inside_bounding_box, query.inside_polygon)?.is_some() {
+            return Err(unsupported("Geo search"));
+        }
+
+        if Highlight::new(query.highlight_fields, query.snippet_fields, query.highlight_pre_tag, query.highlight_post_tag).is_some() {
+            return Err(unsupported("Highlighting"));
+        }
+
+        if query.cursor.is_some() {
+            return Err(unsupported("Cursor pagination"));
+        }
+
+        let mappings = self.mappings(index)?;
+        let fields = mapping_field_types(&mappings);
+
+        let mut documents = self.matching_documents(index, query.search_term.as_deref(), query.search_in.as_deref(), filters.as_ref())?;
+
+        // Facets are counted over every matching document
+        let facets = query.facets.as_deref().map(split_fields).unwrap_or_default();
+        let mut facet_results = Map::new();
+
+        for facet in &facets {
+            let field_type = fields.get(facet).ok_or_else(|| ErrorTypes::FieldNotFound(facet.to_string()))?;
+            if exact_value_field(&fields, facet).is_none() {
+                return Err(ErrorTypes::FieldNotFacetable(facet.to_string()));
+            }
+
+            let mut counts: HashMap<String, i64> = HashMap::new();
+            let mut numbers = Vec::new();
+
+            for (_, _, document) in &documents {
+                let mut keys: Vec<String> = field_values(document, facet).into_iter().map(|x| {
+                    numbers.extend(as_number(x));
+                    facet_key(x)
+                }).collect();
+                keys.sort();
+                keys.dedup();
+                keys.into_iter().for_each(|x| *counts.entry(x).or_default() += 1);
+            }
+
+            let mut counts: Vec<(String, i64)> = counts.into_iter().collect();
+            counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+            counts.truncate(max_values);
+
+            let mut facet_result = json!({
+                "values": counts.into_iter().map(|(key, count)| (key, json!(count))).collect::<Map<String, Value>>()
+            });
+
+            if is_numeric_type(field_type) {
+                facet_result["stats"] = match numbers.is_empty() {
+                    true => json!({ "min": null, "max": null, "avg": null }),
+                    false => json!({
+                        "min": numbers.iter().cloned().fold(f64::INFINITY, f64::min),
+                        "max": numbers.iter().cloned().fold(f64::NEG_INFINITY, f64::max),
+                        "avg": numbers.iter().sum::<f64>() / numbers.len() as f64
+                    })
+                };
+            }
+
+            facet_results.insert(facet.to_string(), facet_result);
+        }
+
+        let sort = match query.sort.as_deref().filter(|x| !x.trim().is_empty()) {
+            Some(x) => parse_sort(x, &mappings)?,
+            None => Vec::new()
+        };
+
+        for entry in &sort {
+            match entry {
+                SortEntry::Score(_) => (),
+                SortEntry::Field(field, _) => {
+                    if !fields.contains_key(field) {
+                        return Err(ErrorTypes::InvalidSort(format!("field [{}] not found", field)));
+                    }
+                    if exact_value_field(&fields, field).is_none() {
+                        return Err(ErrorTypes::InvalidSort(format!("field [{}] can not be sorted, text fields require a keyword subfield", field)));
+                    }
+                },
+                SortEntry::Distance(_) | SortEntry::GeoDistance{ .. } => return Err(unsupported("Sorting by distance"))
+            }
+        }
+
+        let sort_values = |score: f64, document: &Value| -> Vec<Value> {
+            sort.iter().map(|entry| match entry {
+                SortEntry::Field(field, _) => field_values(document, field).first().map(|x| (*x).clone()).unwrap_or(Value::Null),
+                _ => json!(score)
+            }).collect()
+        };
+
+        match sort.is_empty() {
+            true => documents.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal).then_with(|| a.0.cmp(&b.0))),
+            false => documents.sort_by(|a, b| {
+                let (a_values, b_values) = (sort_values(a.1, &a.2), sort_values(b.1, &b.2));
+                for ((entry, a), b) in sort.iter().zip(&a_values).zip(&b_values) {
+                    let order = match entry {
+                        SortEntry::Score(x) | SortEntry::Field(_, x) => *x,
+                        _ => SortOrder::Asc
+                    };
+
+                    // Missing values are sorted last in both orders
+                    let ordering = match (a.is_null(), b.is_null()) {
+                        (true, true) => Ordering::Equal,
+                        (true, false) => return Ordering::Greater,
+                        (false, true) => return Ordering::Less,
+                        (false, false) => compare_values(a, b)
+                    };
+
+                    let ordering = match order {
+                        SortOrder::Asc => ordering,
+                        SortOrder::Desc => ordering.reverse()
+                    };
+
+                    if ordering != Ordering::Equal {
+                        return ordering;
+                    }
+                }
+                a.0.cmp(&b.0)
+            })
+        };
+
+        let return_fields = query.return_fields.as_deref().map(split_fields).unwrap_or_else(|| vec!["*".to_string()]);
+        let total = documents.len();
+
+        let data = documents.into_iter().skip(from_page as usize).take(count as usize).map(|(id, score, document)| {
+            let mut leaves = Vec::new();
+            leaf_values(&document, "", &mut leaves);
+
+            let mut hit_fields: Map<String, Value> = Map::new();
+            for (field, value) in leaves.into_iter().filter(|(field, _)| return_fields.iter().any(|x| field_matches(x, field))) {
+                match hit_fields.get_mut(&field) {
+                    Some(Value::Array(values)) => values.push(value.clone()),
+                    _ => {
+                        hit_fields.insert(field, json!([value]));
+                    }
+                }
+            }
+
+            let mut hit = json!({
+                "_index": index,
+                "_id": id,
+                "_score": score,
+                "fields": hit_fields
+            });
+
+            // Sorted hits have no score, like in Elasticsearch
+            if !sort.is_empty() {
+                hit["_score"] = Value::Null;
+                hit["sort"] = json!(sort_values(score, &document));
+            }
+
+            hit
+        }).collect();
+
+        Ok(SearchResult {
+            took: 0,
+            data,
+            total_data: total as i64,
+            match_type: "eq".to_string(),
+            facets: match facets.is_empty() {
+                true => None,
+                false => Some(Value::Object(facet_results))
+            },
+            next_cursor: None
+        })
+    }
+
+    async fn search_facet_values(&self, index: &str, facet: &str, facet_query: Option<String>, query: SearchQuery) -> Result<FacetSearchResult, ErrorTypes> {
+        let filters = parse_search_filters(query.filters.as_deref(), query.secured_filters.as_deref())?;
+
+        let max_values = query.max_values_per_facet()? as usize;
+
+        let fields = mapping_field_types(&self.mappings(index)?);
+
+        if !fields.contains_key(facet) {
+            return Err(ErrorTypes::FieldNotFound(facet.to_string()));
+        }
+
+        match exact_value_field(&fields, facet) {
+            Some(x) if fields.get(&x).map(|x| x == "keyword").unwrap_or(false) => (),
+            Some(_) => return Err(ErrorTypes::FacetNotSearchable(facet.to_string())),
+            None => return Err(ErrorTypes::FieldNotFacetable(facet.to_string()))
+        };
+
+        let facet_query = facet_query.map(|x| x.trim().to_string()).filter(|x| !x.is_empty());
+        let documents = self.matching_documents(index, query.search_term.as_deref(), query.search_in.as_deref(), filters.as_ref())?;
+
+        let mut counts: HashMap<String, i64> = HashMap::new();
+        for (_, _, document) in &documents {
+            let mut values: Vec<String> = field_values(document, facet)
+                .into_iter()
+                .filter_map(|x| x.as_str())
+                .filter(|x| facet_query.as_deref().map(|query| facet_query_matches(x, query)).unwrap_or(true))
+                .map(|x| x.to_string())
+                .collect();
+            values.sort();
+            values.dedup();
+            values.into_iter().for_each(|x| *counts.entry(x).or_default() += 1);
+        }
+
+        let mut counts: Vec<(String, i64)> = counts.into_iter().collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        counts.truncate(max_values);
+
+        Ok(FacetSearchResult {
+            took: 0,
+            facet_hits: counts.into_iter().map(|(value, count)| FacetHit { value: json!(value), count }).collect()
+        })
+    }
+
+    async fn get_document(&self, index: &str, document_id: &str, retrieve_fields: Option<String>) -> Result<Value, ErrorTypes> {
+        let document = self.with_index(index, |x| {
+            x.documents.get(document_id).cloned().ok_or_else(|| ErrorTypes::DocumentNotFound(document_id.to_string()))
+        })?;
+
+        match retrieve_fields.as_deref().map(split_fields) {
+            Some(patterns) if !patterns.is_empty() => Ok(filter_source(&document, &patterns, "")),
+            _ => Ok(document)
+        }
+    }
+
+    async fn update_document(&self, index: &str, document_id: &str, mut data: Value) -> Result<(), ErrorTypes> {
+        let mappings = self.mappings(index)?;
+        normalize_geo_points(&mut data["doc"], &mapping_field_types(&mappings));
+
+        if let Some(schema) = write_schema(&mappings, None)? {
+            schema.validate(&data["doc"]).map_err(ErrorTypes::InvalidDocument)?;
+        }
+
+        let (_, status, result) = self.with_index(index, |x| {
+            Ok(Self::run_bulk_action(x, BulkAction::Update { document_id: document_id.to_string(), data: data["doc"].take() }))
+        })?;
+
+        match (status, result) {
+            (_, Ok(_)) => Ok(()),
+            (404, Err(_)) => Err(ErrorTypes::DocumentNotFound(document_id.to_string())),
+            (_, Err(x)) => Err(ErrorTypes::BadDataRequest(x))
+        }
+    }
+
+    async fn delete_document(&self, index: &str, document_id: &str) -> Result<(), ErrorTypes> {
+        self.with_index(index, |x| match x.documents.remove(document_id) {
+            Some(_) => Ok(()),
+            None => Err(ErrorTypes::DocumentNotFound(document_id.to_string()))
+        })
+    }
+
+    async fn export_index(self: Arc<Self>, index: &str, format: ExportFormat, fields: Option<String>) -> Result<BoxStream<'static, Result<Bytes, ErrorTypes>>, ErrorTypes> {
+        let fields = export_fields(fields);
+
+        let mappings = self.with_index(index, |x| Ok(x.mappings.clone()))?;
+
+        let columns = match (&fields, format) {
+            (Some(fields), _) => fields.clone(),
+            (None, ExportFormat::Csv) => mapping_document_fields(&mappings),
+            (None, _) => Vec::new()
+        };
+
+        let index = index.to_string();
+
+        // Pages are read as the stream is polled, a page fails if the index was deleted since the export started
+        let pages = stream::unfold((self, Some(ExportWriter::new(format, columns)), None), move |(backend, writer, after)| {
+            let index = index.clone();
+            let fields = fields.clone();
+
+            async move {
+                let mut writer = writer?;
+
+                let page = backend.with_index(&index, |x| Ok(x.documents
+                    .range((after.map(Bound::Excluded).unwrap_or(Bound::Unbounded), Bound::Unbounded))
+                    .take(EXPORT_PAGE_SIZE as usize)
+                    .map(|(id, document)| (id.clone(), document.clone()))
+                    .collect::<Vec<(String, Value)>>()));
+
+                let page = match page {
+                    Ok(x) => x,
+                    Err(x) => return Some((Err(x), (backend, None, None)))
+                };
+
+                let last = page.last().map(|(id, _)| id.clone());
+
+                let documents: Vec<Value> = page.into_iter().map(|(id, document)| {
+                    let mut document = match &fields {
+                        Some(fields) => filter_source(&document, fields, ""),
+                        None => document
+                    };
+                    document["_id"] = json!(id);
+                    document
+                }).collect();
+
+                let mut chunk = writer.write_page(&documents);
+
+                let writer = match (documents.len() as i64) < EXPORT_PAGE_SIZE {
+                    true => {
+                        chunk.push_str(&writer.finish());
+                        None
+                    },
+                    false => Some(writer)
+                };
+
+                Some((Ok(Bytes::from(chunk)), (backend, writer, last)))
+            }
+        });
+
+        Ok(Box::pin(pages))
+    }
+
+    /// There are no nodes
+    fn node_health(&self) -> Vec<NodeHealth> {
+        Vec::new()
+    }
+
+    fn cached_health(&self) -> (HealthState, Option<u64>) {
+        (HealthState::Up(memory_health()), Some(now()))
+    }
+
+    async fn check_health(&self) -> Result<ClusterHealth, ErrorTypes> {
+        Ok(memory_health())
+    }
+
+    async fn get_api_key(&self, id: &str) -> Result<Option<StoredApiKey>, ErrorTypes> {
+        Ok(self.api_keys.read().unwrap().get(id).cloned())
+    }
+
+    async fn resolve_api_key(&self, id: &str) -> Result<Option<StoredApiKey>, ErrorTypes> {
+        self.get_api_key(id).await
+    }
+
+    async fn list_api_keys(&self) -> Result<Vec<ApiKey>, ErrorTypes> {
+        let mut keys: Vec<ApiKey> = self.api_keys.read().unwrap().values().map(|x| x.key.clone()).collect();
+        keys.sort_by_key(|x| x.created_at);
+
+        Ok(keys)
+    }
+
+    async fn put_api_key(&self, key: &StoredApiKey) -> Result<(), ErrorTypes> {
+        self.api_keys.write().unwrap().insert(key.key.id.clone(), key.clone());
+
+        Ok(())
+    }
+
+    async fn delete_api_key(&self, id: &str) -> Result<(), ErrorTypes> {
+        match self.api_keys.write().unwrap().remove(id) {
+            Some(_) => Ok(()),
+            None => Err(ErrorTypes::ApiKeyNotFound(id.to_string()))
+        }
+    }
+}
@@ -0,0 +1,163 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{LazyLock, Mutex, atomic::{AtomicI64, Ordering}},
+    time::Duration
+};
+
+use serde_json::Value;
+
+/// Upper bounds, in seconds, of the buckets of every histogram
+const BUCKETS: [f64; 12] = [0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// Metrics of the whole process, shown by GET /metrics
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::default);
+
+#[derive(Default, Clone)]
+struct Histogram {
+    /// Observations in each bucket of BUCKETS, not cumulative, the last one holds the observations above every bound
+    buckets: [u64; BUCKETS.len() + 1],
+    count: u64,
+    sum: f64
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        let bucket = BUCKETS.iter().position(|x| seconds <= *x).unwrap_or(BUCKETS.len());
+
+        self.buckets[bucket] += 1;
+        self.count += 1;
+        self.sum += seconds;
+    }
+}
+
+/// Labels of a series, in the order they are rendered
+type Labels = Vec<(&'static str, String)>;
+
+/// Request counts and latencies of the API and of Elasticsearch, in the Prometheus text format
+///
+/// Kept in memory, every server instance has its own metrics
+#[derive(Default)]
+pub struct Metrics {
+    http_requests: Mutex<BTreeMap<Labels, Histogram>>,
+    http_in_flight: AtomicI64,
+    elasticsearch_requests: Mutex<BTreeMap<Labels, Histogram>>,
+    elasticsearch_took: Mutex<BTreeMap<Labels, Histogram>>,
+    elasticsearch_in_flight: AtomicI64,
+    errors: Mutex<BTreeMap<Labels, u64>>
+}
+
+/// Decrements an in flight gauge when dropped, so that cancelled requests are not counted forever
+pub struct InFlight<'a>(&'a AtomicI64);
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl Metrics {
+    /// Counts a request to the API as in flight until the guard is dropped
+    pub fn http_started(&self) -> InFlight<'_> {
+        self.http_in_flight.fetch_add(1, Ordering::Relaxed);
+        InFlight(&self.http_in_flight)
+    }
+
+    /// Records a request to the API, route is the pattern of the matched route so that indexes and ids do not make new series
+    pub fn observe_http(&self, method: &str, route: &str, status: u16, duration: Duration) {
+        let labels = vec![("method", method.to_string()), ("route", route.to_string()), ("status", status.to_string())];
+
+        self.http_requests.lock().unwrap().entry(labels).or_default().observe(duration.as_secs_f64());
+    }
+
+    /// Counts an error returned by the API, by its ErrorTypes variant
+    pub fn count_error(&self, error: &'static str) {
+        *self.errors.lock().unwrap().entry(vec![("error", error.to_string())]).or_default() += 1;
+    }
+
+    /// Counts a request to Elasticsearch as in flight until the guard is dropped
+    pub fn elasticsearch_started(&self) -> InFlight<'_> {
+        self.elasticsearch_in_flight.fetch_add(1, Ordering::Relaxed);
+        InFlight(&self.elasticsearch_in_flight)
+    }
+
+    /// Records a request to Elasticsearch, status is None when no node answered
+    pub fn observe_elasticsearch(&self, operation: &str, status: Option<u16>, duration: Duration) {
+        let status = status.map(|x| x.to_string()).unwrap_or_else(|| "error".to_string());
+        let labels = vec![("operation", operation.to_string()), ("status", status)];
+
+        self.elasticsearch_requests.lock().unwrap().entry(labels).or_default().observe(duration.as_secs_f64());
+    }
+
+    /// Records the took time, in milliseconds, of an Elasticsearch search response
+    pub fn observe_took(&self, operation: &str, response: &Value) {
+        if let Some(took) = response["took"].as_u64() {
+            let labels = vec![("operation", operation.to_string())];
+
+            self.elasticsearch_took.lock().unwrap().entry(labels).or_default().observe(took as f64 / 1000.0);
+        }
+    }
+
+    /// Every metric in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+
+        let http_requests = self.http_requests.lock().unwrap().clone();
+        let counts: BTreeMap<Labels, u64> = http_requests.iter().map(|(labels, x)| (labels.clone(), x.count)).collect();
+
+        render_counter(&mut out, "dps_http_requests_total", "Requests to the API by method, route and status", &counts);
+        render_histogram(&mut out, "dps_http_request_duration_seconds", "Time to answer requests to the API by method, route and status", &http_requests);
+        render_gauge(&mut out, "dps_http_requests_in_flight", "Requests to the API being answered", self.http_in_flight.load(Ordering::Relaxed));
+        render_counter(&mut out, "dps_errors_total", "Errors returned by the API by type", &self.errors.lock().unwrap());
+        render_histogram(&mut out, "dps_elasticsearch_request_duration_seconds", "Time of requests to Elasticsearch by operation and status, failovers included", &self.elasticsearch_requests.lock().unwrap());
+        render_histogram(&mut out, "dps_elasticsearch_took_seconds", "Time Elasticsearch reported spending on searches", &self.elasticsearch_took.lock().unwrap());
+        render_gauge(&mut out, "dps_elasticsearch_requests_in_flight", "Requests to Elasticsearch waiting for an answer", self.elasticsearch_in_flight.load(Ordering::Relaxed));
+
+        out
+    }
+}
+
+fn render_counter(out: &mut String, name: &str, help: &str, series: &BTreeMap<Labels, u64>) {
+    let _ = writeln!(out, "# HELP {} {}\n# TYPE {} counter", name, help, name);
+
+    for (labels, value) in series {
+        let _ = writeln!(out, "{}{} {}", name, render_labels(labels), value);
+    }
+}
+
+fn render_gauge(out: &mut String, name: &str, help: &str, value: i64) {
+    let _ = writeln!(out, "# HELP {} {}\n# TYPE {} gauge\n{} {}", name, help, name, name, value);
+}
+
+fn render_histogram(out: &mut String, name: &str, help: &str, series: &BTreeMap<Labels, Histogram>) {
+    let _ = writeln!(out, "# HELP {} {}\n# TYPE {} histogram", name, help, name);
+
+    for (labels, histogram) in series {
+        let mut cumulative = 0;
+
+        for (bound, count) in BUCKETS.iter().map(|x| x.to_string()).chain(["+Inf".to_string()]).zip(histogram.buckets) {
+            cumulative += count;
+
+            let mut bucket_labels = labels.clone();
+            bucket_labels.push(("le", bound));
+
+            let _ = writeln!(out, "{}_bucket{} {}", name, render_labels(&bucket_labels), cumulative);
+        }
+
+        let _ = writeln!(out, "{}_sum{} {}", name, render_labels(labels), histogram.sum);
+        let _ = writeln!(out, "{}_count{} {}", name, render_labels(labels), histogram.count);
+    }
+}
+
+fn render_labels(labels: &Labels) -> String {
+    if labels.is_empty() {
+        return String::new();
+    }
+
+    let labels: Vec<String> = labels
+        .iter()
+        .map(|(name, value)| format!("{}=\"{}\"", name, value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")))
+        .collect();
+
+    format!("{{{}}}", labels.join(","))
+}
@@ -3,5 +3,25 @@ pub mod documents;
 pub mod index;
 pub mod errors;
 pub mod helpers;
+pub mod filters;
+pub mod sort;
+pub mod highlight;
+pub mod geo;
+pub mod cursor;
+pub mod export;
+pub mod import;
+pub mod backend;
+pub mod pool;
+pub mod api_keys;
+pub mod secured_keys;
+pub mod rate_limit;
+pub mod metrics;
+pub mod request_context;
+pub mod health;
+pub mod index_cache;
+pub mod key_cache;
+pub mod schema;
+#[cfg(test)]
+pub mod memory;
 pub use self::errors::*;
 pub use self::client::EClient;
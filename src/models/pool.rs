@@ -0,0 +1,323 @@
+use std::{
+    error::Error,
+    future::Future,
+    sync::{Arc, Mutex, RwLock, atomic::{AtomicUsize, Ordering}},
+    time::{Duration, Instant}
+};
+
+use elasticsearch::{
+    Elasticsearch,
+    auth::Credentials,
+    cert::{Certificate, CertificateValidation},
+    http::{headers::{HeaderName, HeaderValue}, response::Response, transport::{SingleNodeConnectionPool, TransportBuilder}},
+    nodes::NodesInfoParts
+};
+use reqwest::Url;
+use serde::Serialize;
+use serde_json::Value;
+use tracing::{Instrument, Span, field::Empty};
+
+use crate::telemetry::traceparent;
+
+use super::metrics::METRICS;
+
+/// Header every request closure sets with the traceparent it is given, so that Elasticsearch joins the trace
+pub const TRACEPARENT: HeaderName = HeaderName::from_static(crate::telemetry::TRACEPARENT);
+
+/// How long a node is skipped after its first failure, doubled on every following failure
+const DEAD_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Longest time a node is skipped
+const MAX_DEAD_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// How the certificates of https nodes are checked
+#[derive(Clone)]
+pub enum Trust {
+    /// System CAs
+    System,
+    /// PEM of a CA trusted on top of the system CAs
+    Ca(Vec<u8>),
+    /// Any certificate is accepted
+    Insecure
+}
+
+/// Health of a node, as shown by the diagnostics endpoint
+#[derive(Serialize)]
+pub struct NodeHealth {
+    pub url: String,
+    pub alive: bool,
+    /// Failed requests since the last successful one
+    pub failures: u32,
+    /// Seconds until a dead node is tried again
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_in: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>
+}
+
+#[derive(Default)]
+struct NodeState {
+    failures: u32,
+    dead_until: Option<Instant>,
+    last_error: Option<String>
+}
+
+struct Node {
+    url: Url,
+    client: Elasticsearch,
+    state: Mutex<NodeState>
+}
+
+impl Node {
+    fn is_alive(&self, now: Instant) -> bool {
+        self.state.lock().unwrap().dead_until.map(|x| x <= now).unwrap_or(true)
+    }
+
+    fn mark_alive(&self) {
+        *self.state.lock().unwrap() = NodeState::default();
+    }
+
+    /// Skips the node for a time that grows with every consecutive failure
+    fn mark_dead(&self, error: &elasticsearch::Error) {
+        let mut state = self.state.lock().unwrap();
+
+        state.failures += 1;
+        let timeout = DEAD_TIMEOUT.saturating_mul(2u32.saturating_pow(state.failures - 1)).min(MAX_DEAD_TIMEOUT);
+        state.dead_until = Some(Instant::now() + timeout);
+        state.last_error = Some(error.to_string());
+
+        log::warn!("Elasticsearch node {} marked dead for {}s, {}", self.url, timeout.as_secs(), error);
+    }
+}
+
+/// The Elasticsearch nodes of the cluster, requests are spread round robin over the nodes that are alive
+///
+/// A node that fails to answer is marked dead and skipped until its timeout passes
+pub struct NodePool {
+    nodes: RwLock<Vec<Arc<Node>>>,
+    next: AtomicUsize,
+    credentials: Option<Credentials>,
+    trust: Trust
+}
+
+impl NodePool {
+    /// Creates a pool over the seed nodes
+    pub fn new(urls: Vec<Url>, credentials: Option<Credentials>, trust: Trust) -> Self {
+        let pool = Self {
+            nodes: RwLock::new(Vec::new()),
+            next: AtomicUsize::new(0),
+            credentials,
+            trust
+        };
+
+        let nodes = urls.into_iter().map(|x| pool.node(x)).collect();
+        *pool.nodes.write().unwrap() = nodes;
+
+        pool
+    }
+
+    fn node(&self, url: Url) -> Arc<Node> {
+        let mut builder = TransportBuilder::new(SingleNodeConnectionPool::new(url.clone()));
+
+        if let Some(credentials) = &self.credentials {
+            builder = builder.auth(credentials.clone());
+        }
+
+        builder = match &self.trust {
+            Trust::System => builder,
+            // Already parsed once when the config was loaded
+            Trust::Ca(pem) => builder.cert_validation(CertificateValidation::Full(Certificate::from_pem(pem).unwrap())),
+            Trust::Insecure => builder.cert_validation(CertificateValidation::None)
+        };
+
+        Arc::new(Node {
+            url,
+            client: Elasticsearch::new(builder.build().unwrap()),
+            state: Mutex::new(NodeState::default())
+        })
+    }
+
+    /// Nodes in the order they should be tried, alive nodes starting from the next one in the round, then dead nodes soonest to retry first
+    fn candidates(&self) -> Vec<Arc<Node>> {
+        let mut nodes = self.nodes.read().unwrap().clone();
+        let start = self.next.fetch_add(1, Ordering::Relaxed) % nodes.len();
+        nodes.rotate_left(start);
+
+        let now = Instant::now();
+        let (alive, mut dead): (Vec<_>, Vec<_>) = nodes.into_iter().partition(|x| x.is_alive(now));
+        dead.sort_by_key(|x| x.state.lock().unwrap().dead_until);
+
+        alive.into_iter().chain(dead).collect()
+    }
+
+    /// Sends the request through the candidates in a span of its own, its latency is recorded under operation, failovers included
+    async fn send_with<F, Fut>(&self, operation: &str, idempotent: bool, request: F) -> Result<Response, elasticsearch::Error>
+    where
+        F: Fn(Elasticsearch, HeaderValue) -> Fut,
+        Fut: Future<Output = Result<Response, elasticsearch::Error>>
+    {
+        let span = tracing::info_span!(
+            "elasticsearch",
+            otel.name = operation,
+            otel.kind = "client",
+            otel.status_code = Empty,
+            db.system = "elasticsearch",
+            db.operation = operation,
+            server.address = Empty,
+            http.response.status_code = Empty
+        );
+        // Made of ascii hex digits and dashes
+        let traceparent = HeaderValue::from_str(&traceparent(&span)).unwrap();
+
+        let _in_flight = METRICS.elasticsearch_started();
+        let started = Instant::now();
+
+        let resp = self
+            .try_candidates(idempotent, |es| request(es, traceparent.clone()))
+            .instrument(span.clone())
+            .await;
+
+        let status = resp.as_ref().ok().map(|x| x.status_code().as_u16());
+        METRICS.observe_elasticsearch(operation, status, started.elapsed());
+
+        match status {
+            Some(x) => span.record("http.response.status_code", x),
+            None => span.record("otel.status_code", "ERROR")
+        };
+
+        resp
+    }
+
+    async fn try_candidates<F, Fut>(&self, idempotent: bool, request: F) -> Result<Response, elasticsearch::Error>
+    where
+        F: Fn(Elasticsearch) -> Fut,
+        Fut: Future<Output = Result<Response, elasticsearch::Error>>
+    {
+        let mut last_error = None;
+
+        for node in self.candidates() {
+            Span::current().record("server.address", node.url.as_str());
+
+            match request(node.client.clone()).await {
+                Ok(resp) => {
+                    node.mark_alive();
+                    return Ok(resp);
+                },
+                Err(x) => {
+                    node.mark_dead(&x);
+                    let retry = idempotent || is_connect_error(&x);
+                    last_error = Some(x);
+
+                    if !retry {
+                        break;
+                    }
+                }
+            }
+        }
+
+        // There is always at least one node
+        Err(last_error.unwrap())
+    }
+
+    /// Sends an idempotent request, retried on the next node when a node fails to answer
+    ///
+    /// operation names the request in the metrics and traces, such as search or put_mapping. The request
+    /// is given the traceparent to send in the TRACEPARENT header
+    pub async fn send<F, Fut>(&self, operation: &str, request: F) -> Result<Response, elasticsearch::Error>
+    where
+        F: Fn(Elasticsearch, HeaderValue) -> Fut,
+        Fut: Future<Output = Result<Response, elasticsearch::Error>>
+    {
+        self.send_with(operation, true, request).await
+    }
+
+    /// Sends a request that must not be applied twice, only retried on the next node when the connection could not be made
+    pub async fn send_once<F, Fut>(&self, operation: &str, request: F) -> Result<Response, elasticsearch::Error>
+    where
+        F: Fn(Elasticsearch, HeaderValue) -> Fut,
+        Fut: Future<Output = Result<Response, elasticsearch::Error>>
+    {
+        self.send_with(operation, false, request).await
+    }
+
+    /// Replaces the nodes with the http nodes of the cluster, returns the amount of nodes found
+    ///
+    /// Dedicated master nodes are left out, known nodes keep their health
+    pub async fn sniff(&self) -> Result<usize, elasticsearch::Error> {
+        let resp = self.send("nodes_info", |es, traceparent| async move {
+            es.nodes()
+                .info(NodesInfoParts::Metric(&["http"]))
+                .header(TRACEPARENT, traceparent)
+                .send()
+                .await
+        }).await?;
+
+        let json_resp = resp.json::<Value>().await?;
+
+        let scheme = self.nodes.read().unwrap()[0].url.scheme().to_string();
+
+        let urls: Vec<Url> = json_resp["nodes"]
+            .as_object()
+            .map(|nodes| nodes.values()
+                .filter(|node| node["roles"] != serde_json::json!(["master"]))
+                .filter_map(|node| node["http"]["publish_address"].as_str())
+                .filter_map(|address| publish_url(&scheme, address))
+                .collect())
+            .unwrap_or_default();
+
+        if urls.is_empty() {
+            return Ok(0);
+        }
+
+        let known = self.nodes.read().unwrap().clone();
+        let nodes: Vec<Arc<Node>> = urls
+            .into_iter()
+            .map(|url| match known.iter().find(|x| x.url == url) {
+                Some(x) => x.clone(),
+                None => self.node(url)
+            })
+            .collect();
+
+        let found = nodes.len();
+        *self.nodes.write().unwrap() = nodes;
+
+        Ok(found)
+    }
+
+    /// Health of every node
+    pub fn health(&self) -> Vec<NodeHealth> {
+        let now = Instant::now();
+
+        self.nodes.read().unwrap().iter().map(|node| {
+            let state = node.state.lock().unwrap();
+            let retry_in = state.dead_until.filter(|x| *x > now).map(|x| (x - now).as_secs());
+
+            NodeHealth {
+                url: node.url.to_string(),
+                alive: retry_in.is_none(),
+                failures: state.failures,
+                retry_in,
+                last_error: state.last_error.clone()
+            }
+        }).collect()
+    }
+}
+
+/// Whether the request failed before reaching the node, so that it is safe to send again
+fn is_connect_error(error: &elasticsearch::Error) -> bool {
+    error.source()
+        .and_then(|x| x.downcast_ref::<reqwest::Error>())
+        .map(|x| x.is_connect())
+        .unwrap_or(false)
+}
+
+/// Url of a publish address, given as "ip:port" or "hostname/ip:port", the hostname is kept for certificate validation
+fn publish_url(scheme: &str, address: &str) -> Option<Url> {
+    let address = match address.split_once('/') {
+        Some((host, ip_port)) if !host.is_empty() => format!("{}:{}", host, ip_port.rsplit_once(':')?.1),
+        Some((_, ip_port)) => ip_port.to_string(),
+        None => address.to_string()
+    };
+
+    Url::parse(&format!("{}://{}", scheme, address)).ok()
+}
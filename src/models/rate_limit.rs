@@ -0,0 +1,225 @@
+use std::{collections::{HashMap, VecDeque}, sync::Mutex, time::{Duration, Instant}};
+
+use super::api_keys::now;
+
+/// Most buckets kept, the oldest one is dropped to make room for a new one
+const MAX_BUCKETS: usize = 10000;
+
+/// A bucket untouched for this long is full again, forgetting it changes nothing
+const BUCKET_IDLE: Duration = Duration::from_secs(60);
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+    /// Tells the bucket apart from a former bucket of the same name in the creation order
+    created: Instant
+}
+
+/// Buckets by name along with the order they were created in, so that the oldest one is found without a scan
+struct Buckets {
+    by_name: HashMap<String, Bucket>,
+    /// Names of the buckets, oldest first, entries of buckets dropped since are skipped
+    order: VecDeque<(String, Instant)>,
+    swept: Instant
+}
+
+impl Default for Buckets {
+    fn default() -> Self {
+        Self { by_name: HashMap::new(), order: VecDeque::new(), swept: Instant::now() }
+    }
+}
+
+impl Buckets {
+    /// The bucket of name refilled up to now, created full if there is none
+    fn refill(&mut self, name: &str, capacity: f64, now: Instant) -> &mut Bucket {
+        // Idle buckets are dropped once a minute rather than on every request
+        if now.duration_since(self.swept) >= BUCKET_IDLE {
+            self.by_name.retain(|_, x| now.duration_since(x.updated) < BUCKET_IDLE);
+            let by_name = &self.by_name;
+            self.order.retain(|(name, created)| by_name.get(name).is_some_and(|x| x.created == *created));
+            self.swept = now;
+        }
+
+        if !self.by_name.contains_key(name) {
+            while self.by_name.len() >= MAX_BUCKETS {
+                let Some((oldest, created)) = self.order.pop_front() else { break };
+                if self.by_name.get(&oldest).is_some_and(|x| x.created == created) {
+                    self.by_name.remove(&oldest);
+                }
+            }
+
+            self.by_name.insert(name.to_string(), Bucket { tokens: capacity, updated: now, created: now });
+            self.order.push_back((name.to_string(), now));
+        }
+
+        let per_second = capacity / 60.0;
+        let bucket = self.by_name.get_mut(name).unwrap();
+
+        bucket.tokens = (bucket.tokens + now.duration_since(bucket.updated).as_secs_f64() * per_second).min(capacity);
+        bucket.updated = now;
+        bucket
+    }
+}
+
+/// State of a bucket once a token was asked for, sent back in the X-RateLimit-* headers
+#[derive(Debug, Clone, Copy)]
+pub struct Allowance {
+    pub limit: u32,
+    pub remaining: u32,
+    /// Seconds until the bucket is full again
+    pub reset: u64,
+    /// Seconds until the next token, 0 when the request was let through
+    pub retry_after: u64
+}
+
+/// Requests of every API key in the current calendar month, UTC
+#[derive(Default)]
+struct MonthlyUsage {
+    month: String,
+    operations: HashMap<String, u64>
+}
+
+/// Token buckets by name, each one holds up to its limit of tokens and refills evenly over a minute,
+/// along with the monthly usage of every API key
+///
+/// Both are kept in memory, every server instance counts its own requests and the monthly usage starts over when it restarts
+pub struct RateLimiter {
+    buckets: Mutex<Buckets>,
+    usage: Mutex<MonthlyUsage>,
+    /// Unix time the server started counting requests
+    started: u64
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self { buckets: Mutex::default(), usage: Mutex::default(), started: now() }
+    }
+}
+
+impl RateLimiter {
+    /// Takes a token from each bucket, given by name with its limit per minute, returns the state of the one with the fewest tokens left
+    ///
+    /// Tokens are only taken when every bucket has one, otherwise fails with the state of the first empty bucket, retry_after set
+    pub fn acquire(&self, buckets: &[(String, u32)]) -> Result<Option<Allowance>, Allowance> {
+        let now = Instant::now();
+        let mut all = self.buckets.lock().unwrap();
+
+        for (name, per_minute) in buckets {
+            let capacity = f64::from((*per_minute).max(1));
+            let tokens = all.refill(name, capacity, now).tokens;
+
+            if tokens < 1.0 {
+                return Err(allowance(*per_minute, tokens, ((1.0 - tokens) / (capacity / 60.0)).ceil() as u64));
+            }
+        }
+
+        let mut tightest: Option<Allowance> = None;
+
+        for (name, per_minute) in buckets {
+            // Refilled above, the name is there unless it was dropped to make room for another bucket of the request
+            let capacity = f64::from((*per_minute).max(1));
+            let bucket = all.refill(name, capacity, now);
+            bucket.tokens = (bucket.tokens - 1.0).max(0.0);
+
+            let x = allowance(*per_minute, bucket.tokens, 0);
+            if tightest.map(|y| x.remaining < y.remaining).unwrap_or(true) {
+                tightest = Some(x);
+            }
+        }
+
+        Ok(tightest)
+    }
+
+    /// Counts a request of an API key, fails with the seconds until the next month when the quota is used up
+    pub fn record_operation(&self, key_id: &str, quota: Option<u64>) -> Result<u64, u64> {
+        let (month, next_month_in) = current_month(now());
+        let mut usage = self.usage.lock().unwrap();
+
+        if usage.month != month {
+            *usage = MonthlyUsage { month, operations: HashMap::new() };
+        }
+
+        let operations = usage.operations.entry(key_id.to_string()).or_default();
+
+        if quota.map(|x| *operations >= x).unwrap_or(false) {
+            return Err(next_month_in);
+        }
+
+        *operations += 1;
+
+        Ok(*operations)
+    }
+
+    /// Returns the current month, as "YYYY-MM", the requests of an API key in it,
+    /// and the unix time they are counted since, the start of the month or of the server if it started later
+    pub fn usage(&self, key_id: &str) -> (String, u64, u64) {
+        let timestamp = now();
+        let (month, _) = current_month(timestamp);
+        let usage = self.usage.lock().unwrap();
+
+        let operations = match usage.month == month {
+            true => usage.operations.get(key_id).copied().unwrap_or(0),
+            false => 0
+        };
+
+        (month, operations, month_start(timestamp).max(self.started))
+    }
+}
+
+/// State of a bucket holding tokens out of per_minute
+fn allowance(per_minute: u32, tokens: f64, retry_after: u64) -> Allowance {
+    let capacity = f64::from(per_minute.max(1));
+
+    Allowance {
+        limit: per_minute,
+        remaining: tokens as u32,
+        reset: ((capacity - tokens) / (capacity / 60.0)).ceil() as u64,
+        retry_after
+    }
+}
+
+/// Returns the calendar month of a unix time, as "YYYY-MM", with the seconds until the next month starts
+pub fn current_month(timestamp: u64) -> (String, u64) {
+    let (year, month, _) = civil_from_days((timestamp / 86400) as i64);
+    let (next_year, next_month) = match month {
+        12 => (year + 1, 1),
+        x => (year, x + 1)
+    };
+
+    let next_month_start = days_from_civil(next_year, next_month, 1) as u64 * 86400;
+
+    (format!("{:04}-{:02}", year, month), next_month_start.saturating_sub(timestamp))
+}
+
+/// Unix time the calendar month of a unix time starts
+fn month_start(timestamp: u64) -> u64 {
+    let (year, month, _) = civil_from_days((timestamp / 86400) as i64);
+
+    days_from_civil(year, month, 1) as u64 * 86400
+}
+
+/// Date of a day counted from 1970-01-01, see http://howardhinnant.github.io/date_algorithms.html
+pub(crate) fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+
+    (yoe + era * 400 + i64::from(month <= 2), month, day)
+}
+
+/// Days from 1970-01-01 to a date, the inverse of civil_from_days
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = (i64::from(month) + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+
+    era * 146097 + doe - 719468
+}
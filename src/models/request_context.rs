@@ -0,0 +1,40 @@
+use std::{cell::RefCell, future::Future};
+
+use serde_json::Value;
+
+/// What the models learn about the request being answered, written to its access log line
+#[derive(Default, Debug, Clone)]
+pub struct RequestContext {
+    /// Index the API key was authorized for
+    pub index: Option<String>,
+    /// Sum of the took of the Elasticsearch searches, in milliseconds
+    pub took: Option<u64>
+}
+
+tokio::task_local! {
+    static CONTEXT: RefCell<RequestContext>;
+}
+
+/// Runs a request, returns its output along with what was recorded while it ran
+pub async fn scope<F: Future>(request: F) -> (F::Output, RequestContext) {
+    CONTEXT.scope(RefCell::new(RequestContext::default()), async {
+        let output = request.await;
+
+        (output, CONTEXT.with(|x| x.take()))
+    }).await
+}
+
+/// Records the index of the request, does nothing outside of scope
+pub fn record_index(index: &str) {
+    let _ = CONTEXT.try_with(|x| x.borrow_mut().index = Some(index.to_string()));
+}
+
+/// Adds the took of an Elasticsearch search response, does nothing outside of scope
+pub fn record_took(response: &Value) {
+    if let Some(took) = response["took"].as_u64() {
+        let _ = CONTEXT.try_with(|x| {
+            let mut context = x.borrow_mut();
+            context.took = Some(context.took.unwrap_or(0) + took);
+        });
+    }
+}
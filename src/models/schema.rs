@@ -0,0 +1,264 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+use super::{ErrorTypes, documents::BulkAction};
+
+/// Type of a field of a schema
+#[derive(Deserialize, Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldType {
+    Text,
+    Keyword,
+    Number,
+    Date,
+    Geo,
+    Boolean
+}
+
+impl FieldType {
+    fn name(self) -> &'static str {
+        match self {
+            FieldType::Text => "text",
+            FieldType::Keyword => "keyword",
+            FieldType::Number => "number",
+            FieldType::Date => "date",
+            FieldType::Geo => "geo",
+            FieldType::Boolean => "boolean"
+        }
+    }
+
+    /// Whether a single value, not an array, can be stored in a field of this type
+    fn accepts(self, value: &Value) -> bool {
+        match (self, value) {
+            (FieldType::Text | FieldType::Keyword, Value::String(_)) => true,
+            (FieldType::Number, Value::Number(_)) => true,
+            (FieldType::Boolean, Value::Bool(_)) => true,
+            (FieldType::Date, Value::Number(x)) => x.is_i64(),
+            (FieldType::Date, Value::String(x)) => is_date(x),
+            (FieldType::Geo, Value::Object(x)) => x.get("lat").is_some_and(Value::is_number) && x.get("lon").is_some_and(Value::is_number),
+            (FieldType::Geo, Value::String(x)) => x.split_once(',').is_some_and(|(lat, lon)| lat.trim().parse::<f64>().is_ok() && lon.trim().parse::<f64>().is_ok()),
+            _ => false
+        }
+    }
+}
+
+/// A field of a schema, with what it can be used for
+#[derive(Deserialize, Serialize, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct FieldSchema {
+    #[serde(rename = "type")]
+    pub field_type: FieldType,
+    /// Matched by search terms, only text and keyword fields, defaults to true for text fields
+    pub searchable: Option<bool>,
+    #[serde(default)]
+    pub filterable: bool,
+    #[serde(default)]
+    pub sortable: bool,
+    #[serde(default)]
+    pub facetable: bool
+}
+
+impl FieldSchema {
+    fn is_searchable(&self) -> bool {
+        self.searchable.unwrap_or(self.field_type == FieldType::Text)
+    }
+
+    /// Mapping of the field, only the structures used by the flags are indexed
+    fn to_property(&self, name: &str) -> Result<Value, ErrorTypes> {
+        let searchable = self.is_searchable();
+        let exact = self.filterable || self.sortable || self.facetable;
+
+        if searchable && !matches!(self.field_type, FieldType::Text | FieldType::Keyword) {
+            return Err(ErrorTypes::InvalidSchema(format!("field [{}] of type [{}] can not be searchable, only text and keyword fields are", name, self.field_type.name())));
+        }
+
+        if self.facetable && self.field_type == FieldType::Geo {
+            return Err(ErrorTypes::InvalidSchema(format!("field [{}] of type [geo] can not be facetable", name)));
+        }
+
+        // A geo_point without index nor doc_values can not be queried at all
+        if self.field_type == FieldType::Geo && !self.filterable && !self.sortable {
+            return Err(ErrorTypes::InvalidSchema(format!("field [{}] of type [geo] must be filterable or sortable", name)));
+        }
+
+        Ok(match self.field_type {
+            // Filters, sorting and facets use the keyword subfield, as with dynamically mapped strings
+            FieldType::Text if exact => json!({
+                "type": "text",
+                "index": searchable,
+                "fields": {
+                    "keyword": { "type": "keyword", "ignore_above": 256 }
+                }
+            }),
+            FieldType::Text => json!({ "type": "text", "index": searchable }),
+            FieldType::Keyword => json!({
+                "type": "keyword",
+                "index": searchable || self.filterable,
+                "doc_values": self.sortable || self.facetable
+            }),
+            FieldType::Geo => json!({
+                "type": "geo_point",
+                "index": self.filterable,
+                "doc_values": self.sortable
+            }),
+            field_type => json!({
+                "type": match field_type { FieldType::Number => "double", FieldType::Date => "date", _ => "boolean" },
+                "index": self.filterable,
+                "doc_values": self.sortable || self.facetable
+            })
+        })
+    }
+}
+
+/// Fields of an index declared when it is created, by name, object fields are joined with dots (ex: address.city)
+///
+/// Compiled into a strict mapping once, the schema is kept in the _meta of the mapping to check documents against
+#[derive(Deserialize, Serialize, Clone, Debug, Default, PartialEq)]
+#[serde(transparent)]
+pub struct Schema(pub BTreeMap<String, FieldSchema>);
+
+impl Schema {
+    /// The schema an index was created with, None for indexes mapped dynamically
+    pub fn of_index(mappings: &Value) -> Option<Self> {
+        serde_json::from_value(mappings["_meta"]["schema"].clone()).ok()
+    }
+
+    /// Strict mapping of the schema
+    pub fn to_mappings(&self) -> Result<Value, ErrorTypes> {
+        if self.0.is_empty() {
+            return Err(ErrorTypes::InvalidSchema("at least one field is required".to_string()));
+        }
+
+        let mut properties = Map::new();
+
+        for (name, field) in &self.0 {
+            if name.split('.').any(|x| x.is_empty()) {
+                return Err(ErrorTypes::InvalidSchema(format!("invalid field name [{}]", name)));
+            }
+
+            if let Some(parent) = self.0.keys().find(|x| name.starts_with(&format!("{}.", x))) {
+                return Err(ErrorTypes::InvalidSchema(format!("field [{}] can not be inside field [{}] of type [{}]", name, parent, self.0[parent].field_type.name())));
+            }
+
+            // Object fields are mapped as properties of their parents
+            let (parents, leaf) = match name.rsplit_once('.') {
+                Some((parents, leaf)) => (parents.split('.').collect(), leaf),
+                None => (Vec::new(), name.as_str())
+            };
+
+            let mut object = &mut properties;
+            for parent in parents {
+                object = object
+                    .entry(parent)
+                    .or_insert_with(|| json!({ "properties": {} }))["properties"]
+                    .as_object_mut()
+                    .unwrap();
+            }
+
+            object.insert(leaf.to_string(), field.to_property(name)?);
+        }
+
+        Ok(json!({
+            "dynamic": "strict",
+            "_meta": { "schema": self },
+            "properties": properties
+        }))
+    }
+
+    /// Checks the fields of a document, fields that are left out are allowed as with partial updates
+    pub fn validate(&self, document: &Value) -> Result<(), String> {
+        let Some(object) = document.as_object() else {
+            return Err("document must be an object".to_string());
+        };
+
+        self.validate_object(object, "")
+    }
+
+    /// Checks the documents of bulk operations, the error holds the position of the first invalid one
+    pub fn validate_bulk(&self, actions: &[BulkAction]) -> Result<(), ErrorTypes> {
+        for (position, action) in actions.iter().enumerate() {
+            match action {
+                BulkAction::Index { data, .. } | BulkAction::Create { data, .. } | BulkAction::Update { data, .. } => self
+                    .validate(data)
+                    .map_err(|x| ErrorTypes::InvalidDocument(format!("operation at position {}, {}", position, x)))?,
+                BulkAction::Delete { .. } => ()
+            }
+        }
+
+        Ok(())
+    }
+
+    fn validate_object(&self, object: &Map<String, Value>, prefix: &str) -> Result<(), String> {
+        for (name, value) in object {
+            let path = format!("{}{}", prefix, name);
+
+            if value.is_null() {
+                continue;
+            }
+
+            match self.0.get(&path) {
+                Some(field) => {
+                    let valid = match value {
+                        // Geo points may be written as [lon, lat]
+                        Value::Array(values) if field.field_type == FieldType::Geo && values.len() == 2 && values.iter().all(Value::is_number) => true,
+                        Value::Array(values) => values.iter().all(|x| x.is_null() || field.field_type.accepts(x)),
+                        x => field.field_type.accepts(x)
+                    };
+
+                    if !valid {
+                        return Err(format!("field [{}] expects a value of type [{}]", path, field.field_type.name()));
+                    }
+                },
+                None if self.0.keys().any(|x| x.starts_with(&format!("{}.", path))) => match value {
+                    Value::Object(x) => self.validate_object(x, &format!("{}.", path))?,
+                    _ => return Err(format!("field [{}] expects an object", path))
+                },
+                None => return Err(format!("field [{}] is not in the schema", path))
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Mappings of a new index, compiled from its schema, or dynamic with geo_fields mapped as geo_point
+pub fn new_index_mappings(geo_fields: Vec<String>, schema: Option<&Schema>) -> Result<Value, ErrorTypes> {
+    match schema {
+        Some(_) if !geo_fields.is_empty() => Err(ErrorTypes::InvalidSchema("geo_fields can not be used with a schema, declare them with the geo type".to_string())),
+        Some(schema) => schema.to_mappings(),
+        None => {
+            let properties: Map<String, Value> = geo_fields
+                .into_iter()
+                .map(|field| (field, json!({"type": "geo_point"})))
+                .collect();
+
+            Ok(json!({
+                "dynamic": "true",
+                "properties": properties
+            }))
+        }
+    }
+}
+
+/// The schema writes to an index are checked against, None for indexes mapped dynamically
+///
+/// The dynamic mode of an index with a schema can not be changed
+pub fn write_schema(mappings: &Value, dynamic_mode: Option<&str>) -> Result<Option<Schema>, ErrorTypes> {
+    let schema = Schema::of_index(mappings);
+
+    if schema.is_some() && dynamic_mode.is_some() {
+        return Err(ErrorTypes::InvalidDocument("dynamic_mode can not be used on an index with a schema".to_string()));
+    }
+
+    Ok(schema)
+}
+
+/// Valid dates as written by the default date format of Elasticsearch, such as 2024-05-01, 2024-05-01T12:30:00 or 2024-05-01T12:30:00Z
+fn is_date(value: &str) -> bool {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d").is_ok()
+        || NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S%.f").is_ok()
+        || DateTime::parse_from_rfc3339(value).is_ok()
+}
@@ -0,0 +1,135 @@
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use openssl::{hash::MessageDigest, pkey::{Id, PKey, Private}, sign::{Signer, Verifier}};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    ErrorTypes,
+    api_keys::{ApiKey, Role, now, validate_index_patterns},
+    backend::SearchBackend,
+    filters::parse_filter
+};
+
+/*
+Secured API keys are derived from a stored API key without being stored themselves:
+    secured.<params>.<signature>
+
+params is the base64 url (no padding) of the SecuredKeyParams json, signature the base64 url (no padding)
+of the Ed25519 signature of params, the seed of the Ed25519 key is the HMAC-SHA256 of "dps-secured-keys" keyed with the secret of the parent key
+
+Only the public key is stored with the parent key, the API keys index does not hold what is needed to sign secured keys
+
+They can be generated through POST /api/keys/secured or by anyone holding the parent key,
+rotating or revoking the parent key invalidates every key derived from it
+*/
+
+/// Id part of a secured API key, stored keys have longer random ids
+pub const SECURED_KEY_ID: &str = "secured";
+
+/// Signed with the secret of the parent key to get the seed of its Ed25519 key
+const SEED_CONTEXT: &[u8] = b"dps-secured-keys";
+
+/// Restrictions embedded in a secured API key
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct SecuredKeyParams {
+    /// Id of the stored API key it is derived from
+    pub parent: String,
+    /// Filter expression added to every search, see models/filters.rs for the syntax
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub filters: Option<String>,
+    /// Index patterns, on top of the patterns of the parent key
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub indices: Option<Vec<String>>,
+    /// Seconds since the unix epoch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<u64>,
+    /// Most searches per minute
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rate_limit: Option<u32>
+}
+
+impl SecuredKeyParams {
+    /// Checks that the restrictions can be used before handing out a key with them
+    pub fn validate(&self) -> Result<(), ErrorTypes> {
+        if let Some(filters) = &self.filters {
+            parse_filter(filters)?;
+        }
+
+        if let Some(indices) = &self.indices {
+            if indices.is_empty() || indices.iter().any(|x| x.trim().is_empty()) {
+                return Err(ErrorTypes::InvalidApiKeyRequest("indices must hold at least one index pattern".to_string()));
+            }
+
+            validate_index_patterns(indices)?;
+        }
+
+        if self.rate_limit == Some(0) {
+            return Err(ErrorTypes::InvalidApiKeyRequest("rate_limit must be at least 1".to_string()));
+        }
+
+        Ok(())
+    }
+
+    /// Signs the restrictions with the secret of the parent key, returns the secured key
+    pub fn sign(&self, secret: &str) -> String {
+        let params = URL_SAFE_NO_PAD.encode(serde_json::to_vec(self).unwrap());
+
+        let mut signer = Signer::new_without_digest(&signing_key(secret)).expect("Failed to create Ed25519 signer");
+        let signature = URL_SAFE_NO_PAD.encode(signer.sign_oneshot_to_vec(params.as_bytes()).expect("Failed to sign secured API key"));
+
+        format!("{}.{}.{}", SECURED_KEY_ID, params, signature)
+    }
+}
+
+/// Public key checking the secured keys signed with a secret, base64 url (no padding), stored along with the hash of the secret
+pub fn verifying_key(secret: &str) -> String {
+    URL_SAFE_NO_PAD.encode(signing_key(secret).raw_public_key().expect("Failed to get Ed25519 public key"))
+}
+
+fn signing_key(secret: &str) -> PKey<Private> {
+    let key = PKey::hmac(secret.as_bytes()).expect("Failed to create HMAC key");
+    let mut signer = Signer::new(MessageDigest::sha256(), &key).expect("Failed to create HMAC signer");
+    let seed = signer.sign_oneshot_to_vec(SEED_CONTEXT).expect("Failed to derive Ed25519 seed");
+
+    PKey::private_key_from_raw_bytes(&seed, Id::ED25519).expect("Failed to create Ed25519 key")
+}
+
+/// Whether signature is the signature of params by the key of verifying_key, false for keys stored before they had one
+fn verify(verifying_key: &str, params: &str, signature: &[u8]) -> bool {
+    let Some(key) = URL_SAFE_NO_PAD.decode(verifying_key).ok().and_then(|x| PKey::public_key_from_raw_bytes(&x, Id::ED25519).ok()) else {
+        return false;
+    };
+
+    Verifier::new_without_digest(&key)
+        .and_then(|mut x| x.verify_oneshot(signature, params.as_bytes()))
+        .unwrap_or(false)
+}
+
+/// Checks the signature and expiry of a secured key, given without its "secured." prefix, returns the key it acts as
+///
+/// The key has the search role, whatever the role of its parent
+pub async fn resolve_secured_key(search_backend: &dyn SearchBackend, secured_key: &str) -> Result<ApiKey, ErrorTypes> {
+    let (params, sent_signature) = secured_key.split_once('.').ok_or(ErrorTypes::InvalidApiKey)?;
+
+    let decoded: SecuredKeyParams = URL_SAFE_NO_PAD
+        .decode(params)
+        .ok()
+        .and_then(|x| serde_json::from_slice(&x).ok())
+        .ok_or(ErrorTypes::InvalidApiKey)?;
+    let sent_signature = URL_SAFE_NO_PAD.decode(sent_signature).map_err(|_| ErrorTypes::InvalidApiKey)?;
+
+    let parent = search_backend.resolve_api_key(&decoded.parent).await?.ok_or(ErrorTypes::InvalidApiKey)?;
+
+    if !verify(&parent.verifying_key, params, &sent_signature) {
+        return Err(ErrorTypes::InvalidApiKey);
+    }
+
+    if decoded.expires_at.map(|x| x <= now()).unwrap_or(false) {
+        return Err(ErrorTypes::ApiKeyExpired);
+    }
+
+    Ok(ApiKey {
+        role: Role::Search,
+        secured: Some(decoded),
+        ..parent.key
+    })
+}
@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+
+use serde_json::{json, Value};
+
+use super::{ErrorTypes, helpers::{mapping_field_types, exact_value_field}};
+
+/*
+Sort syntax, entries are comma separated and sorted in order:
+    links_count:desc,name:asc
+    _score:desc
+    _geo_distance(_geoloc,-6.12,106.65):asc
+    _distance:asc (distance from around_lat_lng of the geo search)
+
+The order defaults to asc, except for _score which defaults to desc
+
+Named sort strategies are stored in the _meta of the index mappings:
+    {
+        "_meta": {
+            "sort_strategies": {
+                "popular": "links_count:desc,_score:desc"
+            }
+        }
+    }
+and are used by supplying the name as the whole sort value, "relevance" is always available
+*/
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SortOrder {
+    Asc,
+    Desc
+}
+
+impl SortOrder {
+    fn as_str(&self) -> &'static str {
+        match self {
+            SortOrder::Asc => "asc",
+            SortOrder::Desc => "desc",
+        }
+    }
+}
+
+/// A single entry of a sort
+#[derive(Debug, Clone, PartialEq)]
+pub enum SortEntry {
+    Score(SortOrder),
+    Field(String, SortOrder),
+    /// Distance from the point of the geo search
+    Distance(SortOrder),
+    GeoDistance{ field: String, lat: f64, lng: f64, order: SortOrder }
+}
+
+/// Splits by commas that are not inside parentheses
+fn split_entries(input: &str) -> Vec<&str> {
+    let mut entries = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+
+    for (position, c) in input.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                entries.push(input[start..position].trim());
+                start = position + 1;
+            },
+            _ => ()
+        }
+    }
+    entries.push(input[start..].trim());
+
+    entries.into_iter().filter(|x| !x.is_empty()).collect()
+}
+
+fn parse_entry(entry: &str) -> Result<SortEntry, ErrorTypes> {
+    let (target, order) = match entry.rsplit_once(':') {
+        Some((target, order)) => {
+            let order = match order.trim().to_lowercase().as_str() {
+                "asc" => SortOrder::Asc,
+                "desc" => SortOrder::Desc,
+                _ => return Err(ErrorTypes::InvalidSort(format!("unknown order [{}] in [{}], expected asc or desc", order, entry)))
+            };
+            (target.trim(), Some(order))
+        },
+        None => (entry, None)
+    };
+
+    if target == "_score" {
+        return Ok(SortEntry::Score(order.unwrap_or(SortOrder::Desc)));
+    }
+
+    if target == "_distance" {
+        return Ok(SortEntry::Distance(order.unwrap_or(SortOrder::Asc)));
+    }
+
+    if let Some(arguments) = target.strip_prefix("_geo_distance(").and_then(|x| x.strip_suffix(')')) {
+        let arguments: Vec<&str> = arguments.split(',').map(|x| x.trim()).collect();
+        let (field, lat, lng) = match arguments.as_slice() {
+            [field, lat, lng] => match (lat.parse::<f64>(), lng.parse::<f64>()) {
+                (Ok(lat), Ok(lng)) => (field.to_string(), lat, lng),
+                _ => return Err(ErrorTypes::InvalidSort(format!("invalid coordinates in [{}]", entry)))
+            },
+            _ => return Err(ErrorTypes::InvalidSort(format!("[{}] expects _geo_distance(field,lat,lng)", entry)))
+        };
+        return Ok(SortEntry::GeoDistance{ field, lat, lng, order: order.unwrap_or(SortOrder::Asc) });
+    }
+
+    Ok(SortEntry::Field(target.to_string(), order.unwrap_or(SortOrder::Asc)))
+}
+
+/// Parses a sort value into entries, expanding named sort strategies of the index
+pub fn parse_sort(input: &str, mappings: &Value) -> Result<Vec<SortEntry>, ErrorTypes> {
+    let input = input.trim();
+
+    let input = match input {
+        "relevance" => "_score:desc",
+        _ => mappings["_meta"]["sort_strategies"][input].as_str().unwrap_or(input)
+    };
+
+    split_entries(input).into_iter().map(parse_entry).collect()
+}
+
+impl SortEntry {
+    /// Compiles the entry into an Elasticsearch sort clause, checking the field against the index fields
+    /// 
+    /// around is the geo_point field and the point of the geo search, used by _distance
+    fn to_sort(&self, fields: &HashMap<String, String>, around: Option<(&str, f64, f64)>) -> Result<Value, ErrorTypes> {
+        match self {
+            SortEntry::Score(order) => Ok(json!({ "_score": { "order": order.as_str() } })),
+            SortEntry::Field(field, order) => {
+                if !fields.contains_key(field) {
+                    return Err(ErrorTypes::InvalidSort(format!("field [{}] not found", field)));
+                }
+
+                match exact_value_field(fields, field) {
+                    Some(x) => Ok(json!({ x: { "order": order.as_str() } })),
+                    None => Err(ErrorTypes::InvalidSort(format!("field [{}] can not be sorted, text fields require a keyword subfield", field)))
+                }
+            },
+            SortEntry::Distance(order) => match around {
+                Some((field, lat, lng)) => SortEntry::GeoDistance{ field: field.to_string(), lat, lng, order: *order }.to_sort(fields, None),
+                None => Err(ErrorTypes::InvalidSort("_distance requires around_lat_lng".to_string()))
+            },
+            SortEntry::GeoDistance{ field, lat, lng, order } => {
+                match fields.get(field).map(|x| x.as_str()) {
+                    Some("geo_point") => Ok(json!({
+                        "_geo_distance": {
+                            field: { "lat": lat, "lon": lng },
+                            "order": order.as_str(),
+                            "unit": "m"
+                        }
+                    })),
+                    Some(_) => Err(ErrorTypes::InvalidSort(format!("field [{}] is not a geo_point", field))),
+                    None => Err(ErrorTypes::InvalidSort(format!("field [{}] not found", field)))
+                }
+            }
+        }
+    }
+}
+
+/// Builds the sort clause of a search body
+pub fn build_sort(input: &str, mappings: &Value, around: Option<(&str, f64, f64)>) -> Result<Vec<Value>, ErrorTypes> {
+    let fields = mapping_field_types(mappings);
+
+    parse_sort(input, mappings)?
+        .iter()
+        .map(|x| x.to_sort(&fields, around))
+        .collect()
+}
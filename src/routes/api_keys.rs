@@ -0,0 +1,135 @@
+use actix_web::{web::{self, Data}, HttpRequest, HttpResponse};
+use serde_json::json;
+
+use crate::{
+    config::RateLimitConfig,
+    middlewares::auth::API_KEY_HEADER,
+    models::{ErrorTypes, api_keys::{ApiKey, Role, StoredApiKey, now, split_api_key}, backend::SearchBackend, rate_limit::RateLimiter, secured_keys::SecuredKeyParams},
+    routes::api_keys_struct::*
+};
+
+/// Creates an API key, the full key is only returned by this call
+pub async fn create_api_key(data: web::Json<ApiKeyCreate>, search_backend: Data::<dyn SearchBackend>, key: web::ReqData<ApiKey>) -> Result<HttpResponse, ErrorTypes> {
+    key.require(Role::Admin)?;
+
+    let dat = data.into_inner();
+    let (stored, full_key) = StoredApiKey::generate(dat.name, dat.role, dat.indices.unwrap_or_else(|| vec!["*".to_string()]))?;
+
+    key.authorize_key_management(&stored.key.indices)?;
+
+    search_backend.put_api_key(&stored).await?;
+
+    Ok(HttpResponse::Created().json(with_full_key(&stored.key, full_key)))
+}
+
+/// Returns the API keys within the indexes of the API key of the request, without their secrets
+pub async fn list_api_keys(search_backend: Data::<dyn SearchBackend>, key: web::ReqData<ApiKey>) -> Result<HttpResponse, ErrorTypes> {
+    key.require(Role::Admin)?;
+
+    let mut keys = search_backend.list_api_keys().await?;
+    keys.retain(|x| key.covers(&x.indices));
+
+    Ok(HttpResponse::Ok().json(keys))
+}
+
+/// Gives an API key a new secret, the previous full key stops working
+pub async fn rotate_api_key(data: web::Path<ApiKeyId>, search_backend: Data::<dyn SearchBackend>, key: web::ReqData<ApiKey>) -> Result<HttpResponse, ErrorTypes> {
+    key.require(Role::Admin)?;
+
+    let id = data.into_inner().id;
+    let mut stored = search_backend.get_api_key(&id).await?.ok_or(ErrorTypes::ApiKeyNotFound(id))?;
+
+    key.authorize_key_management(&stored.key.indices)?;
+
+    let full_key = stored.new_secret();
+    search_backend.put_api_key(&stored).await?;
+
+    Ok(HttpResponse::Ok().json(with_full_key(&stored.key, full_key)))
+}
+
+/// Revokes an API key
+pub async fn delete_api_key(data: web::Path<ApiKeyId>, search_backend: Data::<dyn SearchBackend>, key: web::ReqData<ApiKey>) -> Result<HttpResponse, ErrorTypes> {
+    key.require(Role::Admin)?;
+
+    let id = data.into_inner().id;
+    let stored = search_backend.get_api_key(&id).await?.ok_or_else(|| ErrorTypes::ApiKeyNotFound(id.clone()))?;
+
+    key.authorize_key_management(&stored.key.indices)?;
+
+    search_backend.delete_api_key(&id).await?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+/// Derives a secured search key from the API key of the request, with filters and limits that the holder can not change
+pub async fn create_secured_api_key(req: HttpRequest, data: web::Json<SecuredApiKeyCreate>, search_backend: Data::<dyn SearchBackend>, key: web::ReqData<ApiKey>) -> Result<HttpResponse, ErrorTypes> {
+    key.require(Role::Search)?;
+
+    let parent = search_backend
+        .resolve_api_key(&key.id)
+        .await?
+        .ok_or_else(|| ErrorTypes::InvalidApiKeyRequest("secured keys can only be derived from a stored API key".to_string()))?;
+
+    let dat = data.into_inner();
+    let expires_at = dat.expires_in
+        .map(|x| now().checked_add(x).ok_or_else(|| ErrorTypes::InvalidApiKeyRequest(format!("expires_in of {} seconds is too large", x))))
+        .transpose()?;
+
+    let params = SecuredKeyParams {
+        parent: parent.key.id.clone(),
+        filters: dat.filters.filter(|x| !x.trim().is_empty()),
+        indices: dat.indices,
+        expires_at,
+        rate_limit: dat.rate_limit
+    };
+
+    params.validate()?;
+
+    // Signed with the secret of the request, which the auth middleware matched to the parent key
+    let secret = req.headers()
+        .get(API_KEY_HEADER)
+        .and_then(|x| x.to_str().ok())
+        .and_then(|x| split_api_key(x.trim()))
+        .map(|(_, secret)| secret)
+        .ok_or(ErrorTypes::InvalidApiKey)?;
+
+    let mut body = json!(params);
+    body["key"] = json!(params.sign(secret));
+
+    Ok(HttpResponse::Created().json(body))
+}
+
+/// Returns the requests of the API key of the request in the current month, admins can ask for another key
+///
+/// Requests are counted in memory by the server instance answering, since it started when that was during the month
+pub async fn get_usage(query: web::Query<UsageQuery>, limiter: Data<RateLimiter>, config: Data<RateLimitConfig>, key: web::ReqData<ApiKey>) -> Result<HttpResponse, ErrorTypes> {
+    key.require(Role::Search)?;
+
+    let key_id = match query.into_inner().key {
+        Some(x) if x != key.id => {
+            key.require(Role::Admin)?;
+            x
+        },
+        _ => key.id.clone()
+    };
+
+    let (month, operations, counted_since) = limiter.usage(&key_id);
+    let quota = config.monthly_quota.filter(|_| config.enabled);
+
+    Ok(HttpResponse::Ok().json(json!({
+        "key": key_id,
+        "month": month,
+        "operations": operations,
+        "quota": quota,
+        "remaining": quota.map(|x| x.saturating_sub(operations)),
+        "counted_by": "instance",
+        "counted_since": counted_since
+    })))
+}
+
+fn with_full_key(key: &ApiKey, full_key: String) -> serde_json::Value {
+    let mut body = json!(key);
+    body["key"] = json!(full_key);
+
+    body
+}
@@ -0,0 +1,36 @@
+use serde::Deserialize;
+
+use crate::models::api_keys::Role;
+
+/// Used for Post: Keys
+#[derive(Deserialize)]
+pub struct ApiKeyCreate {
+    pub name: String,
+    pub role: Role,
+    /// Defaults to every index
+    pub indices: Option<Vec<String>>
+}
+
+/// Used for Post: Rotate key, Delete: Key
+#[derive(Deserialize)]
+pub struct ApiKeyId {
+    pub id: String
+}
+
+/// Used for Post: Secured key
+#[derive(Deserialize)]
+pub struct SecuredApiKeyCreate {
+    pub filters: Option<String>,
+    pub indices: Option<Vec<String>>,
+    /// Seconds until the key expires
+    pub expires_in: Option<u64>,
+    /// Most searches per minute
+    pub rate_limit: Option<u32>
+}
+
+/// Used for Get: Usage
+#[derive(Deserialize)]
+pub struct UsageQuery {
+    /// Id of another API key, admin only
+    pub key: Option<String>
+}
@@ -0,0 +1,25 @@
+use actix_web::{web::{Data, ReqData}, HttpResponse};
+use serde_json::json;
+
+use crate::models::{ErrorTypes, api_keys::{ApiKey, Role}, backend::SearchBackend, metrics::METRICS};
+
+/// Returns the health of every Elasticsearch node and how many of them are alive
+pub async fn node_health(search_backend: Data::<dyn SearchBackend>, key: ReqData<ApiKey>) -> Result<HttpResponse, ErrorTypes> {
+    key.require(Role::Admin)?;
+
+    let nodes = search_backend.node_health();
+    let alive = nodes.iter().filter(|x| x.alive).count();
+
+    Ok(HttpResponse::Ok().json(json!({
+        "alive": alive,
+        "total": nodes.len(),
+        "nodes": nodes
+    })))
+}
+
+/// Returns the metrics of the server in the Prometheus text format
+pub async fn metrics() -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4; charset=utf-8")
+        .body(METRICS.render())
+}
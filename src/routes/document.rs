@@ -1,39 +1,98 @@
 use actix_web::{web::{self, Data}, HttpResponse};
 use serde_json::json;
-use crate::{EClient, routes::{str_or_default_if_exists_in_vec, document_struct::*}};
+use crate::{models::{ErrorTypes, api_keys::{ApiKey, Role}, backend::SearchBackend, documents::{BulkAction, SearchQuery}}, routes::{str_or_default_if_exists_in_vec, document_struct::*}};
 
 /// Inserts a new document, with 3 dynamic modes: true, false, strict
-pub async fn create_document(data: web::Json<DocumentCreate>, elasticsearch_client: Data::<EClient>) -> HttpResponse {  
+pub async fn create_document(data: web::Json<DocumentCreate>, search_backend: Data::<dyn SearchBackend>, key: web::ReqData<ApiKey>) -> Result<HttpResponse, ErrorTypes> {  
     let dat = data.into_inner();
+    key.authorize(Role::Write, &dat.index)?;
     
-    let set_dynamic_mode = match dat.dynamic_mode{
-        Some (x) => Some(str_or_default_if_exists_in_vec(&x, vec!["true".to_string(), "false".to_string(), "strict".to_string()], "strict")),
-        None => None
-    };
+    let set_dynamic_mode = dat.dynamic_mode.map(|x| str_or_default_if_exists_in_vec(&x, vec!["true".to_string(), "false".to_string(), "strict".to_string()], "strict"));
 
-    elasticsearch_client.insert_document(&dat.index, dat.data, set_dynamic_mode).await
+    let document_id = search_backend.insert_document(&dat.index, dat.data, set_dynamic_mode).await?;
+
+    Ok(HttpResponse::Created().json(json!({"document_id": document_id})))
+}
+
+/// Runs multiple index, create, update and delete operations on an index using the bulk api
+pub async fn bulk_documents(data: web::Json<MultipleDocumentCreate>, search_backend: Data::<dyn SearchBackend>, key: web::ReqData<ApiKey>) -> Result<HttpResponse, ErrorTypes> {
+    let dat = data.into_inner();
+    key.authorize(Role::Write, &dat.index)?;
+
+    let set_dynamic_mode = dat.dynamic_mode.map(|x| str_or_default_if_exists_in_vec(&x, vec!["true".to_string(), "false".to_string(), "strict".to_string()], "strict"));
+
+    let mut actions = Vec::with_capacity(dat.data.len());
+    for (position, doc) in dat.data.into_iter().enumerate() {
+        let action = match doc.action.unwrap_or_else(|| "index".to_string()).to_lowercase().as_str() {
+            "index" => doc.data.map(|data| BulkAction::Index { document_id: doc.document_id, data }),
+            "create" => doc.document_id.zip(doc.data).map(|(document_id, data)| BulkAction::Create { document_id, data }),
+            "update" => doc.document_id.zip(doc.data).map(|(document_id, data)| BulkAction::Update { document_id, data }),
+            "delete" => doc.document_id.map(|document_id| BulkAction::Delete { document_id }),
+            _ => None
+        };
+
+        match action {
+            Some(x) => actions.push(x),
+            None => return Err(ErrorTypes::InvalidBulkOperation(position))
+        }
+    }
+
+    let summary = search_backend.bulk_documents(&dat.index, actions, set_dynamic_mode).await?;
+
+    Ok(HttpResponse::Ok().json(summary))
 }
 
 /// Returns a list of documents from index, post method
-pub async fn post_search(data: web::Json<DocumentSearch>, elasticsearch_client: Data::<EClient>) -> HttpResponse {
-    elasticsearch_client.search_index(&data.index, data.search_term.clone(), data.search_in.clone(), data.return_fields.clone(), data.from, data.count).await
+pub async fn post_search(data: web::Json<DocumentSearch>, search_backend: Data::<dyn SearchBackend>, key: web::ReqData<ApiKey>) -> Result<HttpResponse, ErrorTypes> {
+    let dat = data.into_inner();
+    let secured_filters = key.authorize_search(&dat.index)?;
+
+    let query = SearchQuery { secured_filters, ..dat.query.into() };
+    let result = search_backend.search_index(&dat.index, query).await?;
+
+    Ok(HttpResponse::Ok().json(result))
 }
 
 /// Returns a list of documents from index
-pub async fn search(data: web::Path<GetDocumentSearchIndex>, query: web::Query<GetDocumentSearchQuery>, elasticsearch_client: Data::<EClient>) -> HttpResponse {
-    elasticsearch_client.search_index(&data.index, query.search_term.clone(), query.search_in.clone(), query.return_fields.clone(), query.from, query.count).await
+pub async fn search(data: web::Path<GetDocumentSearchIndex>, query: web::Query<GetDocumentSearchQuery>, search_backend: Data::<dyn SearchBackend>, key: web::ReqData<ApiKey>) -> Result<HttpResponse, ErrorTypes> {
+    let secured_filters = key.authorize_search(&data.index)?;
+
+    let query = SearchQuery { secured_filters, ..query.into_inner().into() };
+    let result = search_backend.search_index(&data.index, query).await?;
+
+    Ok(HttpResponse::Ok().json(result))
+}
+
+/// Returns the values of a facet matching the facet query, counted under the results of the search
+pub async fn search_facet_values(path: web::Path<FacetSearchPath>, data: web::Json<FacetSearch>, search_backend: Data::<dyn SearchBackend>, key: web::ReqData<ApiKey>) -> Result<HttpResponse, ErrorTypes> {
+    let path = path.into_inner();
+    let secured_filters = key.authorize_search(&path.index)?;
+
+    let mut dat = data.into_inner();
+    let facet_query = dat.facet_query.take();
+
+    let query = SearchQuery { secured_filters, ..dat.into() };
+    let result = search_backend.search_facet_values(&path.index, &path.field, facet_query, query).await?;
+
+    Ok(HttpResponse::Ok().json(result))
 }
 
 /// Returns a specific document
-pub async fn get_document(data: web::Path<DocById>, return_fields: web::Query<ReturnFields>, elasticsearch_client: Data::<EClient>) -> HttpResponse {
+pub async fn get_document(data: web::Path<DocById>, return_fields: web::Query<ReturnFields>, search_backend: Data::<dyn SearchBackend>, key: web::ReqData<ApiKey>) -> Result<HttpResponse, ErrorTypes> {
     let dat = data.into_inner();
+    key.authorize(Role::Search, &dat.index)?;
+
     let fields_to_return = return_fields.into_inner().return_fields;
 
-    elasticsearch_client.get_document(dat.index, dat.document_id, fields_to_return).await
+    let document = search_backend.get_document(&dat.index, &dat.document_id, fields_to_return).await?;
+
+    Ok(HttpResponse::Ok().json(document))
 }
 
 /// Updates document on index
-pub async fn update_document(data: web::Json<DocumentUpdate>, elasticsearch_client: Data::<EClient>) -> HttpResponse {
+pub async fn update_document(data: web::Json<DocumentUpdate>, search_backend: Data::<dyn SearchBackend>, key: web::ReqData<ApiKey>) -> Result<HttpResponse, ErrorTypes> {
+    key.authorize(Role::Write, &data.index)?;
+
     // Update document on index
 
     // doc is required for updating index, read:
@@ -42,11 +101,17 @@ pub async fn update_document(data: web::Json<DocumentUpdate>, elasticsearch_clie
         "doc": data.data.clone()
     });
 
-    elasticsearch_client.update_document(&data.index, &data.document_id, doc).await
+    search_backend.update_document(&data.index, &data.document_id, doc).await?;
+
+    Ok(HttpResponse::Ok().finish())
 }
 
 /// Deletes document in index
-pub async fn delete_document(document_to_delete: web::Path<DocumentDelete>, elasticsearch_client: Data::<EClient>) -> HttpResponse {
+pub async fn delete_document(document_to_delete: web::Path<DocumentDelete>, search_backend: Data::<dyn SearchBackend>, key: web::ReqData<ApiKey>) -> Result<HttpResponse, ErrorTypes> {
     let dat = document_to_delete.into_inner();
-    elasticsearch_client.delete_document(&dat.index, &dat.document_id).await
+    key.authorize(Role::Write, &dat.index)?;
+
+    search_backend.delete_document(&dat.index, &dat.document_id).await?;
+
+    Ok(HttpResponse::Ok().finish())
 }
\ No newline at end of file
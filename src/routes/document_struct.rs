@@ -1,16 +1,15 @@
 use serde::Deserialize;
 use serde_json::Value;
 
+use crate::models::documents::SearchQuery;
+
 
 /// Used for Post: Search
 #[derive(Deserialize)]
 pub struct DocumentSearch {
     pub index: String,
-    pub search_term: Option<String>,
-    pub search_in: Option<String>,
-    pub return_fields: Option<String>,
-    pub from: Option<i64>,
-    pub count: Option<i64>
+    #[serde(flatten)]
+    pub query: GetDocumentSearchQuery
 }
 
 /// Used for Get: Search
@@ -26,7 +25,77 @@ pub struct GetDocumentSearchQuery {
     pub search_in: Option<String>,
     pub return_fields: Option<String>,
     pub from: Option<i64>,
-    pub count: Option<i64>
+    pub count: Option<i64>,
+    pub filters: Option<String>,
+    pub facets: Option<String>,
+    pub max_values_per_facet: Option<i64>,
+    pub sort: Option<String>,
+    pub highlight_fields: Option<String>,
+    pub snippet_fields: Option<String>,
+    pub highlight_pre_tag: Option<String>,
+    pub highlight_post_tag: Option<String>,
+    pub geo_field: Option<String>,
+    pub around_lat_lng: Option<String>,
+    pub around_radius: Option<i64>,
+    pub inside_bounding_box: Option<String>,
+    pub inside_polygon: Option<String>,
+    pub cursor: Option<String>
+}
+
+impl From<GetDocumentSearchQuery> for SearchQuery {
+    fn from(query: GetDocumentSearchQuery) -> Self {
+        SearchQuery {
+            search_term: query.search_term,
+            search_in: query.search_in,
+            return_fields: query.return_fields,
+            from: query.from,
+            count: query.count,
+            filters: query.filters,
+            facets: query.facets,
+            max_values_per_facet: query.max_values_per_facet,
+            sort: query.sort,
+            highlight_fields: query.highlight_fields,
+            snippet_fields: query.snippet_fields,
+            highlight_pre_tag: query.highlight_pre_tag,
+            highlight_post_tag: query.highlight_post_tag,
+            geo_field: query.geo_field,
+            around_lat_lng: query.around_lat_lng,
+            around_radius: query.around_radius,
+            inside_bounding_box: query.inside_bounding_box,
+            inside_polygon: query.inside_polygon,
+            cursor: query.cursor,
+            secured_filters: None
+        }
+    }
+}
+
+/// Used for Post: Facet Search
+#[derive(Deserialize)]
+pub struct FacetSearchPath {
+    pub index: String,
+    pub field: String
+}
+
+/// Used for Post: Facet Search
+#[derive(Deserialize)]
+pub struct FacetSearch {
+    pub facet_query: Option<String>,
+    pub search_term: Option<String>,
+    pub search_in: Option<String>,
+    pub filters: Option<String>,
+    pub max_values_per_facet: Option<i64>
+}
+
+impl From<FacetSearch> for SearchQuery {
+    fn from(query: FacetSearch) -> Self {
+        SearchQuery {
+            search_term: query.search_term,
+            search_in: query.search_in,
+            filters: query.filters,
+            max_values_per_facet: query.max_values_per_facet,
+            ..Default::default()
+        }
+    }
 }
 
 /// Used for Get: Document
@@ -50,14 +119,24 @@ pub struct DocumentCreate{
     pub dynamic_mode: Option<String>
 }
 
-/// Used for Post: Document_multiple
+/// Used for Post: Document_bulk
 #[derive(Deserialize)]
 pub struct MultipleDocumentCreate{
     pub index: String,
-    pub data: Vec<Value>,
+    pub data: Vec<BulkDocument>,
     pub dynamic_mode: Option<String>
 }
 
+/// Used for Post: Document_bulk, a single operation of the batch
+/// 
+/// action is one of index, create, update, delete, defaults to index
+#[derive(Deserialize)]
+pub struct BulkDocument{
+    pub action: Option<String>,
+    pub document_id: Option<String>,
+    pub data: Option<Value>
+}
+
 /// Used for Put: Document
 #[derive(Deserialize)]
 pub struct DocumentUpdate {
@@ -0,0 +1,56 @@
+use actix_web::{web::Data, HttpResponse};
+use serde_json::{json, Value};
+
+use crate::models::{backend::SearchBackend, health::HealthState};
+
+/// The server is running, whatever the state of the cluster
+pub async fn health_live() -> HttpResponse {
+    HttpResponse::Ok().json(json!({"status": "up"}))
+}
+
+/// Whether the server can answer requests, from the health cached by the monitor so that probes never wait on the cluster
+pub async fn health_ready(search_backend: Data::<dyn SearchBackend>) -> HttpResponse {
+    let (state, checked_at) = search_backend.cached_health();
+
+    let reason = match &state {
+        HealthState::Up(x) if x.is_ready() => None,
+        HealthState::Up(x) => Some(format!("Elasticsearch cluster status is {}", x.status)),
+        // The cluster answers requests, only its health is unknown
+        HealthState::Degraded(x) => return HttpResponse::Ok().json(json!({"status": "degraded", "reason": x, "checked_at": checked_at})),
+        HealthState::Down(x) => Some(x.clone()),
+        HealthState::Unknown => Some("Elasticsearch cluster health not checked yet".to_string())
+    };
+
+    match reason {
+        None => HttpResponse::Ok().json(json!({"status": "ready", "checked_at": checked_at})),
+        Some(reason) => HttpResponse::ServiceUnavailable().json(json!({"status": "not_ready", "reason": reason, "checked_at": checked_at}))
+    }
+}
+
+/// Asks the cluster for its health now, with the nodes the server connects to
+pub async fn health_deep(search_backend: Data::<dyn SearchBackend>) -> HttpResponse {
+    let nodes = search_backend.node_health();
+    let alive = nodes.iter().filter(|x| x.alive).count();
+    let nodes = json!({"alive": alive, "total": nodes.len()});
+
+    match search_backend.check_health().await {
+        Ok(cluster) => {
+            let body = json!({
+                "status": if cluster.is_ready() { "ready" } else { "not_ready" },
+                "cluster": cluster,
+                "nodes": nodes
+            });
+
+            match cluster.is_ready() {
+                true => HttpResponse::Ok().json(body),
+                false => HttpResponse::ServiceUnavailable().json(body)
+            }
+        },
+        Err(x) => HttpResponse::ServiceUnavailable().json(json!({
+            "status": "not_ready",
+            "reason": x.to_string(),
+            "cluster": Value::Null,
+            "nodes": nodes
+        }))
+    }
+}
@@ -1,30 +1,120 @@
-use actix_web::{web::{self, Data}, HttpResponse};
-use crate::{EClient, routes::{index_struct::*}};
+use actix_multipart::Multipart;
+use actix_web::{web::{self, Data}, HttpResponse, ResponseError};
+use futures_util::StreamExt;
+use crate::{models::{ErrorTypes, api_keys::{ApiKey, Role}, backend::SearchBackend, export::ExportFormat, import::{ImportFormat, import_documents}}, routes::{str_or_default_if_exists_in_vec, index_struct::*}};
 
 
-/// Creates a new dynamic index
-pub async fn create_index(data: web::Json<IndexCreate>, elasticsearch_client: Data::<EClient>) -> HttpResponse {
-    elasticsearch_client.create_index(&data.index).await
+/// Creates a new index, mapped from its schema or dynamically when it has none
+pub async fn create_index(data: web::Json<IndexCreate>, search_backend: Data::<dyn SearchBackend>, key: web::ReqData<ApiKey>) -> Result<HttpResponse, ErrorTypes> {
+    let dat = data.into_inner();
+    key.authorize(Role::Admin, &dat.index)?;
+
+    search_backend.create_index(&dat.index, dat.geo_fields.unwrap_or_default(), dat.schema).await?;
+
+    Ok(HttpResponse::Created().finish())
 }
 
 /// Returns list of index if index is not provided, returns specified index if provided
-pub async fn get_index(index: web::Query<OptionalIndex>, elasticsearch_client: Data::<EClient>) -> HttpResponse {
-    elasticsearch_client.get_index(index.into_inner().index).await
+///
+/// The list only holds the indexes the API key can access
+pub async fn get_index(index: web::Query<OptionalIndex>, search_backend: Data::<dyn SearchBackend>, key: web::ReqData<ApiKey>) -> Result<HttpResponse, ErrorTypes> {
+    let index = index.into_inner().index;
+
+    match &index {
+        Some(x) => key.authorize(Role::Search, x)?,
+        None => key.require(Role::Search)?
+    }
+
+    let mut indices = search_backend.get_index(index).await?;
+
+    if let Some(list) = indices.as_array_mut() {
+        list.retain(|x| x["index"].as_str().map(|name| key.can_access(name)).unwrap_or(false));
+    }
+
+    Ok(HttpResponse::Ok().json(indices))
+}
+
+/// Streams every document of an index as ndjson, csv or json, defaults to ndjson
+pub async fn export_index(index: web::Path<RequiredIndex>, query: web::Query<IndexExport>, search_backend: Data::<dyn SearchBackend>, key: web::ReqData<ApiKey>) -> Result<HttpResponse, ErrorTypes> {
+    let query = query.into_inner();
+
+    let index = index.into_inner().index;
+    key.authorize(Role::Search, &index)?;
+
+    let format = query.format.as_deref().unwrap_or("ndjson").parse::<ExportFormat>()?;
+
+    let body = search_backend.into_inner().export_index(&index, format, query.fields).await?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(format.content_type())
+        .insert_header(("Content-Disposition", format!("attachment; filename=\"{}.{}\"", index, format.extension())))
+        .streaming(body))
+}
+
+/// Imports the documents of an uploaded csv, ndjson or json array file into an index
+///
+/// The format defaults to the extension of the file name
+pub async fn import_index(index: web::Path<RequiredIndex>, query: web::Query<IndexImport>, mut payload: Multipart, search_backend: Data::<dyn SearchBackend>, key: web::ReqData<ApiKey>) -> Result<HttpResponse, ErrorTypes> {
+    key.authorize(Role::Write, &index.index)?;
+
+    let query = query.into_inner();
+
+    // Uses the first part of the form that is a file
+    let field = loop {
+        match payload.next().await {
+            Some(Ok(field)) if field.content_disposition().get_filename().is_some() => break field,
+            Some(Ok(_)) => continue,
+            Some(Err(x)) => return Err(ErrorTypes::InvalidImport(x.to_string())),
+            None => return Err(ErrorTypes::InvalidImport("no file uploaded".to_string()))
+        }
+    };
+
+    let format = match query.format {
+        Some(x) => x,
+        None => field.content_disposition()
+            .get_filename()
+            .and_then(|x| x.rsplit_once('.'))
+            .map(|(_, extension)| extension.to_string())
+            .unwrap_or_default()
+    };
+
+    let format = format.parse::<ImportFormat>()?;
+
+    let set_dynamic_mode = query.dynamic_mode.map(|x| str_or_default_if_exists_in_vec(&x, vec!["true".to_string(), "false".to_string(), "strict".to_string()], "strict"));
+
+    let summary = import_documents(search_backend.get_ref(), &index.into_inner().index, format, query.types, set_dynamic_mode, field).await?;
+
+    match &summary.error {
+        Some(x) => Ok(HttpResponse::build(x.status_code()).json(summary)),
+        None => Ok(HttpResponse::Ok().json(summary))
+    }
 }
 
 /// Returns the mappings of an index
-pub async fn get_mapping(index: web::Path<RequiredIndex>, elasticsearch_client: Data::<EClient>) -> HttpResponse {
-    elasticsearch_client.get_index_mappings(index.into_inner().index).await
+pub async fn get_mapping(index: web::Path<RequiredIndex>, search_backend: Data::<dyn SearchBackend>, key: web::ReqData<ApiKey>) -> Result<HttpResponse, ErrorTypes> {
+    key.authorize(Role::Search, &index.index)?;
+
+    let mappings = search_backend.get_index_mappings(&index.into_inner().index).await?;
+
+    Ok(HttpResponse::Ok().json(mappings))
 }
 
 /// Updates the mappings of an index
-pub async fn update_mapping(data: web::Json<IndexMappingUpdate>, elasticsearch_client: Data::<EClient>) -> HttpResponse {
+pub async fn update_mapping(data: web::Json<IndexMappingUpdate>, search_backend: Data::<dyn SearchBackend>, key: web::ReqData<ApiKey>) -> Result<HttpResponse, ErrorTypes> {
+    key.authorize(Role::Admin, &data.index)?;
+
     // Updates the mappings of an index, including its datatypes
-    elasticsearch_client.update_index_mappings(&data.index, data.mappings.clone()).await
+    search_backend.update_index_mappings(&data.index, data.mappings.clone()).await?;
+
+    Ok(HttpResponse::Ok().finish())
 }
 
 /// Deletes an index
-pub async fn delete_index(index_to_delete: web::Path<IndexDelete>, elasticsearch_client: Data::<EClient>) -> HttpResponse {
+pub async fn delete_index(index_to_delete: web::Path<IndexDelete>, search_backend: Data::<dyn SearchBackend>, key: web::ReqData<ApiKey>) -> Result<HttpResponse, ErrorTypes> {
     let dat = index_to_delete.into_inner();
-    elasticsearch_client.delete_index(&dat.index).await
+    key.authorize(Role::Admin, &dat.index)?;
+
+    search_backend.delete_index(&dat.index).await?;
+
+    Ok(HttpResponse::Ok().finish())
 }
\ No newline at end of file
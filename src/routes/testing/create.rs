@@ -6,33 +6,39 @@ JSON Data Format For Creating new Index:
     }
 */
 
-use actix_web::{web::Data, HttpResponse};
+use actix_web::{web::{Data, ReqData}, HttpResponse};
 use serde_json::Value;
 
-use crate::models::EClient;
+use crate::models::{ErrorTypes, api_keys::{ApiKey, Role}, backend::SearchBackend, documents::BulkAction};
 
 // Temporary hardcode to add test data
 #[allow(unused_must_use)]
-pub async fn hardcoded_data_for_testing(elasticsearch_client: Data::<EClient>) -> HttpResponse{
-
+pub async fn hardcoded_data_for_testing(search_backend: Data::<dyn SearchBackend>, key: ReqData<ApiKey>) -> Result<HttpResponse, ErrorTypes>{
     const INDEX: &str = "airplanes_v3";
-        
-    let index_exists = elasticsearch_client.create_index(INDEX).await;
 
-    println!("{:#?}", index_exists);
+    key.authorize(Role::Admin, INDEX)?;
+
+    let index_exists = search_backend.create_index(INDEX, vec!["_geoloc".to_string()], None).await;
+
+    tracing::info!(index = INDEX, result = ?index_exists, "Created test index");
 
-    // No question mark for await, https://github.com/actix/actix-web/wiki/FAQ
-    let resp = reqwest::Client::new()
+    let download_failed = |x: reqwest::Error| {
+        tracing::error!(error = %x, "Failed to download the test data");
+        ErrorTypes::Unknown
+    };
+
+    let airports = reqwest::Client::new()
         .get("https://raw.githubusercontent.com/algolia/datasets/master/airports/airports.json")
         .send()
-        .await;
-
-    let x = resp.unwrap();
+        .await
+        .and_then(|x| x.error_for_status())
+        .map_err(download_failed)?
+        .json::<Vec<Value>>()
+        .await
+        .map_err(download_failed)?;
 
-    let y = x.json::<Vec<Value>>().await.unwrap();
-    for data in y {
-        elasticsearch_client.insert_document(INDEX, data, None).await;
-    }
+    let actions = airports.into_iter().map(|data| BulkAction::Index { document_id: None, data }).collect();
+    search_backend.bulk_documents(INDEX, actions, None).await;
 
-    HttpResponse::Ok().finish()
+    Ok(HttpResponse::Ok().finish())
 }
\ No newline at end of file
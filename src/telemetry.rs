@@ -0,0 +1,99 @@
+use std::collections::HashMap;
+
+use actix_web::http::header::HeaderMap;
+use opentelemetry::{
+    Context,
+    propagation::{Extractor, TextMapPropagator},
+    trace::{SpanContext, TraceContextExt, TraceFlags, TraceState, TracerProvider}
+};
+use opentelemetry_otlp::{ExporterBuildError, SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{
+    Resource,
+    propagation::TraceContextPropagator,
+    trace::{IdGenerator, RandomIdGenerator, Sampler, SdkTracerProvider}
+};
+use tracing::{Level, Span, Subscriber};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::{EnvFilter, Layer, filter::Targets, layer::SubscriberExt, registry::LookupSpan, util::SubscriberInitExt};
+
+use crate::config::{Config, TracingConfig};
+
+/// Header of the W3C trace context
+pub const TRACEPARENT: &str = "traceparent";
+
+/// Logs to stderr at log.level and records the spans of the crate, exported over OTLP when tracing.otlp_endpoint is set
+///
+/// Also receives the records of the log crate. The provider has to be shut down on exit to export the last spans
+pub fn init(config: &Config) -> Result<SdkTracerProvider, ExporterBuildError> {
+    let provider = tracer_provider(&config.tracing)?;
+
+    tracing_subscriber::registry()
+        .with(tracing_subscriber::fmt::layer().with_filter(EnvFilter::new(&config.log.level)))
+        .with(span_layer(&provider))
+        .init();
+
+    Ok(provider)
+}
+
+/// Provider of the spans, sampled by trace id unless the caller already decided
+pub fn tracer_provider(config: &TracingConfig) -> Result<SdkTracerProvider, ExporterBuildError> {
+    let mut builder = SdkTracerProvider::builder()
+        .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(config.sample_ratio))))
+        .with_resource(Resource::builder().with_service_name(config.service_name.clone()).build());
+
+    if let Some(endpoint) = &config.otlp_endpoint {
+        let exporter = SpanExporter::builder().with_http().with_endpoint(endpoint).build()?;
+        builder = builder.with_batch_exporter(exporter);
+    }
+
+    Ok(builder.build())
+}
+
+/// Turns the spans of the crate into OpenTelemetry spans, whatever the log level
+pub fn span_layer<S>(provider: &SdkTracerProvider) -> impl Layer<S>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>
+{
+    tracing_opentelemetry::layer()
+        .with_tracer(provider.tracer("dps"))
+        .with_filter(Targets::new().with_target("dps", Level::INFO))
+}
+
+/// Trace context sent by the caller, an empty context when there is none or it is malformed
+pub fn extract_context(headers: &HeaderMap) -> Context {
+    TraceContextPropagator::new().extract(&HeaderExtractor(headers))
+}
+
+/// traceparent to send along the requests made within a span
+///
+/// Spans are only recorded once a subscriber is set, requests made outside of them get an unsampled trace of their own
+pub fn traceparent(span: &Span) -> String {
+    let context = span.context();
+
+    let context = match context.span().span_context().is_valid() {
+        true => context,
+        false => {
+            let ids = RandomIdGenerator::default();
+            let span_context = SpanContext::new(ids.new_trace_id(), ids.new_span_id(), TraceFlags::default(), false, TraceState::default());
+
+            Context::new().with_remote_span_context(span_context)
+        }
+    };
+
+    let mut headers = HashMap::new();
+    TraceContextPropagator::new().inject_context(&context, &mut headers);
+
+    headers.remove(TRACEPARENT).unwrap_or_default()
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|x| x.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(|x| x.as_str()).collect()
+    }
+}
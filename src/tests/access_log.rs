@@ -0,0 +1,130 @@
+use std::{io::Write, sync::{Arc, Mutex}};
+
+use actix_web::{test::{self, TestRequest}, web::Data, App, dev::{ServiceFactory, ServiceRequest, ServiceResponse}};
+use serde_json::Value;
+
+use crate::{
+    config::{AccessLogConfig, AuthConfig, CorsConfig},
+    middlewares::{access_log::AccessLogger, auth::API_KEY_HEADER},
+    models::{backend::SearchBackend, memory::InMemoryBackend}
+};
+
+use super::{auth::ADMIN_KEY, call, seed_airports};
+
+/// Keeps what the access log writes
+#[derive(Clone, Default)]
+struct Output(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Output {
+    fn lines(&self) -> Vec<Value> {
+        String::from_utf8(self.0.lock().unwrap().clone())
+            .unwrap()
+            .lines()
+            .map(|x| serde_json::from_str(x).unwrap())
+            .collect()
+    }
+}
+
+fn logged_app(auth: AuthConfig, config: AccessLogConfig, output: &Output) -> App<impl ServiceFactory<ServiceRequest, Config = (), Response = ServiceResponse, Error = actix_web::Error, InitError = ()>> {
+    let search_backend: Arc<dyn SearchBackend> = Arc::new(InMemoryBackend::new());
+
+    App::new()
+        .app_data(Data::from(search_backend))
+        .app_data(Data::new(auth))
+        .app_data(Data::new(config))
+        .app_data(Data::new(AccessLogger::new(Box::new(output.clone()))))
+        .configure(crate::api(CorsConfig::default()))
+}
+
+fn auth() -> AuthConfig {
+    AuthConfig { enabled: true, admin_key: Some(ADMIN_KEY.to_string()) }
+}
+
+#[actix_web::test]
+async fn request_ids_are_echoed_in_responses_and_errors() {
+    let app = test::init_service(logged_app(auth(), AccessLogConfig::default(), &Output::default())).await;
+
+    let resp = test::call_service(&app, TestRequest::get().uri("/api/welcome").to_request()).await;
+    let generated = resp.headers().get("X-Request-Id").unwrap().to_str().unwrap().to_string();
+    assert_eq!(generated.len(), 32);
+
+    let resp = test::call_service(&app, TestRequest::get().uri("/api/welcome").insert_header(("X-Request-Id", "lb-7f3a.1")).to_request()).await;
+    assert_eq!(resp.headers().get("X-Request-Id").unwrap(), "lb-7f3a.1");
+
+    // Ids that could break the log are replaced
+    let resp = test::call_service(&app, TestRequest::get().uri("/api/welcome").insert_header(("X-Request-Id", "a b\"c")).to_request()).await;
+    assert_ne!(resp.headers().get("X-Request-Id").unwrap(), "a b\"c");
+
+    let resp = test::call_service(&app, TestRequest::get().uri("/api/index").insert_header(("X-Request-Id", "missing-key")).to_request()).await;
+    assert_eq!(resp.status(), 401);
+    assert_eq!(resp.headers().get("X-Request-Id").unwrap(), "missing-key");
+    assert_eq!(resp.headers().get("Content-Type").unwrap(), "application/json");
+
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["error"], "Missing API key, send it in the X-API-Key header");
+    assert_eq!(body["request_id"], "missing-key");
+}
+
+#[actix_web::test]
+async fn every_request_is_logged_as_a_json_line() {
+    let output = Output::default();
+    let app = test::init_service(logged_app(AuthConfig { enabled: false, admin_key: None }, AccessLogConfig::default(), &output)).await;
+    seed_airports(&app).await;
+
+    let (status, _) = call(&app, TestRequest::get()
+        .uri("/api/search/airports?search%5Fterm=jakarta&facet_query=indo&count=2&filters=city%3AJakarta")
+        .insert_header(("X-Request-Id", "search-1"))
+        .to_request()).await;
+    assert_eq!(status, 200);
+
+    let lines = output.lines();
+    // Seeding made two requests
+    assert_eq!(lines.len(), 3);
+
+    let line = &lines[2];
+    assert_eq!(line["request_id"], "search-1");
+    assert_eq!(line["method"], "GET");
+    assert_eq!(line["route"], "/api/search/{index}");
+    assert_eq!(line["path"], "/api/search/airports");
+    assert_eq!(line["query"], "search%5Fterm=[redacted]&facet_query=[redacted]&count=2&filters=[redacted]");
+    assert_eq!(line["index"], "airports");
+    assert_eq!(line["status"], 200);
+    assert_eq!(line["api_key"], "anonymous");
+    assert!(line["duration_ms"].as_f64().unwrap() >= 0.0);
+    assert!(line["timestamp"].as_str().unwrap().ends_with('Z'));
+    // The in-memory backend does not report took
+    assert!(line["took_ms"].is_null());
+
+    // Indexes sent in the body are logged too
+    assert_eq!(lines[1]["route"], "/api/documents/bulk");
+    assert_eq!(lines[1]["index"], "airports");
+}
+
+#[actix_web::test]
+async fn sampling_and_redaction_are_configurable() {
+    let output = Output::default();
+    let config = AccessLogConfig { sample_ratio: 0.0, ..Default::default() };
+    let app = test::init_service(logged_app(auth(), config, &output)).await;
+
+    let (status, _) = call(&app, TestRequest::get().uri("/api/index").to_request()).await;
+    assert_eq!(status, 401);
+    assert!(output.lines().is_empty());
+
+    let output = Output::default();
+    let config = AccessLogConfig { redact_search_terms: false, ..Default::default() };
+    let app = test::init_service(logged_app(auth(), config, &output)).await;
+
+    call(&app, TestRequest::get().uri("/api/search/missing?search_term=jakarta").insert_header((API_KEY_HEADER, ADMIN_KEY)).to_request()).await;
+    assert_eq!(output.lines()[0]["query"], "search_term=jakarta");
+    assert_eq!(output.lines()[0]["status"], 404);
+}
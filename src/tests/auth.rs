@@ -0,0 +1,423 @@
+use std::sync::Arc;
+
+use actix_http::Request;
+use actix_web::{
+    App,
+    dev::{Service, ServiceFactory, ServiceRequest, ServiceResponse},
+    http::StatusCode,
+    test::{self, TestRequest},
+    web::Data
+};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use openssl::{hash::MessageDigest, pkey::PKey, sign::Signer};
+use serde_json::{json, Value};
+
+use crate::{
+    config::{AuthConfig, CorsConfig, RateLimitConfig},
+    middlewares::auth::API_KEY_HEADER,
+    models::{api_keys::{API_KEYS_INDEX, Role, StoredApiKey, hash}, backend::SearchBackend}
+};
+
+use super::{app_with, call, index_cache::{client, count, node}, seed_airports};
+
+pub const ADMIN_KEY: &str = "an-admin-key-that-is-long-enough-for-tests";
+
+/// The API with authentication enabled and ADMIN_KEY as admin key
+pub fn secured_app() -> App<impl ServiceFactory<ServiceRequest, Config = (), Response = ServiceResponse, Error = actix_web::Error, InitError = ()>> {
+    app_with(AuthConfig { enabled: true, admin_key: Some(ADMIN_KEY.to_string()) }, RateLimitConfig::default())
+}
+
+/// Creates an API key with the admin key, returns the full key
+pub async fn create_key<S>(app: &S, body: Value) -> String
+where
+    S: Service<Request, Response = ServiceResponse, Error = actix_web::Error>
+{
+    let (status, body) = call(app, TestRequest::post().uri("/api/keys").insert_header((API_KEY_HEADER, ADMIN_KEY)).set_json(body).to_request()).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    body["key"].as_str().unwrap().to_string()
+}
+
+#[actix_web::test]
+async fn requests_without_a_valid_key_are_rejected() {
+    let app = test::init_service(secured_app()).await;
+
+    let (status, body) = call(&app, TestRequest::get().uri("/api/index").to_request()).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(body["error"], "Missing API key, send it in the X-API-Key header");
+
+    for key in ["not-a-key", "unknown.secret", &format!("{}x", ADMIN_KEY)] {
+        let (status, body) = call(&app, TestRequest::get().uri("/api/index").insert_header((API_KEY_HEADER, key)).to_request()).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(body["error"], "Invalid API key");
+    }
+
+    // Public route
+    let (status, _) = call(&app, TestRequest::get().uri("/api/welcome").to_request()).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn search_keys_can_only_read_their_indexes() {
+    let app = test::init_service(secured_app()).await;
+
+    let (status, _) = call(&app, TestRequest::post().uri("/api/index").insert_header((API_KEY_HEADER, ADMIN_KEY)).set_json(json!({"index": "airports"})).to_request()).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let (status, _) = call(&app, TestRequest::post().uri("/api/index").insert_header((API_KEY_HEADER, ADMIN_KEY)).set_json(json!({"index": "flights"})).to_request()).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let key = create_key(&app, json!({"name": "website", "role": "search", "indices": ["air*"]})).await;
+
+    let (status, _) = call(&app, TestRequest::get().uri("/api/search/airports").insert_header((API_KEY_HEADER, key.as_str())).to_request()).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, body) = call(&app, TestRequest::get().uri("/api/index").insert_header((API_KEY_HEADER, key.as_str())).to_request()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body.as_array().unwrap().len(), 1);
+    assert_eq!(body[0]["index"], "airports");
+
+    let (status, body) = call(&app, TestRequest::get().uri("/api/search/flights").insert_header((API_KEY_HEADER, key.as_str())).to_request()).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert!(body["error"].as_str().unwrap().ends_with("can not access index [flights]"));
+
+    let (status, body) = call(&app, TestRequest::post().uri("/api/document").insert_header((API_KEY_HEADER, key.as_str())).set_json(json!({"index": "airports", "data": {"name": "Changi Airport"}})).to_request()).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert!(body["error"].as_str().unwrap().ends_with("requires the write role"));
+
+    let (status, _) = call(&app, TestRequest::get().uri("/api/keys").insert_header((API_KEY_HEADER, key.as_str())).to_request()).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+}
+
+#[actix_web::test]
+async fn write_keys_can_change_documents() {
+    let app = test::init_service(secured_app()).await;
+    let key = create_key(&app, json!({"name": "importer", "role": "write"})).await;
+
+    let (status, _) = call(&app, TestRequest::post().uri("/api/index").insert_header((API_KEY_HEADER, key.as_str())).set_json(json!({"index": "airports"})).to_request()).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, _) = call(&app, TestRequest::post().uri("/api/index").insert_header((API_KEY_HEADER, ADMIN_KEY)).set_json(json!({"index": "airports"})).to_request()).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let (status, _) = call(&app, TestRequest::post().uri("/api/document").insert_header((API_KEY_HEADER, key.as_str())).set_json(json!({"index": "airports", "dynamic_mode": "true", "data": {"name": "Changi Airport"}})).to_request()).await;
+    assert_eq!(status, StatusCode::CREATED);
+}
+
+#[actix_web::test]
+async fn rotated_and_revoked_keys_stop_working() {
+    let app = test::init_service(secured_app()).await;
+    let key = create_key(&app, json!({"name": "website", "role": "search"})).await;
+    let id = key.split_once('.').unwrap().0;
+
+    let (status, body) = call(&app, TestRequest::get().uri("/api/keys").insert_header((API_KEY_HEADER, ADMIN_KEY)).to_request()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body[0]["id"], id);
+    assert_eq!(body[0]["indices"], json!(["*"]));
+    assert!(body[0].get("secret_hash").is_none());
+
+    let (status, body) = call(&app, TestRequest::post().uri(&format!("/api/keys/{}/rotate", id)).insert_header((API_KEY_HEADER, ADMIN_KEY)).to_request()).await;
+    assert_eq!(status, StatusCode::OK);
+    let rotated = body["key"].as_str().unwrap().to_string();
+    assert!(body["rotated_at"].is_u64());
+
+    let (status, _) = call(&app, TestRequest::get().uri("/api/index").insert_header((API_KEY_HEADER, key.as_str())).to_request()).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (status, _) = call(&app, TestRequest::get().uri("/api/index").insert_header((API_KEY_HEADER, rotated.as_str())).to_request()).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = call(&app, TestRequest::delete().uri(&format!("/api/keys/{}", id)).insert_header((API_KEY_HEADER, ADMIN_KEY)).to_request()).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = call(&app, TestRequest::get().uri("/api/index").insert_header((API_KEY_HEADER, rotated.as_str())).to_request()).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    let (status, body) = call(&app, TestRequest::delete().uri(&format!("/api/keys/{}", id)).insert_header((API_KEY_HEADER, ADMIN_KEY)).to_request()).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body["error"], format!("API key [{}] not found", id));
+}
+
+#[actix_web::test]
+async fn invalid_key_requests_and_reserved_index() {
+    let app = test::init_service(secured_app()).await;
+
+    let (status, body) = call(&app, TestRequest::post().uri("/api/keys").insert_header((API_KEY_HEADER, ADMIN_KEY)).set_json(json!({"name": " ", "role": "search"})).to_request()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"], "Invalid API key request, name can not be empty");
+
+    let (status, body) = call(&app, TestRequest::get().uri("/api/search/dps-api-keys").insert_header((API_KEY_HEADER, ADMIN_KEY)).to_request()).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body["error"], "Forbidden, index [dps-api-keys] is reserved");
+}
+
+#[actix_web::test]
+async fn index_names_naming_several_indexes_are_rejected() {
+    let app = test::init_service(secured_app()).await;
+
+    let key = create_key(&app, json!({"name": "website", "role": "search", "indices": ["air*"]})).await;
+
+    for index in ["airports,dps-api-keys", "airports,flights", "air*", "air?orts", "-flights", "_all", ".security"] {
+        let (status, body) = call(&app, TestRequest::get().uri(&format!("/api/search/{}", index.replace('?', "%3F"))).insert_header((API_KEY_HEADER, key.as_str())).to_request()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", index);
+        assert!(body["error"].as_str().unwrap().starts_with(&format!("Bad data request, invalid index name [{}]", index)));
+    }
+
+    // Even for keys over every index
+    let (status, _) = call(&app, TestRequest::get().uri("/api/search/airports,dps-api-keys").insert_header((API_KEY_HEADER, ADMIN_KEY)).to_request()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, body) = call(&app, TestRequest::post().uri("/api/keys").insert_header((API_KEY_HEADER, ADMIN_KEY)).set_json(json!({"name": "website", "role": "search", "indices": ["air*,dps-api-keys"]})).to_request()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body["error"].as_str().unwrap().starts_with("Invalid API key request, invalid index pattern [air*,dps-api-keys]"));
+
+    let (status, _) = call(&app, TestRequest::post().uri("/api/keys/secured").insert_header((API_KEY_HEADER, key.as_str())).set_json(json!({"indices": ["airports,flights"]})).to_request()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn admin_keys_only_manage_keys_within_their_indexes() {
+    let app = test::init_service(secured_app()).await;
+
+    let admin = create_key(&app, json!({"name": "tenant a admin", "role": "admin", "indices": ["tenant_a*"]})).await;
+    let other = create_key(&app, json!({"name": "tenant b website", "role": "search", "indices": ["tenant_b"]})).await;
+    let other_id = other.split_once('.').unwrap().0;
+
+    for indices in [Value::Null, json!(["*"]), json!(["tenant_*"]), json!(["tenant_a", "tenant_b"])] {
+        let (status, body) = call(&app, TestRequest::post().uri("/api/keys").insert_header((API_KEY_HEADER, admin.as_str())).set_json(json!({"name": "website", "role": "search", "indices": indices})).to_request()).await;
+        assert_eq!(status, StatusCode::FORBIDDEN, "{}", indices);
+        assert!(body["error"].as_str().unwrap().contains("can not manage keys over indexes"));
+    }
+
+    let (status, body) = call(&app, TestRequest::post().uri("/api/keys").insert_header((API_KEY_HEADER, admin.as_str())).set_json(json!({"name": "website", "role": "search", "indices": ["tenant_a", "tenant_a_shop*"]})).to_request()).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let own_id = body["id"].as_str().unwrap().to_string();
+
+    let (status, _) = call(&app, TestRequest::post().uri(&format!("/api/keys/{}/rotate", other_id)).insert_header((API_KEY_HEADER, admin.as_str())).to_request()).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = call(&app, TestRequest::delete().uri(&format!("/api/keys/{}", other_id)).insert_header((API_KEY_HEADER, admin.as_str())).to_request()).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (status, body) = call(&app, TestRequest::get().uri("/api/keys").insert_header((API_KEY_HEADER, admin.as_str())).to_request()).await;
+    assert_eq!(status, StatusCode::OK);
+    let ids: Vec<&str> = body.as_array().unwrap().iter().map(|x| x["id"].as_str().unwrap()).collect();
+    assert!(ids.contains(&own_id.as_str()) && !ids.contains(&other_id));
+
+    let (status, _) = call(&app, TestRequest::post().uri(&format!("/api/keys/{}/rotate", own_id)).insert_header((API_KEY_HEADER, admin.as_str())).to_request()).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = call(&app, TestRequest::delete().uri(&format!("/api/keys/{}", own_id)).insert_header((API_KEY_HEADER, admin.as_str())).to_request()).await;
+    assert_eq!(status, StatusCode::OK);
+
+    // Nor can they fill indexes outside of their own with test data
+    let (status, body) = call(&app, TestRequest::get().uri("/api/test/add_data").insert_header((API_KEY_HEADER, admin.as_str())).to_request()).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert!(body["error"].as_str().unwrap().contains("airplanes_v3"));
+}
+
+#[actix_web::test]
+async fn disabled_authentication_lets_requests_through() {
+    let app = test::init_service(super::app()).await;
+    seed_airports(&app).await;
+
+    let (status, _) = call(&app, TestRequest::get().uri("/api/search/airports").to_request()).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+/// Creates the shops index with products of two tenants, as the admin
+async fn seed_tenants<S>(app: &S)
+where
+    S: Service<Request, Response = ServiceResponse, Error = actix_web::Error>
+{
+    let data: Vec<Value> = [("1", "acme", "Anvil"), ("2", "acme", "Rocket"), ("3", "globex", "Hammock")]
+        .iter()
+        .map(|(id, tenant, name)| json!({"document_id": id, "data": {"tenant": tenant, "name": name}}))
+        .collect();
+
+    let (status, _) = call(app, TestRequest::post().uri("/api/index").insert_header((API_KEY_HEADER, ADMIN_KEY)).set_json(json!({"index": "shops"})).to_request()).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let (status, _) = call(app, TestRequest::post().uri("/api/documents/bulk").insert_header((API_KEY_HEADER, ADMIN_KEY)).set_json(json!({
+        "index": "shops",
+        "dynamic_mode": "true",
+        "data": data
+    })).to_request()).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+/// Derives a secured key from key, returns the secured key
+async fn create_secured_key<S>(app: &S, key: &str, body: Value) -> String
+where
+    S: Service<Request, Response = ServiceResponse, Error = actix_web::Error>
+{
+    let (status, body) = call(app, TestRequest::post().uri("/api/keys/secured").insert_header((API_KEY_HEADER, key)).set_json(body).to_request()).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    body["key"].as_str().unwrap().to_string()
+}
+
+#[actix_web::test]
+async fn secured_keys_always_apply_their_filters() {
+    let app = test::init_service(secured_app()).await;
+    seed_tenants(&app).await;
+
+    let key = create_key(&app, json!({"name": "storefront", "role": "search", "indices": ["shops"]})).await;
+    let secured = create_secured_key(&app, &key, json!({"filters": "tenant:acme"})).await;
+
+    let (status, body) = call(&app, TestRequest::get().uri("/api/search/shops").insert_header((API_KEY_HEADER, secured.as_str())).to_request()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["total_data"], 2);
+
+    // An OR does not reach the documents of another tenant
+    for (filters, total) in [("tenant:globex", 0), ("tenant:globex OR tenant:acme", 2)] {
+        let (status, body) = call(&app, TestRequest::post().uri("/api/search").insert_header((API_KEY_HEADER, secured.as_str())).set_json(json!({
+            "index": "shops",
+            "filters": filters
+        })).to_request()).await;
+
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["total_data"], total, "{}", filters);
+        assert!(body["data"].as_array().unwrap().iter().all(|x| x["fields"]["tenant"] == json!(["acme"])));
+    }
+
+    // Neither do unbalanced parentheses, the filters are combined once parsed
+    let (status, _) = call(&app, TestRequest::post().uri("/api/search").insert_header((API_KEY_HEADER, secured.as_str())).set_json(json!({
+        "index": "shops",
+        "filters": "tenant:acme) OR (tenant:globex"
+    })).to_request()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, body) = call(&app, TestRequest::post().uri("/api/search/shops/facets/tenant").insert_header((API_KEY_HEADER, secured.as_str())).set_json(json!({})).to_request()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["facet_hits"].as_array().unwrap().len(), 1);
+
+    // Reading documents or exports would skip the filters
+    let (status, _) = call(&app, TestRequest::get().uri("/api/document/shops/3").insert_header((API_KEY_HEADER, secured.as_str())).to_request()).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = call(&app, TestRequest::get().uri("/api/index/shops/export").insert_header((API_KEY_HEADER, secured.as_str())).to_request()).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = call(&app, TestRequest::post().uri("/api/keys/secured").insert_header((API_KEY_HEADER, secured.as_str())).set_json(json!({})).to_request()).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+}
+
+#[actix_web::test]
+async fn secured_keys_can_not_be_tampered_with() {
+    let app = test::init_service(secured_app()).await;
+    seed_tenants(&app).await;
+
+    let key = create_key(&app, json!({"name": "storefront", "role": "write"})).await;
+    let secured = create_secured_key(&app, &key, json!({"filters": "tenant:acme", "indices": ["shops"]})).await;
+
+    // Other filters with the original signature
+    let (_, params, signature) = {
+        let mut parts = secured.splitn(3, '.');
+        (parts.next().unwrap(), parts.next().unwrap(), parts.next().unwrap())
+    };
+    let params: Value = serde_json::from_slice(&URL_SAFE_NO_PAD.decode(params).unwrap()).unwrap();
+    assert_eq!(params["filters"], "tenant:acme");
+
+    let forged = format!("secured.{}.{}", URL_SAFE_NO_PAD.encode(json!({"parent": params["parent"], "filters": "tenant:globex"}).to_string()), signature);
+    let (status, body) = call(&app, TestRequest::get().uri("/api/search/shops").insert_header((API_KEY_HEADER, forged.as_str())).to_request()).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(body["error"], "Invalid API key");
+
+    // What the API keys index holds about the parent is not enough to sign
+    let secret_hash = hash(key.split_once('.').unwrap().1);
+    let params = URL_SAFE_NO_PAD.encode(json!({"parent": params["parent"]}).to_string());
+    let hmac = PKey::hmac(secret_hash.as_bytes()).unwrap();
+    let forged_signature = Signer::new(MessageDigest::sha256(), &hmac).unwrap().sign_oneshot_to_vec(params.as_bytes()).unwrap();
+    let forged = format!("secured.{}.{}", params, URL_SAFE_NO_PAD.encode(forged_signature));
+    let (status, _) = call(&app, TestRequest::get().uri("/api/search/shops").insert_header((API_KEY_HEADER, forged.as_str())).to_request()).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+    // Secured keys only search, whatever the role of their parent
+    let (status, _) = call(&app, TestRequest::delete().uri("/api/document/shops/3").insert_header((API_KEY_HEADER, secured.as_str())).to_request()).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    // Rotating the parent invalidates the keys derived from it
+    let id = key.split_once('.').unwrap().0;
+    let (status, _) = call(&app, TestRequest::post().uri(&format!("/api/keys/{}/rotate", id)).insert_header((API_KEY_HEADER, ADMIN_KEY)).to_request()).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, _) = call(&app, TestRequest::get().uri("/api/search/shops").insert_header((API_KEY_HEADER, secured.as_str())).to_request()).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn secured_key_restrictions() {
+    let app = test::init_service(secured_app()).await;
+    seed_tenants(&app).await;
+
+    let key = create_key(&app, json!({"name": "storefront", "role": "search"})).await;
+
+    let (status, body) = call(&app, TestRequest::post().uri("/api/keys/secured").insert_header((API_KEY_HEADER, ADMIN_KEY)).set_json(json!({})).to_request()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"], "Invalid API key request, secured keys can only be derived from a stored API key");
+
+    let (status, _) = call(&app, TestRequest::post().uri("/api/keys/secured").insert_header((API_KEY_HEADER, key.as_str())).set_json(json!({"filters": "tenant:"})).to_request()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let other_index = create_secured_key(&app, &key, json!({"indices": ["products"]})).await;
+    let (status, _) = call(&app, TestRequest::get().uri("/api/search/shops").insert_header((API_KEY_HEADER, other_index.as_str())).to_request()).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let expired = create_secured_key(&app, &key, json!({"expires_in": 0})).await;
+    let (status, body) = call(&app, TestRequest::get().uri("/api/search/shops").insert_header((API_KEY_HEADER, expired.as_str())).to_request()).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(body["error"], "API key expired");
+
+    let (status, body) = call(&app, TestRequest::post().uri("/api/keys/secured").insert_header((API_KEY_HEADER, key.as_str())).set_json(json!({"expires_in": u64::MAX})).to_request()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"], format!("Invalid API key request, expires_in of {} seconds is too large", u64::MAX));
+
+    let limited = create_secured_key(&app, &key, json!({"rate_limit": 2})).await;
+    for _ in 0..2 {
+        let (status, _) = call(&app, TestRequest::get().uri("/api/search/shops").insert_header((API_KEY_HEADER, limited.as_str())).to_request()).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    let resp = test::call_service(&app, TestRequest::get().uri("/api/search/shops").insert_header((API_KEY_HEADER, limited.as_str())).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(resp.headers().get("Retry-After").unwrap(), "30");
+}
+
+#[actix_web::test]
+async fn api_keys_are_fetched_once_per_ttl() {
+    const AIRPORTS: &str = r#"{"airports":{"mappings":{"properties":{"name":{"type":"keyword"}}},"settings":{}}}"#;
+    const SEARCH: &str = r#"{"took":1,"hits":{"total":{"value":0},"hits":[]}}"#;
+
+    let (stored, key) = StoredApiKey::generate("website".to_string(), Role::Search, vec!["airports".to_string()]).unwrap();
+    let get_key = format!("GET /{}/_doc/{}", API_KEYS_INDEX, stored.key.id);
+    let body: &'static str = Box::leak(json!({"_id": stored.key.id, "found": true, "_source": stored}).to_string().into_boxed_str());
+    let routes: &'static [(&'static str, &'static str, &'static str)] = Box::leak(Box::new([
+        (Box::leak(get_key.clone().into_boxed_str()) as &str, "200 OK", body),
+        ("DELETE /dps-api-keys/_doc/", "200 OK", r#"{"result":"deleted"}"#),
+        ("GET /airports ", "200 OK", AIRPORTS),
+        ("POST /airports/_search", "200 OK", SEARCH)
+    ]));
+
+    let (url, received) = node(routes);
+    let search_backend: Arc<dyn SearchBackend> = Arc::new(client(url, "30"));
+    let app = test::init_service(App::new()
+        .app_data(Data::from(search_backend))
+        .app_data(Data::new(AuthConfig { enabled: true, admin_key: Some(ADMIN_KEY.to_string()) }))
+        .configure(crate::api(CorsConfig::default()))).await;
+
+    for _ in 0..3 {
+        let (status, _) = call(&app, TestRequest::get().uri("/api/search/airports").insert_header((API_KEY_HEADER, key.as_str())).to_request()).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+    assert_eq!(count(&received, &get_key), 1);
+
+    // Secured keys find their parent in the cache as well
+    let secured = create_secured_key(&app, &key, json!({})).await;
+    let (status, _) = call(&app, TestRequest::get().uri("/api/search/airports").insert_header((API_KEY_HEADER, secured.as_str())).to_request()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(count(&received, &get_key), 1);
+
+    // Deleting the key drops it from the cache, the handler itself reads it fresh
+    let (status, _) = call(&app, TestRequest::delete().uri(&format!("/api/keys/{}", stored.key.id)).insert_header((API_KEY_HEADER, ADMIN_KEY)).to_request()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(count(&received, &get_key), 2);
+
+    let (status, _) = call(&app, TestRequest::get().uri("/api/search/airports").insert_header((API_KEY_HEADER, key.as_str())).to_request()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(count(&received, &get_key), 3);
+}
@@ -0,0 +1,198 @@
+use std::{collections::HashMap, fs, net::TcpListener};
+
+use openssl::{
+    asn1::Asn1Time,
+    hash::MessageDigest,
+    pkey::PKey,
+    rsa::Rsa,
+    ssl::{SslAcceptor, SslMethod},
+    x509::{X509Builder, X509NameBuilder}
+};
+
+use crate::{config::{Config, ConfigError}, models::client::EClient};
+
+fn env(vars: &[(&str, &str)]) -> HashMap<String, String> {
+    vars.iter().map(|(key, value)| (key.to_string(), value.to_string())).collect()
+}
+
+fn args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|x| x.to_string()).collect()
+}
+
+#[test]
+fn defaults_match_previous_hardcoded_values() {
+    let config = Config::default();
+    config.validate().unwrap();
+
+    assert_eq!(config.server.bind, "127.0.0.1:8080");
+    assert_eq!(config.elasticsearch.nodes, vec!["http://127.0.0.1:9200"]);
+    assert_eq!((config.index.shards, config.index.replicas), (3, 0));
+    assert_eq!(config.search.default_page_size, 20);
+}
+
+#[test]
+fn flags_override_env_which_overrides_the_file() {
+    let path = std::env::temp_dir().join(format!("dps-config-test-{}.toml", std::process::id()));
+    fs::write(&path, "[server]\nbind = \"0.0.0.0:9000\"\nworkers = 2\n\n[index]\nreplicas = 1\n").unwrap();
+
+    let config = Config::from_sources(
+        env(&[("DPS_CONFIG", path.to_str().unwrap()), ("DPS_WORKERS", "4"), ("DPS_INDEX_REPLICAS", "2")]),
+        args(&["--replicas", "3", "--elasticsearch-nodes=http://es1:9200, http://es2:9200"])
+    ).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(config.server.bind, "0.0.0.0:9000");
+    assert_eq!(config.server.workers, Some(4));
+    assert_eq!(config.index.replicas, 3);
+    assert_eq!(config.elasticsearch.nodes, vec!["http://es1:9200", "http://es2:9200"]);
+}
+
+#[test]
+fn invalid_values_name_the_setting() {
+    let error = Config::from_sources(env(&[("DPS_DEFAULT_PAGE_SIZE", "0")]), vec![]).unwrap_err();
+    assert_eq!(error.to_string(), "Invalid value [0] for search.default_page_size, expected between 1 and 10000");
+
+    let error = Config::from_sources(env(&[]), args(&["--shards", "many"])).unwrap_err();
+    assert_eq!(error.to_string(), "Invalid value [many] for index.shards, expected a number of shards");
+
+    let error = Config::from_sources(env(&[]), args(&["--elasticsearch-nodes", "127.0.0.1:9200"])).unwrap_err();
+    assert!(matches!(error, ConfigError::InvalidValue { key, .. } if key == "elasticsearch.nodes"));
+
+    let error = Config::from_sources(env(&[]), args(&["--elasticsearch-username", "elastic"])).unwrap_err();
+    assert!(matches!(error, ConfigError::InvalidValue { key, .. } if key == "elasticsearch.username"));
+
+    let error = Config::from_sources(env(&[]), args(&["--cors-search-origins", "https://example.com/app"])).unwrap_err();
+    assert!(matches!(error, ConfigError::InvalidValue { key, .. } if key == "cors.search.allowed_origins"));
+
+    let error = Config::from_sources(env(&[]), args(&["--cors-search-credentials", "true"])).unwrap_err();
+    assert!(matches!(error, ConfigError::InvalidValue { key, .. } if key == "cors.search.allow_credentials"));
+
+    let error = Config::from_sources(env(&[]), args(&["--log-level", "verbose"])).unwrap_err();
+    assert!(matches!(error, ConfigError::InvalidValue { key, .. } if key == "log.level"));
+
+    let error = Config::from_sources(env(&[]), args(&["--otlp-endpoint", "127.0.0.1:4318"])).unwrap_err();
+    assert!(matches!(error, ConfigError::InvalidValue { key, .. } if key == "tracing.otlp_endpoint"));
+
+    let error = Config::from_sources(env(&[("DPS_TRACE_SAMPLE_RATIO", "1.5")]), args(&[])).unwrap_err();
+    assert!(matches!(error, ConfigError::InvalidValue { key, .. } if key == "tracing.sample_ratio"));
+}
+
+#[test]
+fn unknown_flags_and_missing_files_are_rejected() {
+    assert!(matches!(Config::from_sources(env(&[]), args(&["--port", "80"])), Err(ConfigError::UnknownFlag(_))));
+    assert!(matches!(Config::from_sources(env(&[]), args(&["--bind"])), Err(ConfigError::MissingValue(_))));
+    assert!(matches!(Config::from_sources(env(&[]), args(&["--config", "/nonexistent/dps.toml"])), Err(ConfigError::Read(..))));
+    assert!(matches!(Config::from_sources(env(&[]), args(&["--help"])), Err(ConfigError::Help)));
+}
+
+#[test]
+fn unknown_file_keys_are_rejected() {
+    let path = std::env::temp_dir().join(format!("dps-config-typo-{}.toml", std::process::id()));
+    fs::write(&path, "[search]\ndefault_page = 50\n").unwrap();
+
+    let error = Config::from_sources(env(&[]), args(&["--config", path.to_str().unwrap()])).unwrap_err();
+    fs::remove_file(&path).unwrap();
+
+    assert!(matches!(error, ConfigError::Parse(..)));
+    assert!(error.to_string().contains("default_page"));
+}
+
+#[test]
+fn elasticsearch_auth_methods() {
+    let config = Config::from_sources(env(&[("DPS_ELASTICSEARCH_API_KEY", "VnVhQ2ZHY0JDZGJrUW0tZTVhT3g6dWkybHAyYXhUTm1zeWFrdzl0dk5udw==")]), vec![]).unwrap();
+    assert_eq!(config.elasticsearch.api_key_parts(), Some(("VuaCfGcBCdbkQm-e5aOx".to_string(), "ui2lp2axTNmsyakw9tvNnw".to_string())));
+
+    let config = Config::from_sources(env(&[]), args(&["--elasticsearch-api-key", "id:key"])).unwrap();
+    assert_eq!(config.elasticsearch.api_key_parts(), Some(("id".to_string(), "key".to_string())));
+
+    let error = Config::from_sources(env(&[]), args(&["--elasticsearch-api-key", "not a key"])).unwrap_err();
+    assert!(matches!(error, ConfigError::InvalidValue { key, .. } if key == "elasticsearch.api_key"));
+
+    let error = Config::from_sources(env(&[("DPS_ELASTICSEARCH_BEARER_TOKEN", "token")]), args(&["--elasticsearch-api-key", "id:key"])).unwrap_err();
+    assert!(matches!(error, ConfigError::InvalidValue { key, .. } if key == "elasticsearch"));
+}
+
+#[test]
+fn elasticsearch_tls_settings() {
+    let fingerprint = "AB:".repeat(31) + "AB";
+
+    let config = Config::from_sources(env(&[("DPS_ELASTICSEARCH_NODES", "https://127.0.0.1:9200"), ("DPS_ELASTICSEARCH_CA_FINGERPRINT", &fingerprint)]), vec![]).unwrap();
+    assert_eq!(config.elasticsearch.ca_fingerprint_bytes(), Some(vec![0xAB; 32]));
+
+    let error = Config::from_sources(env(&[("DPS_ELASTICSEARCH_CA_FINGERPRINT", &fingerprint)]), vec![]).unwrap_err();
+    assert!(error.to_string().ends_with("the first node must be an https url"));
+
+    let error = Config::from_sources(env(&[("DPS_ELASTICSEARCH_NODES", "https://127.0.0.1:9200"), ("DPS_ELASTICSEARCH_CA_FINGERPRINT", "ABCD")]), vec![]).unwrap_err();
+    assert!(matches!(error, ConfigError::InvalidValue { key, .. } if key == "elasticsearch.tls.ca_fingerprint"));
+
+    let error = Config::from_sources(env(&[]), args(&["--elasticsearch-ca-certificate", "ca.pem", "--elasticsearch-verify-certificates", "false"])).unwrap_err();
+    assert!(matches!(error, ConfigError::InvalidValue { key, .. } if key == "elasticsearch.tls.verify_certificates"));
+
+    let config = Config::from_sources(env(&[]), args(&["--elasticsearch-ca-certificate", "/nonexistent/ca.pem"])).unwrap();
+    assert!(matches!(EClient::new(&config), Err(ConfigError::Certificate(..))));
+}
+
+/// Serves a self signed certificate on a local port, returns the port and the fingerprint of the certificate
+fn self_signed_listener() -> (u16, String) {
+    let key = PKey::from_rsa(Rsa::generate(2048).unwrap()).unwrap();
+
+    let mut name = X509NameBuilder::new().unwrap();
+    name.append_entry_by_text("CN", "localhost").unwrap();
+    let name = name.build();
+
+    let mut certificate = X509Builder::new().unwrap();
+    certificate.set_version(2).unwrap();
+    certificate.set_subject_name(&name).unwrap();
+    certificate.set_issuer_name(&name).unwrap();
+    certificate.set_pubkey(&key).unwrap();
+    certificate.set_not_before(&Asn1Time::days_from_now(0).unwrap()).unwrap();
+    certificate.set_not_after(&Asn1Time::days_from_now(1).unwrap()).unwrap();
+    certificate.sign(&key, MessageDigest::sha256()).unwrap();
+    let certificate = certificate.build();
+
+    let fingerprint = certificate.digest(MessageDigest::sha256()).unwrap().iter().map(|x| format!("{:02X}", x)).collect();
+
+    let mut acceptor = SslAcceptor::mozilla_intermediate(SslMethod::tls()).unwrap();
+    acceptor.set_private_key(&key).unwrap();
+    acceptor.set_certificate(&certificate).unwrap();
+    let acceptor = acceptor.build();
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let _ = acceptor.accept(stream);
+        }
+    });
+
+    (port, fingerprint)
+}
+
+#[test]
+fn elasticsearch_certificate_pinning() {
+    let (port, fingerprint) = self_signed_listener();
+    let node = format!("https://localhost:{}", port);
+
+    let config = Config::from_sources(env(&[("DPS_ELASTICSEARCH_NODES", &node), ("DPS_ELASTICSEARCH_CA_FINGERPRINT", &fingerprint)]), vec![]).unwrap();
+    assert!(EClient::new(&config).is_ok());
+
+    let config = Config::from_sources(env(&[("DPS_ELASTICSEARCH_NODES", &node), ("DPS_ELASTICSEARCH_CA_FINGERPRINT", &"00".repeat(32))]), vec![]).unwrap();
+    let error = EClient::new(&config).err().unwrap();
+    assert!(error.to_string().ends_with("no certificate of the chain matches the fingerprint"));
+
+    // A node that is down does not prevent pinning against the next one
+    let dead = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let nodes = format!("https://localhost:{},{}", dead, node);
+
+    let config = Config::from_sources(env(&[("DPS_ELASTICSEARCH_NODES", &nodes), ("DPS_ELASTICSEARCH_CA_FINGERPRINT", &fingerprint)]), vec![]).unwrap();
+    assert!(EClient::new(&config).is_ok());
+}
+
+#[test]
+fn example_config_is_valid() {
+    let config = Config::from_sources(env(&[]), args(&["--config", concat!(env!("CARGO_MANIFEST_DIR"), "/dps.example.toml")])).unwrap();
+
+    assert_eq!(config.cors.search.allowed_origins, vec!["*"]);
+    assert!(config.cors.admin.allowed_origins.is_empty());
+}
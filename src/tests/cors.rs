@@ -0,0 +1,88 @@
+use std::sync::Arc;
+
+use actix_web::{
+    App,
+    dev::{ServiceFactory, ServiceRequest, ServiceResponse},
+    http::{StatusCode, header},
+    test::{self, TestRequest},
+    web::Data
+};
+
+use crate::{config::{AuthConfig, CorsConfig, CorsPolicy}, models::{backend::SearchBackend, memory::InMemoryBackend}};
+
+fn app_with_cors(cors: CorsConfig) -> App<impl ServiceFactory<ServiceRequest, Config = (), Response = ServiceResponse, Error = actix_web::Error, InitError = ()>> {
+    let search_backend: Arc<dyn SearchBackend> = Arc::new(InMemoryBackend::new());
+
+    App::new()
+        .app_data(Data::from(search_backend))
+        .app_data(Data::new(AuthConfig { enabled: true, admin_key: None }))
+        .configure(crate::api(cors))
+}
+
+fn preflight(uri: &str, origin: &str, method: &str) -> actix_http::Request {
+    TestRequest::default()
+        .method(actix_web::http::Method::OPTIONS)
+        .uri(uri)
+        .insert_header((header::ORIGIN, origin))
+        .insert_header((header::ACCESS_CONTROL_REQUEST_METHOD, method))
+        .insert_header((header::ACCESS_CONTROL_REQUEST_HEADERS, "x-api-key, content-type"))
+        .to_request()
+}
+
+#[actix_web::test]
+async fn search_and_admin_routes_have_their_own_policy() {
+    let app = test::init_service(app_with_cors(CorsConfig::default())).await;
+
+    let resp = test::call_service(&app, preflight("/api/search/products", "https://shop.example.com", "GET")).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.headers().get(header::ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(), "https://shop.example.com");
+    assert_eq!(resp.headers().get(header::ACCESS_CONTROL_MAX_AGE).unwrap(), "3600");
+    assert!(resp.headers().get(header::ACCESS_CONTROL_ALLOW_CREDENTIALS).is_none());
+
+    let resp = test::call_service(&app, preflight("/api/search", "https://shop.example.com", "POST")).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    // Search routes do not take writes, admin routes take no other origin by default
+    let resp = test::call_service(&app, preflight("/api/search/products", "https://shop.example.com", "DELETE")).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    let resp = test::call_service(&app, preflight("/api/index", "https://shop.example.com", "POST")).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    assert!(resp.headers().get(header::ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+
+    // Errors of the authentication still carry the CORS headers, so that browsers can read them
+    let resp = test::call_service(&app, TestRequest::get().uri("/api/search/products").insert_header((header::ORIGIN, "https://shop.example.com")).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(resp.headers().get(header::ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(), "https://shop.example.com");
+}
+
+#[actix_web::test]
+async fn origin_patterns_and_credentials() {
+    let cors = CorsConfig {
+        admin: CorsPolicy {
+            allowed_origins: vec!["https://*.admin.example.com".to_string(), "http://localhost:3000".to_string()],
+            allow_credentials: true,
+            max_age: None,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+    let app = test::init_service(app_with_cors(cors)).await;
+
+    for origin in ["https://eu.admin.example.com", "http://localhost:3000"] {
+        let resp = test::call_service(&app, preflight("/api/keys", origin, "POST")).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers().get(header::ACCESS_CONTROL_ALLOW_ORIGIN).unwrap(), origin);
+        assert_eq!(resp.headers().get(header::ACCESS_CONTROL_ALLOW_CREDENTIALS).unwrap(), "true");
+        assert!(resp.headers().get(header::ACCESS_CONTROL_MAX_AGE).is_none());
+    }
+
+    for origin in ["https://admin.example.com.evil.com", "https://example.com", "http://localhost:3001"] {
+        let resp = test::call_service(&app, preflight("/api/keys", origin, "POST")).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST, "{}", origin);
+    }
+
+    // Requests without an Origin are not from a browser, CORS does not apply
+    let resp = test::call_service(&app, TestRequest::get().uri("/api/welcome").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
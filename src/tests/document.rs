@@ -0,0 +1,159 @@
+use actix_web::{http::StatusCode, test::{self, TestRequest}};
+use serde_json::json;
+
+use super::{app, call, seed_airports};
+
+#[actix_web::test]
+async fn create_and_get_document() {
+    let app = test::init_service(app()).await;
+    seed_airports(&app).await;
+
+    let (status, body) = call(&app, TestRequest::post().uri("/api/document").set_json(json!({
+        "index": "airports",
+        "data": { "name": "Juanda International Airport", "city": "Surabaya", "country": "Indonesia", "links_count": 80 }
+    })).to_request()).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let uri = format!("/api/document/airports/{}?return_fields=city", body["document_id"].as_str().unwrap());
+    let (status, document) = call(&app, TestRequest::get().uri(&uri).to_request()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(document, json!({"city": "Surabaya"}));
+}
+
+#[actix_web::test]
+async fn strict_index_rejects_new_fields() {
+    let app = test::init_service(app()).await;
+    seed_airports(&app).await;
+
+    let (status, body) = call(&app, TestRequest::post().uri("/api/document").set_json(json!({
+        "index": "airports",
+        "data": { "name": "Juanda International Airport", "terminals": 2 }
+    })).to_request()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body["error"].as_str().unwrap().contains("[terminals]"));
+
+    let (status, _) = call(&app, TestRequest::post().uri("/api/document").set_json(json!({
+        "index": "airports",
+        "dynamic_mode": "true",
+        "data": { "name": "Juanda International Airport", "terminals": 2 }
+    })).to_request()).await;
+    assert_eq!(status, StatusCode::CREATED);
+}
+
+#[actix_web::test]
+async fn update_and_delete_document() {
+    let app = test::init_service(app()).await;
+    seed_airports(&app).await;
+
+    let (status, _) = call(&app, TestRequest::put().uri("/api/document").set_json(json!({
+        "index": "airports",
+        "document_id": "DPS",
+        "data": { "links_count": 150 }
+    })).to_request()).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (_, document) = call(&app, TestRequest::get().uri("/api/document/airports/DPS").to_request()).await;
+    assert_eq!(document["links_count"], 150);
+    assert_eq!(document["city"], "Denpasar");
+
+    let (status, _) = call(&app, TestRequest::delete().uri("/api/document/airports/DPS").to_request()).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, body) = call(&app, TestRequest::get().uri("/api/document/airports/DPS").to_request()).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body["error"], "Document ID [DPS] not found");
+}
+
+#[actix_web::test]
+async fn bulk_reports_every_operation() {
+    let app = test::init_service(app()).await;
+    seed_airports(&app).await;
+
+    let (status, body) = call(&app, TestRequest::post().uri("/api/documents/bulk").set_json(json!({
+        "index": "airports",
+        "data": [
+            { "action": "create", "document_id": "CGK", "data": { "name": "Duplicate" } },
+            { "action": "update", "document_id": "SIN", "data": { "links_count": 400 } },
+            { "action": "delete", "document_id": "XXX" },
+            { "action": "index", "data": { "name": "Juanda International Airport", "links_count": "many" } }
+        ]
+    })).to_request()).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["total"], 4);
+    assert_eq!(body["successful"], 2);
+    assert_eq!(body["failed"], 2);
+    assert_eq!(body["items"][0]["status"], 409);
+    assert_eq!(body["items"][1]["result"], "updated");
+    assert_eq!(body["items"][2]["result"], "not_found");
+    assert_eq!(body["items"][3]["position"], 3);
+    assert_eq!(body["items"][3]["status"], 400);
+}
+
+#[actix_web::test]
+async fn bulk_rejects_invalid_operations() {
+    let app = test::init_service(app()).await;
+    seed_airports(&app).await;
+
+    let (status, body) = call(&app, TestRequest::post().uri("/api/documents/bulk").set_json(json!({
+        "index": "airports",
+        "data": [
+            { "action": "index", "data": { "name": "Juanda International Airport" } },
+            { "action": "delete" }
+        ]
+    })).to_request()).await;
+
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body["error"].as_str().unwrap().starts_with("Invalid bulk operation at position 1"));
+}
+
+#[actix_web::test]
+async fn bulk_parses_each_action() {
+    let app = test::init_service(app()).await;
+    seed_airports(&app).await;
+
+    let invalid = [
+        json!({ "action": "upsert", "document_id": "CGK", "data": { "name": "Soekarno-Hatta" } }),
+        json!({ "action": "create", "data": { "name": "Juanda International Airport" } }),
+        json!({ "action": "create", "document_id": "SUB" }),
+        json!({ "action": "update", "data": { "links_count": 400 } }),
+        json!({ "action": "delete" }),
+        json!({ "action": "index", "document_id": "SUB" }),
+        json!({ "document_id": "SUB" })
+    ];
+
+    for operation in invalid {
+        let (status, body) = call(&app, TestRequest::post().uri("/api/documents/bulk").set_json(json!({
+            "index": "airports",
+            "data": [
+                { "action": "delete", "document_id": "SIN" },
+                operation
+            ]
+        })).to_request()).await;
+
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+        assert!(body["error"].as_str().unwrap().starts_with("Invalid bulk operation at position 1"));
+    }
+
+    // Nothing runs when an operation is invalid
+    let (status, _) = call(&app, TestRequest::get().uri("/api/document/airports/SIN").to_request()).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, body) = call(&app, TestRequest::post().uri("/api/documents/bulk").set_json(json!({
+        "index": "airports",
+        "data": [
+            { "action": "CREATE", "document_id": "SUB", "data": { "name": "Juanda International Airport" } },
+            { "data": { "name": "Ngurah Rai International Airport" } },
+            { "action": "update", "document_id": "SUB", "data": { "links_count": 40 } },
+            { "action": "delete", "document_id": "SIN" }
+        ]
+    })).to_request()).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["successful"], 4);
+    assert_eq!(body["items"][0]["document_id"], "SUB");
+    assert_eq!(body["items"][0]["result"], "created");
+    assert_eq!(body["items"][1]["result"], "created");
+    assert_eq!(body["items"][2]["result"], "updated");
+    assert_eq!(body["items"][3]["result"], "deleted");
+}
@@ -0,0 +1,25 @@
+use serde_json::json;
+
+use crate::models::{errors::ErrorTypes, geo::GeoSearch};
+
+#[test]
+fn bounding_boxes_keep_their_corners() {
+    // From Fiji to Samoa, across the antimeridian
+    let geo = GeoSearch::new(None, None, None, Some("-12.5,177.0,-19.0,-170.0".to_string()), None).unwrap().unwrap();
+
+    assert_eq!(geo.to_filters("_geoloc"), vec![json!({
+        "geo_bounding_box": {
+            "_geoloc": {
+                "top_left": { "lat": -12.5, "lon": 177.0 },
+                "bottom_right": { "lat": -19.0, "lon": -170.0 }
+            }
+        }
+    })]);
+}
+
+#[test]
+fn bounding_boxes_upside_down_are_rejected() {
+    let result = GeoSearch::new(None, None, None, Some("-19.0,177.0,-12.5,-170.0".to_string()), None);
+
+    assert!(matches!(result, Err(ErrorTypes::InvalidGeo(x)) if x.contains("is below its bottom right corner")));
+}
@@ -0,0 +1,142 @@
+use std::sync::Arc;
+
+use actix_web::{
+    App,
+    dev::{ServiceFactory, ServiceRequest, ServiceResponse},
+    test::{self, TestRequest},
+    web::Data
+};
+use reqwest::Url;
+
+use crate::{
+    config::{AuthConfig, Config, CorsConfig},
+    models::{EClient, ErrorTypes, backend::SearchBackend, health::HealthState}
+};
+
+use super::{app, call, index_cache::node, pool::{dead_node, fake_node}};
+
+const GREEN: &str = r#"{"cluster_name":"docker-cluster","status":"green","number_of_nodes":3,"number_of_data_nodes":2,"unassigned_shards":0,"number_of_pending_tasks":1,"active_shards_percent_as_number":100.0}"#;
+const RED: &str = r#"{"cluster_name":"docker-cluster","status":"red","number_of_nodes":1,"number_of_data_nodes":1,"unassigned_shards":4,"number_of_pending_tasks":0,"active_shards_percent_as_number":50.0}"#;
+
+fn client_of(node: Url) -> Arc<EClient> {
+    let config = Config::from_sources([("DPS_ELASTICSEARCH_NODES".to_string(), node.to_string())].into(), vec![]).unwrap();
+    Arc::new(EClient::new(&config).unwrap())
+}
+
+/// The API over an Elasticsearch client, with authentication disabled
+fn client_app(client: &Arc<EClient>) -> App<impl ServiceFactory<ServiceRequest, Config = (), Response = ServiceResponse, Error = actix_web::Error, InitError = ()>> {
+    let search_backend: Arc<dyn SearchBackend> = client.clone();
+
+    App::new()
+        .app_data(Data::from(search_backend))
+        .app_data(Data::new(AuthConfig { enabled: false, admin_key: None }))
+        .configure(crate::api(CorsConfig::default()))
+}
+
+#[actix_web::test]
+async fn probes_of_the_in_memory_backend_are_healthy() {
+    let app = test::init_service(app()).await;
+
+    for probe in ["live", "ready", "deep"] {
+        let (status, body) = call(&app, TestRequest::get().uri(&format!("/health/{}", probe)).to_request()).await;
+        assert_eq!(status, 200, "{}", probe);
+        assert_ne!(body["status"], "not_ready");
+    }
+
+    let (_, body) = call(&app, TestRequest::get().uri("/health/deep").to_request()).await;
+    assert_eq!(body["cluster"]["status"], "green");
+}
+
+#[actix_web::test]
+async fn deep_health_reports_the_cluster() {
+    let client = client_of(fake_node(Some(GREEN)));
+    let app = test::init_service(client_app(&client)).await;
+
+    // Nothing is known before the first check
+    let (status, body) = call(&app, TestRequest::get().uri("/health/ready").to_request()).await;
+    assert_eq!(status, 503);
+    assert_eq!(body["checked_at"], serde_json::Value::Null);
+
+    let (status, body) = call(&app, TestRequest::get().uri("/health/deep").to_request()).await;
+    assert_eq!(status, 200);
+    assert_eq!(body["status"], "ready");
+    assert_eq!(body["cluster"]["cluster_name"], "docker-cluster");
+    assert_eq!(body["cluster"]["number_of_nodes"], 3);
+    assert_eq!(body["cluster"]["unassigned_shards"], 0);
+    assert_eq!(body["cluster"]["pending_tasks"], 1);
+    assert_eq!(body["nodes"]["alive"], 1);
+    assert_eq!(body["nodes"]["total"], 1);
+
+    // The deep check is cached for the readiness probe
+    let (status, body) = call(&app, TestRequest::get().uri("/health/ready").to_request()).await;
+    assert_eq!(status, 200);
+    assert!(body["checked_at"].is_u64());
+}
+
+#[actix_web::test]
+async fn red_clusters_are_not_ready() {
+    let client = client_of(fake_node(Some(RED)));
+    let app = test::init_service(client_app(&client)).await;
+
+    let (status, body) = call(&app, TestRequest::get().uri("/health/deep").to_request()).await;
+    assert_eq!(status, 503);
+    assert_eq!(body["cluster"]["unassigned_shards"], 4);
+
+    let (status, body) = call(&app, TestRequest::get().uri("/health/ready").to_request()).await;
+    assert_eq!(status, 503);
+    assert_eq!(body["reason"], "Elasticsearch cluster status is red");
+
+    // Red clusters can still answer requests about the shards they have
+    assert!(client.health.ensure_reachable().is_ok());
+}
+
+#[actix_web::test]
+async fn requests_fail_fast_while_the_cluster_is_down() {
+    let client = client_of(dead_node());
+    let app = test::init_service(client_app(&client)).await;
+
+    assert!(client.check_health().await.is_err());
+    assert!(matches!(client.health.state().0, HealthState::Down(_)));
+
+    let (status, body) = call(&app, TestRequest::get().uri("/health/ready").to_request()).await;
+    assert_eq!(status, 503);
+    assert_eq!(body["reason"], "Server currently unavailable");
+
+    let (status, _) = call(&app, TestRequest::get().uri("/health/live").to_request()).await;
+    assert_eq!(status, 200);
+
+    // A node that answers is not asked while the cached state is down
+    let client = client_of(fake_node(Some(GREEN)));
+    let app = test::init_service(client_app(&client)).await;
+    client.health.record(&Err(ErrorTypes::ServerDown));
+
+    let (status, body) = call(&app, TestRequest::get().uri("/api/mappings/airports").to_request()).await;
+    assert_eq!(status, 503);
+    assert_eq!(body["error"], "Server currently unavailable");
+
+    client.check_health().await.unwrap();
+    let (status, _) = call(&app, TestRequest::get().uri("/health/ready").to_request()).await;
+    assert_eq!(status, 200);
+}
+
+#[actix_web::test]
+async fn health_denied_to_the_server_does_not_fail_requests() {
+    const FORBIDDEN: &str = r#"{"error":{"type":"security_exception","reason":"action [cluster:monitor/health] is unauthorized"},"status":403}"#;
+    const AIRPORTS: &str = r#"{"airports":{"mappings":{"properties":{"name":{"type":"keyword"}}},"settings":{}}}"#;
+
+    let (url, _) = node(&[("GET /_cluster/health", "403 Forbidden", FORBIDDEN), ("GET /airports ", "200 OK", AIRPORTS)]);
+    let client = client_of(url.parse().unwrap());
+    let app = test::init_service(client_app(&client)).await;
+
+    assert!(client.check_health().await.is_err());
+    assert!(matches!(client.health.state().0, HealthState::Degraded(_)));
+    assert!(client.health.ensure_reachable().is_ok());
+
+    let (status, body) = call(&app, TestRequest::get().uri("/api/mappings/airports").to_request()).await;
+    assert_eq!(status, 200);
+    assert_eq!(body["properties"]["name"]["type"], "keyword");
+
+    let (status, body) = call(&app, TestRequest::get().uri("/health/ready").to_request()).await;
+    assert_eq!(status, 200);
+    assert_eq!(body["status"], "degraded");
+}
@@ -0,0 +1,31 @@
+use serde_json::json;
+
+use crate::models::highlight::Highlight;
+
+#[test]
+fn snippets_keep_tags_holding_spaces_whole() {
+    let highlight = Highlight::new(None, Some("description:3".to_string()), Some("<mark class=\"hit\">".to_string()), Some("</mark>".to_string())).unwrap();
+
+    let mut hit = json!({
+        "highlight": {
+            "description": ["the largest airport of the island of <mark class=\"hit\">Bali</mark> in Indonesia"]
+        }
+    });
+    highlight.apply(&mut hit);
+
+    assert_eq!(hit["_snippet"]["description"], "… of <mark class=\"hit\">Bali</mark> in …");
+}
+
+#[test]
+fn snippets_never_cut_a_highlighted_phrase() {
+    let highlight = Highlight::new(None, Some("description:2".to_string()), None, None).unwrap();
+
+    let mut hit = json!({
+        "highlight": {
+            "description": ["flights from <em>Ngurah Rai International</em> airport to Jakarta"]
+        }
+    });
+    highlight.apply(&mut hit);
+
+    assert_eq!(hit["_snippet"]["description"], "… from <em>Ngurah Rai International</em> …");
+}
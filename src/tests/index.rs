@@ -0,0 +1,211 @@
+use std::{future::poll_fn, pin::Pin};
+
+use actix_web::{body::MessageBody, http::StatusCode, test::{self, TestRequest}, web::Bytes};
+use futures_util::stream;
+use serde_json::{json, Value};
+
+use crate::models::{
+    backend::SearchBackend,
+    export::EXPORT_PAGE_SIZE,
+    import::{ImportFormat, ImportSummary, import_documents},
+    memory::InMemoryBackend
+};
+
+use super::{app, call, seed_airports};
+
+#[actix_web::test]
+async fn create_and_get_index() {
+    let app = test::init_service(app()).await;
+
+    let (status, _) = call(&app, TestRequest::post().uri("/api/index").set_json(json!({"index": "airports"})).to_request()).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let (status, body) = call(&app, TestRequest::get().uri("/api/index?index=airports").to_request()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body[0]["index"], "airports");
+    assert_eq!(body[0]["docs.count"], "0");
+}
+
+#[actix_web::test]
+async fn create_existing_index_conflicts() {
+    let app = test::init_service(app()).await;
+    seed_airports(&app).await;
+
+    let (status, body) = call(&app, TestRequest::post().uri("/api/index").set_json(json!({"index": "airports"})).to_request()).await;
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body["error"], "Failed to create new index, index [airports] already exists");
+}
+
+#[actix_web::test]
+async fn delete_index() {
+    let app = test::init_service(app()).await;
+    seed_airports(&app).await;
+
+    let (status, _) = call(&app, TestRequest::delete().uri("/api/index/airports").to_request()).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let (status, body) = call(&app, TestRequest::get().uri("/api/mappings/airports").to_request()).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body["error"], "Index [airports] not found");
+}
+
+#[actix_web::test]
+async fn mappings_follow_inserted_documents() {
+    let app = test::init_service(app()).await;
+    seed_airports(&app).await;
+
+    let (status, body) = call(&app, TestRequest::get().uri("/api/mappings/airports").to_request()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["dynamic"], "strict");
+    assert_eq!(body["properties"]["links_count"]["type"], "long");
+    assert_eq!(body["properties"]["country"]["fields"]["keyword"]["type"], "keyword");
+
+    let (status, _) = call(&app, TestRequest::put().uri("/api/mappings").set_json(json!({
+        "index": "airports",
+        "mappings": { "properties": { "links_count": { "type": "keyword" } } }
+    })).to_request()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn import_csv_reports_rejected_lines() {
+    let app = test::init_service(app()).await;
+    seed_airports(&app).await;
+
+    let csv = "_id,name,city,country,links_count:integer\nBDO,Husein Sastranegara,Bandung,Indonesia,30\nSUB,Juanda,Surabaya,Indonesia,many\n";
+    let body = format!("--boundary\r\nContent-Disposition: form-data; name=\"file\"; filename=\"airports.csv\"\r\nContent-Type: text/csv\r\n\r\n{}\r\n--boundary--\r\n", csv);
+
+    let (status, summary) = call(&app, TestRequest::post()
+        .uri("/api/index/airports/import")
+        .insert_header(("Content-Type", "multipart/form-data; boundary=boundary"))
+        .set_payload(body)
+        .to_request()).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(summary["imported"], 1);
+    assert_eq!(summary["rejected"], 1);
+    assert_eq!(summary["errors"][0]["line"], 3);
+
+    let (status, document) = call(&app, TestRequest::get().uri("/api/document/airports/BDO").to_request()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(document["links_count"], 30);
+}
+
+#[actix_web::test]
+async fn import_unknown_dynamic_mode_is_strict() {
+    let app = test::init_service(app()).await;
+    seed_airports(&app).await;
+
+    let csv = "_id,name,terminals:integer\nBDO,Husein Sastranegara,1\n";
+    let body = format!("--boundary\r\nContent-Disposition: form-data; name=\"file\"; filename=\"airports.csv\"\r\nContent-Type: text/csv\r\n\r\n{}\r\n--boundary--\r\n", csv);
+
+    let (status, summary) = call(&app, TestRequest::post()
+        .uri("/api/index/airports/import?dynamic_mode=runtime")
+        .insert_header(("Content-Type", "multipart/form-data; boundary=boundary"))
+        .set_payload(body)
+        .to_request()).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(summary["imported"], 0);
+    assert_eq!(summary["rejected"], 1);
+
+    let (_, mappings) = call(&app, TestRequest::get().uri("/api/mappings/airports").to_request()).await;
+    assert_eq!(mappings["dynamic"], "strict");
+}
+
+#[actix_web::test]
+async fn export_csv() {
+    let app = test::init_service(app()).await;
+    seed_airports(&app).await;
+
+    let resp = test::call_service(&app, TestRequest::get().uri("/api/index/airports/export?format=csv&fields=city,links_count").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let body = test::read_body(resp).await;
+    let lines: Vec<&str> = std::str::from_utf8(&body).unwrap().lines().collect();
+    assert_eq!(lines[0], "_id,city,links_count");
+    assert_eq!(lines[1], "CGK,Jakarta,212");
+    assert_eq!(lines.len(), 6);
+}
+
+#[actix_web::test]
+async fn failed_export_page_cuts_the_response_short() {
+    let app = test::init_service(app()).await;
+
+    let (status, _) = call(&app, TestRequest::post().uri("/api/index").set_json(json!({"index": "numbers"})).to_request()).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let data: Vec<Value> = (0..EXPORT_PAGE_SIZE + 10).map(|x| json!({"action": "index", "data": {"number": x}})).collect();
+    let (status, _) = call(&app, TestRequest::post().uri("/api/documents/bulk").set_json(json!({"index": "numbers", "dynamic_mode": "true", "data": data})).to_request()).await;
+    assert_eq!(status, StatusCode::OK);
+
+    let resp = test::call_service(&app, TestRequest::get().uri("/api/index/numbers/export?format=json").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let mut body = resp.into_body();
+    let first = poll_fn(|cx| Pin::new(&mut body).poll_next(cx)).await.unwrap().unwrap();
+    assert!(first.starts_with(b"["));
+
+    // The second page fails once the index is gone, the json is never closed
+    let (status, _) = call(&app, TestRequest::delete().uri("/api/index/numbers").to_request()).await;
+    assert_eq!(status, StatusCode::OK);
+
+    assert!(poll_fn(|cx| Pin::new(&mut body).poll_next(cx)).await.unwrap().is_err());
+    assert!(poll_fn(|cx| Pin::new(&mut body).poll_next(cx)).await.is_none());
+}
+
+/// Imports a file sent in the given chunks into a new index of an in-memory backend
+async fn import_chunks(format: ImportFormat, types: Option<&str>, chunks: &[&str]) -> ImportSummary {
+    let backend = InMemoryBackend::new();
+    backend.create_index("airports", vec![], None).await.unwrap();
+
+    let chunks: Vec<Result<Bytes, String>> = chunks.iter().map(|x| Ok(Bytes::copy_from_slice(x.as_bytes()))).collect();
+
+    import_documents(&backend, "airports", format, types.map(str::to_string), Some("true".to_string()), stream::iter(chunks)).await.unwrap()
+}
+
+#[actix_web::test]
+async fn import_json_arrays_require_one_comma_between_elements() {
+    for file in ["[{} {}]", "[,,{}]", "[,{}]", "[{},,{}]", "[{},]", "[{}"] {
+        let summary = import_chunks(ImportFormat::Json, None, &[file]).await;
+        assert!(summary.error.is_some(), "{}", file);
+    }
+
+    let summary = import_chunks(ImportFormat::Json, None, &["[{\"_id\": \"BDO\"} {\"_id\": \"SUB\"}]"]).await;
+    assert_eq!(summary.error.unwrap().to_string(), "Invalid import, expected , or ] at line 1, found [{]");
+
+    for file in ["[]", " [ ] ", "[{\"name\": \"a\"}]", "[\n{\"name\": \"a, ]\"},\n{\"name\": \"b \\\" }\"}\n]"] {
+        let summary = import_chunks(ImportFormat::Json, None, &[file]).await;
+        assert!(summary.error.is_none(), "{}", file);
+        assert_eq!(summary.rejected, 0, "{}", file);
+    }
+}
+
+#[actix_web::test]
+async fn import_elements_and_lines_split_across_chunks() {
+    // Every byte in a chunk of its own
+    let json = "[{\"name\": \"Husein [Sastranegara]\", \"links\": [1, 2]},\n {\"name\": \"Juanda \\\"}\"}, 12]";
+    let chunks: Vec<String> = json.chars().map(String::from).collect();
+    let summary = import_chunks(ImportFormat::Json, None, &chunks.iter().map(String::as_str).collect::<Vec<_>>()).await;
+    assert!(summary.error.is_none());
+    assert_eq!(summary.imported, 2);
+    assert_eq!(summary.rejected, 1);
+    assert_eq!(summary.errors[0].line, 2);
+
+    let ndjson = "{\"name\": \"Husein Sastranegara\"}\n\n{\"name\": \"Juanda\"}\n{\"name\": ";
+    let chunks: Vec<String> = ndjson.chars().map(String::from).collect();
+    let summary = import_chunks(ImportFormat::Ndjson, None, &chunks.iter().map(String::as_str).collect::<Vec<_>>()).await;
+    assert_eq!(summary.imported, 2);
+    assert_eq!(summary.rejected, 1);
+    assert_eq!(summary.errors[0].line, 4);
+}
+
+#[actix_web::test]
+async fn import_rejects_floats_that_are_not_numbers() {
+    let summary = import_chunks(ImportFormat::Csv, Some("elevation:float"), &["name,elevation\nBDO,NaN\nSUB,inf\nCGK,10.5\n"]).await;
+
+    assert_eq!(summary.imported, 1);
+    assert_eq!(summary.rejected, 2);
+    assert_eq!(summary.errors[0].reason, "column [elevation] value [NaN] is not a valid float");
+    assert_eq!(summary.errors[1].line, 3);
+}
@@ -0,0 +1,205 @@
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpListener,
+    sync::{Arc, Mutex}
+};
+
+use actix_web::{ResponseError, http::StatusCode};
+use serde_json::json;
+
+use crate::{config::Config, models::{EClient, ErrorTypes, documents::SearchQuery}};
+
+const AIRPORTS: &str = r#"{"airports":{"mappings":{"properties":{"name":{"type":"keyword"}}},"settings":{"index":{"max_result_window":"20"}}}}"#;
+const SEARCH: &str = r#"{"took":1,"hits":{"total":{"value":0},"hits":[]}}"#;
+const MISSING: &str = r#"{"error":{"type":"index_not_found_exception","reason":"no such index [missing]"},"status":404}"#;
+
+/// Request lines received by a stand-in node
+type Received = Arc<Mutex<Vec<String>>>;
+
+/// Answers requests whose request line starts with one of the prefixes with its status and body, 404 otherwise
+pub(super) fn node(routes: &'static [(&'static str, &'static str, &'static str)]) -> (String, Received) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let received = Received::default();
+    let kept = received.clone();
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let mut reader = BufReader::new(stream);
+            let mut head = String::new();
+
+            while reader.read_line(&mut head).map(|x| x > 2).unwrap_or(false) {}
+
+            let length = head
+                .lines()
+                .find_map(|x| x.to_lowercase().strip_prefix("content-length:").map(|x| x.trim().parse().unwrap_or(0)))
+                .unwrap_or(0);
+            let _ = reader.read_exact(&mut vec![0; length]);
+
+            let line = head.lines().next().unwrap_or_default().to_string();
+            let (status, body) = routes
+                .iter()
+                .find(|(prefix, _, _)| line.starts_with(prefix))
+                .map(|(_, status, body)| (*status, *body))
+                .unwrap_or(("404 Not Found", "{}"));
+
+            kept.lock().unwrap().push(line);
+
+            let _ = write!(reader.get_mut(), "HTTP/1.1 {}\r\nContent-Type: application/json\r\nX-Elastic-Product: Elasticsearch\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", status, body.len(), body);
+        }
+    });
+
+    (url, received)
+}
+
+pub(super) fn client(node: String, ttl: &str) -> EClient {
+    let config = Config::from_sources([
+        ("DPS_ELASTICSEARCH_NODES".to_string(), node),
+        ("DPS_ELASTICSEARCH_INDEX_CACHE_TTL".to_string(), ttl.to_string())
+    ].into(), vec![]).unwrap();
+
+    EClient::new(&config).unwrap()
+}
+
+/// Requests whose request line starts with the prefix
+pub(super) fn count(received: &Received, prefix: &str) -> usize {
+    received.lock().unwrap().iter().filter(|x| x.starts_with(prefix)).count()
+}
+
+#[actix_web::test]
+async fn searches_fetch_the_metadata_once() {
+    let (url, received) = node(&[("GET /airports ", "200 OK", AIRPORTS), ("POST /airports/_search", "200 OK", SEARCH)]);
+    let client = client(url, "30");
+
+    for _ in 0..3 {
+        client.search_index("airports", SearchQuery { sort: Some("name".to_string()), ..Default::default() }).await.unwrap();
+    }
+
+    assert_eq!(count(&received, "GET /airports "), 1);
+    assert_eq!(count(&received, "POST /airports/_search"), 3);
+}
+
+#[actix_web::test]
+async fn changes_made_through_the_service_are_seen_right_away() {
+    let (url, received) = node(&[
+        ("GET /airports ", "200 OK", AIRPORTS),
+        ("POST /airports/_search", "200 OK", SEARCH),
+        ("PUT /airports/_mapping", "200 OK", r#"{"acknowledged":true}"#)
+    ]);
+    let client = client(url, "30");
+
+    client.search_index("airports", SearchQuery::default()).await.unwrap();
+    client.update_index_mappings("airports", json!({"properties": {"city": {"type": "keyword"}}})).await.unwrap();
+    client.search_index("airports", SearchQuery::default()).await.unwrap();
+
+    assert_eq!(count(&received, "GET /airports "), 2);
+}
+
+#[actix_web::test]
+async fn writes_keep_the_cached_dynamic_mode() {
+    let (url, received) = node(&[
+        ("GET /airports ", "200 OK", AIRPORTS),
+        ("POST /airports/_doc", "201 Created", r#"{"_id":"1","result":"created"}"#),
+        ("PUT /airports/_mapping", "200 OK", r#"{"acknowledged":true}"#)
+    ]);
+    let client = client(url, "30");
+
+    for _ in 0..3 {
+        client.insert_document("airports", json!({"name": "Changi Airport"}), None).await.unwrap();
+    }
+
+    // The first write makes the index strict, later writes find it strict in the cache
+    assert_eq!(count(&received, "PUT /airports/_mapping"), 1);
+
+    client.insert_document("airports", json!({"name": "Changi Airport"}), Some("true".to_string())).await.unwrap();
+    assert_eq!(count(&received, "PUT /airports/_mapping"), 3);
+
+    assert_eq!(count(&received, "GET /airports "), 1);
+    assert_eq!(count(&received, "POST /airports/_doc"), 4);
+}
+
+#[actix_web::test]
+async fn fetched_dynamic_modes_are_set_again() {
+    // Changed to strict elsewhere after it was fetched
+    const DYNAMIC: &str = r#"{"airports":{"mappings":{"dynamic":"true","properties":{"name":{"type":"keyword"}}},"settings":{}}}"#;
+
+    let (url, received) = node(&[
+        ("GET /airports ", "200 OK", DYNAMIC),
+        ("POST /airports/_doc", "201 Created", r#"{"_id":"1","result":"created"}"#),
+        ("PUT /airports/_mapping", "200 OK", r#"{"acknowledged":true}"#)
+    ]);
+    let client = client(url, "30");
+
+    client.insert_document("airports", json!({"name": "Changi Airport"}), Some("true".to_string())).await.unwrap();
+
+    // The mode the write needs and then strict
+    assert_eq!(count(&received, "PUT /airports/_mapping"), 2);
+    assert_eq!(count(&received, "GET /airports "), 1);
+}
+
+#[actix_web::test]
+async fn missing_indexes_are_cached() {
+    let (url, received) = node(&[("GET /missing ", "404 Not Found", MISSING)]);
+    let client = client(url, "30");
+
+    for _ in 0..2 {
+        let error = client.get_document("missing", "1", None).await.unwrap_err();
+        assert!(matches!(error, ErrorTypes::IndexNotFound(x) if x == "missing"));
+    }
+
+    assert_eq!(count(&received, "GET /missing "), 1);
+    assert_eq!(received.lock().unwrap().len(), 1);
+}
+
+#[actix_web::test]
+async fn indexes_deleted_elsewhere_are_dropped_from_the_cache() {
+    let (url, received) = node(&[("GET /airports ", "200 OK", AIRPORTS), ("POST /airports/_search", "404 Not Found", MISSING)]);
+    let client = client(url, "30");
+
+    for _ in 0..2 {
+        let error = client.search_index("airports", SearchQuery::default()).await.err().unwrap();
+        assert!(matches!(error, ErrorTypes::IndexNotFound(_)));
+    }
+
+    assert_eq!(count(&received, "GET /airports "), 2);
+}
+
+#[actix_web::test]
+async fn a_ttl_of_zero_disables_the_cache() {
+    let (url, received) = node(&[("GET /airports ", "200 OK", AIRPORTS), ("POST /airports/_search", "200 OK", SEARCH)]);
+    let client = client(url, "0");
+
+    for _ in 0..2 {
+        client.search_index("airports", SearchQuery::default()).await.unwrap();
+    }
+
+    assert_eq!(count(&received, "GET /airports "), 2);
+}
+
+#[actix_web::test]
+async fn pages_past_the_result_window_are_refused() {
+    let (url, received) = node(&[("GET /airports ", "200 OK", AIRPORTS), ("POST /airports/_search", "200 OK", SEARCH)]);
+    let client = client(url, "30");
+
+    client.search_index("airports", SearchQuery { from: Some(1), count: Some(10), ..Default::default() }).await.unwrap();
+
+    let error = client.search_index("airports", SearchQuery { from: Some(2), count: Some(10), ..Default::default() }).await.err().unwrap();
+    assert_eq!(error.to_string(), "Bad data request, page 2 goes past the result window of 20 results, use a cursor to page further");
+    assert_eq!(count(&received, "POST /airports/_search"), 1);
+}
+
+#[actix_web::test]
+async fn elasticsearch_errors_are_gateway_errors() {
+    let (url, _) = node(&[("GET /airports ", "200 OK", AIRPORTS), ("POST /airports/_search", "500 Internal Server Error", r#"{"error":{"reason":"shard failure"},"status":500}"#)]);
+    let failed = client(url, "30").search_index("airports", SearchQuery::default()).await.err().unwrap();
+    assert_eq!(failed.status_code(), StatusCode::BAD_GATEWAY);
+    assert_eq!(failed.to_string(), "Elasticsearch error, shard failure");
+
+    let (url, _) = node(&[("GET /airports ", "403 Forbidden", r#"{"error":{"reason":"action is unauthorized for user"},"status":403}"#)]);
+    let forbidden = client(url, "30").search_index("airports", SearchQuery::default()).await.err().unwrap();
+    assert_eq!(forbidden.status_code(), StatusCode::BAD_GATEWAY);
+
+    let (url, _) = node(&[("GET /airports ", "200 OK", AIRPORTS), ("POST /airports/_search", "429 Too Many Requests", r#"{"error":{"reason":"rejected execution"},"status":429}"#)]);
+    let busy = client(url, "30").search_index("airports", SearchQuery::default()).await.err().unwrap();
+    assert_eq!(busy.status_code(), StatusCode::SERVICE_UNAVAILABLE);
+}
@@ -0,0 +1,86 @@
+use std::time::Duration;
+
+use actix_web::test::{self, TestRequest};
+use serde_json::json;
+
+use crate::{config::{AuthConfig, RateLimitConfig}, models::metrics::Metrics};
+
+use super::{app, app_with};
+
+/// Value of a series in the rendered metrics
+fn value(metrics: &str, series: &str) -> Option<f64> {
+    metrics
+        .lines()
+        .find_map(|line| line.strip_prefix(series).and_then(|x| x.strip_prefix(' ')))
+        .and_then(|x| x.parse().ok())
+}
+
+#[actix_web::test]
+async fn requests_are_counted_by_route_pattern_and_status() {
+    let app = test::init_service(app()).await;
+
+    for index in ["missing", "other"] {
+        let resp = test::call_service(&app, TestRequest::get().uri(&format!("/api/search/{}", index)).to_request()).await;
+        assert_eq!(resp.status(), 404);
+    }
+
+    let resp = test::call_service(&app, TestRequest::get().uri("/metrics").to_request()).await;
+    assert_eq!(resp.status(), 200);
+    assert!(resp.headers().get("Content-Type").unwrap().to_str().unwrap().starts_with("text/plain; version=0.0.4"));
+
+    let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+
+    // Other tests share the metrics of the process, counts only grow
+    let labels = r#"method="GET",route="/api/search/{index}",status="404""#;
+    assert!(value(&body, &format!("dps_http_requests_total{{{}}}", labels)).unwrap() >= 2.0);
+    assert!(value(&body, &format!("dps_http_request_duration_seconds_bucket{{{},le=\"+Inf\"}}", labels)).unwrap() >= 2.0);
+    assert!(value(&body, r#"dps_errors_total{error="IndexNotFound"}"#).unwrap() >= 2.0);
+    assert!(!body.contains("/api/search/missing"));
+    assert!(body.contains("# TYPE dps_http_requests_in_flight gauge"));
+    assert!(body.contains("# TYPE dps_elasticsearch_request_duration_seconds histogram"));
+}
+
+#[actix_web::test]
+async fn errors_of_middlewares_are_counted() {
+    let app = test::init_service(app_with(AuthConfig { enabled: true, admin_key: None }, RateLimitConfig::default())).await;
+
+    let resp = test::call_service(&app, TestRequest::get().uri("/api/index").to_request()).await;
+    assert_eq!(resp.status(), 401);
+
+    // The metrics need no API key
+    let resp = test::call_service(&app, TestRequest::get().uri("/metrics").to_request()).await;
+    assert_eq!(resp.status(), 200);
+
+    let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+
+    assert!(value(&body, r#"dps_errors_total{error="MissingApiKey"}"#).unwrap() >= 1.0);
+    assert!(value(&body, r#"dps_http_requests_total{method="GET",route="/api/index",status="401"}"#).unwrap() >= 1.0);
+}
+
+#[test]
+fn histograms_are_cumulative() {
+    let metrics = Metrics::default();
+
+    metrics.observe_elasticsearch("search", Some(200), Duration::from_millis(3));
+    metrics.observe_elasticsearch("search", Some(200), Duration::from_millis(30));
+    metrics.observe_elasticsearch("search", None, Duration::from_secs(20));
+    metrics.observe_took("search", &json!({"took": 7}));
+    metrics.observe_took("search", &json!({"error": "no took"}));
+
+    let in_flight = metrics.http_started();
+    assert!(metrics.render().contains("\ndps_http_requests_in_flight 1\n"));
+    drop(in_flight);
+
+    let body = metrics.render();
+
+    assert!(body.contains("\ndps_http_requests_in_flight 0\n"));
+    assert_eq!(value(&body, r#"dps_elasticsearch_request_duration_seconds_bucket{operation="search",status="200",le="0.001"}"#), Some(0.0));
+    assert_eq!(value(&body, r#"dps_elasticsearch_request_duration_seconds_bucket{operation="search",status="200",le="0.005"}"#), Some(1.0));
+    assert_eq!(value(&body, r#"dps_elasticsearch_request_duration_seconds_bucket{operation="search",status="200",le="0.05"}"#), Some(2.0));
+    assert_eq!(value(&body, r#"dps_elasticsearch_request_duration_seconds_bucket{operation="search",status="200",le="+Inf"}"#), Some(2.0));
+    assert_eq!(value(&body, r#"dps_elasticsearch_request_duration_seconds_count{operation="search",status="200"}"#), Some(2.0));
+    assert_eq!(value(&body, r#"dps_elasticsearch_request_duration_seconds_bucket{operation="search",status="error",le="10"}"#), Some(0.0));
+    assert_eq!(value(&body, r#"dps_elasticsearch_request_duration_seconds_bucket{operation="search",status="error",le="+Inf"}"#), Some(1.0));
+    assert_eq!(value(&body, r#"dps_elasticsearch_took_seconds_sum{operation="search"}"#), Some(0.007));
+    assert_eq!(value(&body, r#"dps_elasticsearch_took_seconds_count{operation="search"}"#), Some(1.0));
+}
@@ -0,0 +1,96 @@
+use std::sync::Arc;
+
+use actix_http::Request;
+use actix_web::{
+    App,
+    dev::{Service, ServiceFactory, ServiceRequest, ServiceResponse},
+    http::StatusCode,
+    test::{self, TestRequest},
+    web::Data
+};
+use serde_json::{json, Value};
+
+use crate::{config::{AuthConfig, CorsConfig, RateLimitConfig}, models::{backend::SearchBackend, memory::InMemoryBackend, rate_limit::RateLimiter}};
+
+mod index;
+mod document;
+mod search;
+mod config;
+mod pool;
+mod auth;
+mod rate_limit;
+mod cors;
+mod metrics;
+mod telemetry;
+mod access_log;
+mod health;
+mod index_cache;
+mod schema;
+mod highlight;
+mod geo;
+
+/// The API over a new, empty in-memory backend, with authentication disabled
+pub fn app() -> App<impl ServiceFactory<ServiceRequest, Config = (), Response = ServiceResponse, Error = actix_web::Error, InitError = ()>> {
+    app_with(AuthConfig { enabled: false, admin_key: None }, RateLimitConfig::default())
+}
+
+/// The API over a new, empty in-memory backend
+pub fn app_with(auth: AuthConfig, rate_limit: RateLimitConfig) -> App<impl ServiceFactory<ServiceRequest, Config = (), Response = ServiceResponse, Error = actix_web::Error, InitError = ()>> {
+    let search_backend: Arc<dyn SearchBackend> = Arc::new(InMemoryBackend::new());
+
+    App::new()
+        .app_data(Data::from(search_backend))
+        .app_data(Data::new(auth))
+        .app_data(Data::new(rate_limit))
+        .app_data(Data::new(RateLimiter::default()))
+        .configure(crate::api(CorsConfig::default()))
+}
+
+/// Sends a request, returns the status and the json body, null if the body is empty
+pub async fn call<S>(app: &S, request: Request) -> (StatusCode, Value)
+where
+    S: Service<Request, Response = ServiceResponse, Error = actix_web::Error>
+{
+    let resp = test::call_service(app, request).await;
+    let status = resp.status();
+    let body = test::read_body(resp).await;
+
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+/// Creates the airports index with a few airports, ids are their IATA codes
+pub async fn seed_airports<S>(app: &S)
+where
+    S: Service<Request, Response = ServiceResponse, Error = actix_web::Error>
+{
+    let (status, _) = call(app, TestRequest::post().uri("/api/index").set_json(json!({"index": "airports"})).to_request()).await;
+    assert_eq!(status, StatusCode::CREATED);
+
+    let airports = [
+        ("CGK", "Soekarno Hatta International Airport", "Jakarta", "Indonesia", 212),
+        ("DPS", "Ngurah Rai International Airport", "Denpasar", "Indonesia", 134),
+        ("KUL", "Kuala Lumpur International Airport", "Kuala Lumpur", "Malaysia", 281),
+        ("SIN", "Changi Airport", "Singapore", "Singapore", 395),
+        ("HLP", "Halim Perdanakusuma Airport", "Jakarta", "Indonesia", 12)
+    ];
+
+    let data: Vec<Value> = airports.iter().map(|(id, name, city, country, links_count)| json!({
+        "action": "index",
+        "document_id": id,
+        "data": {
+            "name": name,
+            "city": city,
+            "country": country,
+            "links_count": links_count
+        }
+    })).collect();
+
+    let (status, body) = call(app, TestRequest::post().uri("/api/documents/bulk").set_json(json!({
+        "index": "airports",
+        "dynamic_mode": "true",
+        "data": data
+    })).to_request()).await;
+
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["failed"], 0);
+}
@@ -0,0 +1,116 @@
+use std::{io::{Read, Write}, net::TcpListener, sync::Arc};
+
+use actix_web::{http::StatusCode, test::{self, TestRequest}, web::Data, App};
+use reqwest::Url;
+
+use crate::{config::{AuthConfig, Config, CorsConfig}, models::{EClient, backend::SearchBackend, pool::{NodePool, Trust}}};
+
+use super::call;
+
+/// Answers every request with the json body, or closes the connection without answering if body is None
+pub(super) fn fake_node(body: Option<&'static str>) -> Url {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = Url::parse(&format!("http://{}", listener.local_addr().unwrap())).unwrap();
+
+    std::thread::spawn(move || {
+        for mut stream in listener.incoming().flatten() {
+            let mut request = [0; 4096];
+            let _ = stream.read(&mut request);
+
+            if let Some(body) = body {
+                let _ = write!(stream, "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nX-Elastic-Product: Elasticsearch\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", body.len(), body);
+            }
+        }
+    });
+
+    url
+}
+
+/// A url nothing listens on
+pub(super) fn dead_node() -> Url {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    Url::parse(&format!("http://{}", listener.local_addr().unwrap())).unwrap()
+}
+
+async fn ping(pool: &NodePool) -> bool {
+    pool.send("ping", |es, _| async move { es.ping().send().await }).await.is_ok()
+}
+
+#[actix_web::test]
+async fn requests_fail_over_to_alive_nodes() {
+    let dead = dead_node();
+    let pool = NodePool::new(vec![dead.clone(), fake_node(Some("{}"))], None, Trust::System);
+
+    for _ in 0..4 {
+        assert!(ping(&pool).await);
+    }
+
+    let health = pool.health();
+    assert_eq!(health[0].url, dead.to_string());
+    assert!(!health[0].alive);
+    assert_eq!(health[0].failures, 1);
+    assert!(health[0].last_error.is_some());
+    assert!(health[1].alive);
+}
+
+#[actix_web::test]
+async fn non_idempotent_requests_are_not_resent() {
+    let pool = NodePool::new(vec![fake_node(None), fake_node(Some("{}"))], None, Trust::System);
+
+    // The first node received the request but never answered
+    let resp = pool.send_once("ping", |es, _| async move { es.ping().send().await }).await;
+    assert!(resp.is_err());
+
+    assert!(ping(&pool).await);
+
+    // Nothing listens, so the request never reached the first node
+    let pool = NodePool::new(vec![dead_node(), fake_node(Some("{}"))], None, Trust::System);
+    let resp = pool.send_once("ping", |es, _| async move { es.ping().send().await }).await;
+    assert!(resp.is_ok());
+}
+
+#[actix_web::test]
+async fn all_nodes_down_is_an_error() {
+    let pool = NodePool::new(vec![dead_node(), dead_node()], None, Trust::System);
+
+    assert!(!ping(&pool).await);
+    assert!(pool.health().iter().all(|x| !x.alive));
+}
+
+#[actix_web::test]
+async fn sniffing_discovers_http_nodes() {
+    let nodes_info = r#"{"nodes": {
+        "a": {"roles": ["data", "ingest"], "http": {"publish_address": "127.0.0.1:9201"}},
+        "b": {"roles": ["master"], "http": {"publish_address": "127.0.0.1:9202"}},
+        "c": {"roles": ["data", "master"], "http": {"publish_address": "es3.local/10.0.0.3:9200"}}
+    }}"#;
+    let pool = NodePool::new(vec![fake_node(Some(nodes_info))], None, Trust::System);
+
+    assert_eq!(pool.sniff().await.unwrap(), 2);
+
+    let urls: Vec<String> = pool.health().into_iter().map(|x| x.url).collect();
+    assert_eq!(urls, vec!["http://127.0.0.1:9201/", "http://es3.local:9200/"]);
+}
+
+#[actix_web::test]
+async fn diagnostics_show_node_health() {
+    let config = Config::from_sources(
+        [("DPS_ELASTICSEARCH_NODES".to_string(), format!("{},{}", dead_node(), fake_node(Some("{}"))))].into(),
+        vec![]
+    ).unwrap();
+    let client = EClient::new(&config).unwrap();
+    ping(&client.elastic).await;
+
+    let search_backend: Arc<dyn SearchBackend> = Arc::new(client);
+    let app = test::init_service(App::new()
+        .app_data(Data::from(search_backend))
+        .app_data(Data::new(AuthConfig { enabled: false, admin_key: None }))
+        .configure(crate::api(CorsConfig::default()))).await;
+
+    let (status, body) = call(&app, TestRequest::get().uri("/api/diagnostics/nodes").to_request()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["alive"], 1);
+    assert_eq!(body["total"], 2);
+    assert_eq!(body["nodes"][0]["alive"], false);
+    assert!(body["nodes"][0]["retry_in"].as_u64().unwrap() <= 10);
+}
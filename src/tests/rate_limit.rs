@@ -0,0 +1,179 @@
+use actix_web::{http::StatusCode, test::{self, TestRequest}};
+use serde_json::json;
+
+use crate::{config::{AuthConfig, RateLimitConfig}, middlewares::auth::API_KEY_HEADER, models::{api_keys::now, rate_limit::{RateLimiter, current_month}}};
+
+use super::{app_with, auth::{ADMIN_KEY, create_key}, call};
+
+fn limits(search: u32, write: u32, ip_search: u32, monthly_quota: Option<u64>) -> RateLimitConfig {
+    RateLimitConfig {
+        search_per_minute: search,
+        write_per_minute: write,
+        ip_search_per_minute: ip_search,
+        ip_write_per_minute: 0,
+        monthly_quota,
+        ..Default::default()
+    }
+}
+
+fn auth() -> AuthConfig {
+    AuthConfig { enabled: true, admin_key: Some(ADMIN_KEY.to_string()) }
+}
+
+#[actix_web::test]
+async fn keys_are_limited_separately_for_searches_and_writes() {
+    let app = test::init_service(app_with(auth(), limits(2, 1, 0, None))).await;
+    let key = create_key(&app, json!({"name": "website", "role": "write"})).await;
+
+    for remaining in ["1", "0"] {
+        let resp = test::call_service(&app, TestRequest::get().uri("/api/index").insert_header((API_KEY_HEADER, key.as_str())).to_request()).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers().get("X-RateLimit-Limit").unwrap(), "2");
+        assert_eq!(resp.headers().get("X-RateLimit-Remaining").unwrap(), remaining);
+    }
+
+    let resp = test::call_service(&app, TestRequest::get().uri("/api/index").insert_header((API_KEY_HEADER, key.as_str())).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(resp.headers().get("Retry-After").unwrap(), "30");
+    assert_eq!(resp.headers().get("X-RateLimit-Remaining").unwrap(), "0");
+    assert_eq!(resp.headers().get("X-RateLimit-Reset").unwrap(), "60");
+
+    // Writes have their own bucket, POST /api/search is a search
+    let (status, _) = call(&app, TestRequest::delete().uri("/api/index/missing").insert_header((API_KEY_HEADER, key.as_str())).to_request()).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = call(&app, TestRequest::delete().uri("/api/document/missing/1").insert_header((API_KEY_HEADER, key.as_str())).to_request()).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    let (status, _) = call(&app, TestRequest::post().uri("/api/search").insert_header((API_KEY_HEADER, key.as_str())).set_json(json!({"index": "missing"})).to_request()).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+
+    // Other keys are not affected
+    let (status, _) = call(&app, TestRequest::get().uri("/api/index").insert_header((API_KEY_HEADER, ADMIN_KEY)).to_request()).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn client_ips_are_limited() {
+    let app = test::init_service(app_with(auth(), limits(0, 0, 1, None))).await;
+
+    let request = |ip: &str| TestRequest::get()
+        .uri("/api/welcome")
+        .peer_addr(format!("{}:40000", ip).parse().unwrap())
+        .to_request();
+
+    let (status, _) = call(&app, request("10.0.0.1")).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, body) = call(&app, request("10.0.0.1")).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(body["error"], "Too many requests, retry in 60s");
+
+    let (status, _) = call(&app, request("10.0.0.2")).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn forwarded_for_is_only_used_when_trusted() {
+    let config = RateLimitConfig { trust_forwarded_for: true, ..limits(0, 0, 1, None) };
+    let app = test::init_service(app_with(auth(), config)).await;
+
+    for (client, status) in [("1.1.1.1", StatusCode::OK), ("2.2.2.2", StatusCode::OK), ("1.1.1.1", StatusCode::TOO_MANY_REQUESTS)] {
+        let (resp_status, _) = call(&app, TestRequest::get().uri("/api/welcome").insert_header(("X-Forwarded-For", client)).to_request()).await;
+        assert_eq!(resp_status, status);
+    }
+
+    let app = test::init_service(app_with(auth(), limits(0, 0, 1, None))).await;
+
+    let (status, _) = call(&app, TestRequest::get().uri("/api/welcome").insert_header(("X-Forwarded-For", "1.1.1.1")).to_request()).await;
+    assert_eq!(status, StatusCode::OK);
+    let (status, _) = call(&app, TestRequest::get().uri("/api/welcome").insert_header(("X-Forwarded-For", "2.2.2.2")).to_request()).await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[actix_web::test]
+async fn monthly_quota_and_usage() {
+    let app = test::init_service(app_with(auth(), limits(0, 0, 0, Some(3)))).await;
+    let key = create_key(&app, json!({"name": "website", "role": "search"})).await;
+    let id = key.split_once('.').unwrap().0;
+
+    let (status, body) = call(&app, TestRequest::get().uri("/api/usage").insert_header((API_KEY_HEADER, key.as_str())).to_request()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["key"], id);
+    assert_eq!(body["operations"], 1);
+    assert_eq!(body["quota"], 3);
+    assert_eq!(body["remaining"], 2);
+    assert_eq!(body["month"].as_str().unwrap().len(), 7);
+
+    // Counted by this instance since it started, as it started after the month did
+    assert_eq!(body["counted_by"], "instance");
+    let counted_since = body["counted_since"].as_u64().unwrap();
+    assert!(counted_since <= now() && now() - counted_since < 60);
+
+    for _ in 0..2 {
+        let (status, _) = call(&app, TestRequest::get().uri("/api/index").insert_header((API_KEY_HEADER, key.as_str())).to_request()).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    let resp = test::call_service(&app, TestRequest::get().uri("/api/index").insert_header((API_KEY_HEADER, key.as_str())).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert!(resp.headers().get("Retry-After").is_some());
+
+    // Only admins can see the usage of another key
+    let (status, body) = call(&app, TestRequest::get().uri(&format!("/api/usage?key={}", id)).insert_header((API_KEY_HEADER, ADMIN_KEY)).to_request()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["operations"], 3);
+    assert_eq!(body["remaining"], 0);
+
+    let other = create_key(&app, json!({"name": "app", "role": "search"})).await;
+    let (status, _) = call(&app, TestRequest::get().uri(&format!("/api/usage?key={}", id)).insert_header((API_KEY_HEADER, other.as_str())).to_request()).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+}
+
+#[actix_web::test]
+async fn disabled_rate_limits_let_everything_through() {
+    let config = RateLimitConfig { enabled: false, ..limits(1, 1, 1, Some(1)) };
+    let app = test::init_service(app_with(auth(), config)).await;
+
+    for _ in 0..3 {
+        let resp = test::call_service(&app, TestRequest::get().uri("/api/index").insert_header((API_KEY_HEADER, ADMIN_KEY)).to_request()).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(resp.headers().get("X-RateLimit-Limit").is_none());
+    }
+}
+
+#[test]
+fn quotas_reset_at_the_start_of_every_month() {
+    assert_eq!(current_month(1709208000), ("2024-02".to_string(), 43200));
+    assert_eq!(current_month(1704067199), ("2023-12".to_string(), 1));
+    assert_eq!(current_month(0), ("1970-01".to_string(), 31 * 86400));
+}
+
+#[test]
+fn requests_refused_by_one_bucket_take_no_token_from_the_others() {
+    let limiter = RateLimiter::default();
+    let buckets = [("ip:10.0.0.1:search".to_string(), 2), ("key:website:search".to_string(), 1)];
+
+    assert_eq!(limiter.acquire(&buckets).unwrap().unwrap().remaining, 0);
+
+    let refused = limiter.acquire(&buckets).unwrap_err();
+    assert_eq!(refused.limit, 1);
+    assert_eq!(refused.retry_after, 60);
+
+    // The IP still has the token the refused request did not use
+    assert_eq!(limiter.acquire(&buckets[..1]).unwrap().unwrap().remaining, 0);
+    assert!(limiter.acquire(&buckets[..1]).is_err());
+}
+
+#[test]
+fn buckets_are_capped() {
+    let limiter = RateLimiter::default();
+    let first = [("ip:10.0.0.1:search".to_string(), 1)];
+
+    limiter.acquire(&first).unwrap();
+    assert!(limiter.acquire(&first).is_err());
+
+    // Clients that keep changing IPs push the oldest buckets out instead of growing the map
+    for i in 0..10000 {
+        limiter.acquire(&[(format!("ip:2001:db8::{:x}:search", i), 1)]).unwrap();
+    }
+
+    assert!(limiter.acquire(&first).is_ok());
+}
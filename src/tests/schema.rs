@@ -0,0 +1,143 @@
+use actix_http::Request;
+use actix_web::{dev::{Service, ServiceResponse}, http::StatusCode, test::{self, TestRequest}};
+use serde_json::json;
+
+use super::{app, call, index_cache::{client, count, node}};
+
+/// Creates the airports index with a schema
+async fn create_airports<S>(app: &S)
+where
+    S: Service<Request, Response = ServiceResponse, Error = actix_web::Error>
+{
+    let (status, body) = call(app, TestRequest::post().uri("/api/index").set_json(json!({
+        "index": "airports",
+        "schema": {
+            "name": { "type": "text", "sortable": true },
+            "country": { "type": "keyword", "filterable": true, "facetable": true },
+            "links_count": { "type": "number", "filterable": true, "sortable": true },
+            "opened": { "type": "date" },
+            "location": { "type": "geo", "filterable": true },
+            "address.city": { "type": "text" }
+        }
+    })).to_request()).await;
+    assert_eq!(status, StatusCode::CREATED, "{}", body);
+}
+
+#[actix_web::test]
+async fn schemas_are_compiled_into_strict_mappings() {
+    let app = test::init_service(app()).await;
+    create_airports(&app).await;
+
+    let (status, body) = call(&app, TestRequest::get().uri("/api/mappings/airports").to_request()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["dynamic"], "strict");
+    assert_eq!(body["properties"]["name"], json!({"type": "text", "index": true, "fields": {"keyword": {"type": "keyword", "ignore_above": 256}}}));
+    assert_eq!(body["properties"]["country"], json!({"type": "keyword", "index": true, "doc_values": true}));
+    assert_eq!(body["properties"]["links_count"], json!({"type": "double", "index": true, "doc_values": true}));
+    assert_eq!(body["properties"]["opened"], json!({"type": "date", "index": false, "doc_values": false}));
+    assert_eq!(body["properties"]["location"]["type"], "geo_point");
+    assert_eq!(body["properties"]["address"]["properties"]["city"]["type"], "text");
+    assert_eq!(body["_meta"]["schema"]["country"]["type"], "keyword");
+}
+
+#[actix_web::test]
+async fn documents_are_checked_against_the_schema() {
+    let app = test::init_service(app()).await;
+    create_airports(&app).await;
+
+    let (status, body) = call(&app, TestRequest::post().uri("/api/document").set_json(json!({
+        "index": "airports",
+        "data": {
+            "name": "Juanda International Airport",
+            "country": "Indonesia",
+            "links_count": 80,
+            "opened": "1964-08-07",
+            "location": { "lat": -7.37, "lng": 112.78 },
+            "address": { "city": "Surabaya" }
+        }
+    })).to_request()).await;
+    assert_eq!(status, StatusCode::CREATED, "{}", body);
+
+    let rejected = [
+        (json!({ "links_count": "many" }), "field [links_count] expects a value of type [number]"),
+        (json!({ "terminals": 2 }), "field [terminals] is not in the schema"),
+        (json!({ "address": "Surabaya" }), "field [address] expects an object"),
+        (json!({ "opened": "August 1964" }), "field [opened] expects a value of type [date]"),
+        (json!({ "opened": "1964-13-45" }), "field [opened] expects a value of type [date]"),
+        (json!({ "opened": "1964-08-07T25:00:00Z" }), "field [opened] expects a value of type [date]")
+    ];
+
+    for (data, reason) in rejected {
+        let (status, body) = call(&app, TestRequest::post().uri("/api/document").set_json(json!({"index": "airports", "data": data})).to_request()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"], format!("Document does not match the schema of the index, {}", reason));
+    }
+
+    let (status, body) = call(&app, TestRequest::post().uri("/api/document").set_json(json!({
+        "index": "airports",
+        "dynamic_mode": "true",
+        "data": { "name": "Juanda International Airport" }
+    })).to_request()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"], "Document does not match the schema of the index, dynamic_mode can not be used on an index with a schema");
+
+    let (status, body) = call(&app, TestRequest::post().uri("/api/documents/bulk").set_json(json!({
+        "index": "airports",
+        "data": [
+            { "action": "index", "document_id": "SUB", "data": { "name": "Juanda International Airport" } },
+            { "action": "update", "document_id": "SUB", "data": { "links_count": true } }
+        ]
+    })).to_request()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"], "Document does not match the schema of the index, operation at position 1, field [links_count] expects a value of type [number]");
+
+    // The mapping is never changed by writes
+    let (_, body) = call(&app, TestRequest::get().uri("/api/mappings/airports").to_request()).await;
+    assert_eq!(body["dynamic"], "strict");
+}
+
+#[actix_web::test]
+async fn invalid_schemas_are_refused() {
+    let app = test::init_service(app()).await;
+
+    let invalid = [
+        (json!({ "schema": { "links_count": { "type": "number", "searchable": true } } }), "Invalid schema, field [links_count] of type [number] can not be searchable, only text and keyword fields are"),
+        (json!({ "schema": { "address": { "type": "text" }, "address.city": { "type": "text" } } }), "Invalid schema, field [address.city] can not be inside field [address] of type [text]"),
+        (json!({ "schema": { "location": { "type": "geo", "filterable": true } }, "geo_fields": ["location"] }), "Invalid schema, geo_fields can not be used with a schema, declare them with the geo type"),
+        (json!({ "schema": { "location": { "type": "geo" } } }), "Invalid schema, field [location] of type [geo] must be filterable or sortable"),
+        (json!({ "schema": {} }), "Invalid schema, at least one field is required")
+    ];
+
+    for (mut request, error) in invalid {
+        request["index"] = json!("airports");
+
+        let (status, body) = call(&app, TestRequest::post().uri("/api/index").set_json(request).to_request()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"], error);
+    }
+
+    let (status, _) = call(&app, TestRequest::post().uri("/api/index").set_json(json!({
+        "index": "airports",
+        "schema": { "name": { "type": "string" } }
+    })).to_request()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, _) = call(&app, TestRequest::get().uri("/api/mappings/airports").to_request()).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn writes_to_an_index_with_a_schema_leave_its_mapping_alone() {
+    const AIRPORTS: &str = r#"{"airports":{"mappings":{"dynamic":"strict","_meta":{"schema":{"name":{"type":"text"}}},"properties":{"name":{"type":"text","index":true}}},"settings":{}}}"#;
+
+    let (url, received) = node(&[("GET /airports ", "200 OK", AIRPORTS), ("POST /airports/_doc", "201 Created", r#"{"_id":"SUB","result":"created"}"#)]);
+    let client = client(url, "30");
+
+    let id = client.insert_document("airports", json!({"name": "Juanda International Airport"}), None).await.unwrap();
+    assert_eq!(id, "SUB");
+
+    assert!(client.insert_document("airports", json!({"name": 1}), None).await.is_err());
+
+    assert_eq!(count(&received, "POST /airports/_doc"), 1);
+    assert_eq!(count(&received, "PUT"), 0);
+}
@@ -0,0 +1,230 @@
+use actix_web::{http::StatusCode, test::{self, TestRequest}};
+use serde_json::{json, Value};
+
+use crate::models::{ErrorTypes, documents::facet_query_pattern, filters::parse_filter};
+
+use super::{app, call, seed_airports};
+
+/// Ids of the returned documents, in order
+fn ids(body: &Value) -> Vec<&str> {
+    body["data"].as_array().unwrap().iter().map(|x| x["_id"].as_str().unwrap()).collect()
+}
+
+#[actix_web::test]
+async fn search_matches_terms() {
+    let app = test::init_service(app()).await;
+    seed_airports(&app).await;
+
+    let (status, body) = call(&app, TestRequest::get().uri("/api/search/airports?search_term=soekarno%20international%20jakarta").to_request()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["total_data"], 1);
+    assert_eq!(ids(&body), vec!["CGK"]);
+    assert_eq!(body["data"][0]["fields"]["city"], json!(["Jakarta"]));
+
+    let (_, body) = call(&app, TestRequest::post().uri("/api/search").set_json(json!({
+        "index": "airports",
+        "search_term": "jakarta",
+        "search_in": "name"
+    })).to_request()).await;
+    assert_eq!(body["total_data"], 0);
+}
+
+#[actix_web::test]
+async fn search_paginates() {
+    let app = test::init_service(app()).await;
+    seed_airports(&app).await;
+
+    let (_, first) = call(&app, TestRequest::get().uri("/api/search/airports?count=2&from=0&sort=links_count:desc").to_request()).await;
+    let (_, last) = call(&app, TestRequest::get().uri("/api/search/airports?count=2&from=2&sort=links_count:desc").to_request()).await;
+
+    assert_eq!(first["total_data"], 5);
+    assert_eq!(ids(&first), vec!["SIN", "KUL"]);
+    assert_eq!(ids(&last), vec!["HLP"]);
+}
+
+#[actix_web::test]
+async fn search_filters() {
+    let app = test::init_service(app()).await;
+    seed_airports(&app).await;
+
+    let (status, body) = call(&app, TestRequest::post().uri("/api/search").set_json(json!({
+        "index": "airports",
+        "filters": "country:Indonesia AND links_count >= 100",
+        "sort": "city:asc"
+    })).to_request()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(ids(&body), vec!["DPS", "CGK"]);
+
+    let (status, body) = call(&app, TestRequest::post().uri("/api/search").set_json(json!({
+        "index": "airports",
+        "filters": "country:Indonesia AND"
+    })).to_request()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert!(body["error"].as_str().unwrap().starts_with("Invalid filter expression"));
+}
+
+#[actix_web::test]
+async fn search_filters_on_words_that_read_as_floats() {
+    let app = test::init_service(app()).await;
+    seed_airports(&app).await;
+
+    let (status, body) = call(&app, TestRequest::post().uri("/api/document").set_json(json!({
+        "index": "airports",
+        "data": {"name": "Infinity", "city": "Nan", "country": "Thailand", "links_count": 4}
+    })).to_request()).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let id = body["document_id"].as_str().unwrap().to_string();
+
+    for filters in ["city:Nan", "name:Infinity", "city IN [NaN, inf, Nan]"] {
+        let (status, body) = call(&app, TestRequest::post().uri("/api/search").set_json(json!({
+            "index": "airports",
+            "filters": filters
+        })).to_request()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(ids(&body), vec![id.as_str()], "{}", filters);
+    }
+}
+
+#[actix_web::test]
+async fn search_filters_numeric_ids_as_written() {
+    let app = test::init_service(app()).await;
+    seed_airports(&app).await;
+
+    let (status, body) = call(&app, TestRequest::post().uri("/api/document").set_json(json!({
+        "index": "airports",
+        "dynamic_mode": "true",
+        "data": {"name": "Sultan Hasanuddin", "city": "Makassar", "country": "Indonesia", "links_count": 30, "object_id": "3797", "zip": "00123"}
+    })).to_request()).await;
+    assert_eq!(status, StatusCode::CREATED);
+    let id = body["document_id"].as_str().unwrap().to_string();
+
+    for filters in ["object_id:3797", "zip:00123", "object_id IN [12, 3797]"] {
+        let (status, body) = call(&app, TestRequest::post().uri("/api/search").set_json(json!({
+            "index": "airports",
+            "filters": filters
+        })).to_request()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(ids(&body), vec![id.as_str()], "{}", filters);
+    }
+}
+
+#[test]
+fn numeric_filters_keep_the_number_as_written() {
+    assert_eq!(parse_filter("object_id:3797").unwrap().to_query(), json!({
+        "bool": {
+            "should": [
+                { "term": { "object_id": 3797 } },
+                { "term": { "object_id.keyword": "3797" } }
+            ],
+            "minimum_should_match": 1
+        }
+    }));
+
+    // Above 2^53, an f64 would round it to 9007199254740992
+    let query = parse_filter("id = 9007199254740993").unwrap().to_query();
+    assert_eq!(query["bool"]["should"][0]["term"]["id"].as_u64(), Some(9007199254740993));
+    assert_eq!(query["bool"]["should"][1]["term"]["id.keyword"], "9007199254740993");
+
+    assert_eq!(parse_filter("price:2.50").unwrap().to_query()["bool"]["should"][1]["term"]["price.keyword"], "2.50");
+    assert_eq!(parse_filter("links_count >= 10").unwrap().to_query(), json!({ "range": { "links_count": { "gte": 10 } } }));
+}
+
+#[test]
+fn deeply_nested_filters_are_rejected() {
+    let nested = |depth: usize| format!("{}country:Indonesia{}", "(".repeat(depth), ")".repeat(depth));
+
+    assert!(parse_filter(&nested(64)).is_ok());
+    assert!(parse_filter(&format!("{}country:Indonesia", "NOT ".repeat(64))).is_ok());
+
+    // Deep enough to overflow the stack of a worker without the limit
+    for filter in [nested(100_000), format!("{}country:Indonesia", "NOT ".repeat(100_000)), format!("{}country:Indonesia", "(NOT ".repeat(50_000))] {
+        assert!(matches!(parse_filter(&filter), Err(ErrorTypes::InvalidFilter(x)) if x == "filter nested too deeply"));
+    }
+}
+
+#[actix_web::test]
+async fn search_sort_requires_known_field() {
+    let app = test::init_service(app()).await;
+    seed_airports(&app).await;
+
+    let (status, body) = call(&app, TestRequest::get().uri("/api/search/airports?sort=terminals:asc").to_request()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"], "Invalid sort, field [terminals] not found");
+}
+
+#[actix_web::test]
+async fn search_counts_facets() {
+    let app = test::init_service(app()).await;
+    seed_airports(&app).await;
+
+    let (status, body) = call(&app, TestRequest::get().uri("/api/search/airports?facets=country,links_count&count=0").to_request()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["facets"]["country"]["values"]["Indonesia"], 3);
+    assert_eq!(body["facets"]["country"]["values"]["Malaysia"], 1);
+    assert_eq!(body["facets"]["links_count"]["stats"]["max"], 395.0);
+
+    let (status, body) = call(&app, TestRequest::post().uri("/api/search/airports/facets/city").set_json(json!({
+        "facet_query": "lum",
+        "search_term": "international"
+    })).to_request()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["facet_hits"], json!([{"value": "Kuala Lumpur", "count": 1}]));
+}
+
+#[actix_web::test]
+async fn max_values_per_facet_is_bounded() {
+    let app = test::init_service(app()).await;
+    seed_airports(&app).await;
+
+    let (status, body) = call(&app, TestRequest::get().uri("/api/search/airports?facets=country&max_values_per_facet=1").to_request()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["facets"]["country"]["values"], json!({"Indonesia": 3}));
+
+    for max_values in [0, -1, 1001] {
+        let (status, body) = call(&app, TestRequest::get().uri(&format!("/api/search/airports?facets=country&max_values_per_facet={}", max_values)).to_request()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"], format!("Bad data request, max_values_per_facet must be between 1 and 1000, got {}", max_values));
+
+        let (status, _) = call(&app, TestRequest::post().uri("/api/search/airports/facets/city").set_json(json!({"max_values_per_facet": max_values})).to_request()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+}
+
+#[actix_web::test]
+async fn search_pages_must_be_countable() {
+    let app = test::init_service(app()).await;
+    seed_airports(&app).await;
+
+    for (from, count) in [(-1, 2), (0, -2)] {
+        let (status, body) = call(&app, TestRequest::get().uri(&format!("/api/search/airports?from={}&count={}", from, count)).to_request()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"], format!("Bad data request, from and count can not be negative, got from {} and count {}", from, count));
+    }
+
+    // Would overflow an i64 once multiplied, or once the count is added
+    for (from, count) in [(i64::MAX, 2), (i64::MAX / 2, 2)] {
+        let (status, body) = call(&app, TestRequest::post().uri("/api/search").set_json(json!({"index": "airports", "from": from, "count": count})).to_request()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"], format!("Bad data request, page {} of {} results is too large", from, count));
+    }
+
+    let (status, body) = call(&app, TestRequest::get().uri("/api/search/airports?from=1&count=2").to_request()).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(ids(&body).len(), 2);
+}
+
+#[actix_web::test]
+async fn search_missing_index_is_not_found() {
+    let app = test::init_service(app()).await;
+
+    let (status, body) = call(&app, TestRequest::get().uri("/api/search/airports").to_request()).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body["error"], "Index [airports] not found");
+}
+
+#[test]
+fn facet_query_patterns_keep_multi_char_case_mappings_as_written() {
+    assert_eq!(facet_query_pattern("Kuala L"), "(.*[^a-zA-Z0-9])?[kK][uU][aA][lL][aA]\\ [lL].*");
+    assert_eq!(facet_query_pattern("straße"), "(.*[^a-zA-Z0-9])?[sS][tT][rR][aA]ß[eE].*");
+    assert_eq!(facet_query_pattern("İz"), "(.*[^a-zA-Z0-9])?İ[zZ].*");
+}
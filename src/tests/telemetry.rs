@@ -0,0 +1,113 @@
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::TcpListener,
+    sync::{Arc, Mutex}
+};
+
+use actix_web::{test::{self, TestRequest}, web::Data, App};
+use tracing_subscriber::layer::SubscriberExt;
+
+use crate::{
+    config::{AuthConfig, Config, CorsConfig, TracingConfig},
+    models::{EClient, backend::SearchBackend},
+    telemetry::{span_layer, tracer_provider}
+};
+
+const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+
+/// Requests received by a stand-in server, as (head, body)
+type Received = Arc<Mutex<Vec<(String, Vec<u8>)>>>;
+
+/// Answers every request with the status and json body, keeps what it received
+fn stand_in(status: &'static str, body: &'static str) -> (String, Received) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    let received = Received::default();
+    let kept = received.clone();
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let mut reader = BufReader::new(stream);
+            let mut head = String::new();
+
+            while reader.read_line(&mut head).map(|x| x > 2).unwrap_or(false) {}
+
+            let length = head
+                .lines()
+                .find_map(|x| x.to_lowercase().strip_prefix("content-length:").map(|x| x.trim().parse().unwrap_or(0)))
+                .unwrap_or(0);
+            let mut request_body = vec![0; length];
+            let _ = reader.read_exact(&mut request_body);
+
+            kept.lock().unwrap().push((head, request_body));
+
+            let _ = write!(reader.get_mut(), "HTTP/1.1 {}\r\nContent-Type: application/json\r\nX-Elastic-Product: Elasticsearch\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", status, body.len(), body);
+        }
+    });
+
+    (url, received)
+}
+
+fn hex_decode(hex: &str) -> Vec<u8> {
+    (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap()).collect()
+}
+
+#[actix_web::test]
+async fn traces_continue_into_elasticsearch_and_reach_the_collector() {
+    let (collector, exported) = stand_in("200 OK", "");
+    let (node, node_requests) = stand_in("404 Not Found", "{}");
+
+    let provider = tracer_provider(&TracingConfig { otlp_endpoint: Some(format!("{}/v1/traces", collector)), ..Default::default() }).unwrap();
+    let _subscriber = tracing::subscriber::set_default(tracing_subscriber::registry().with(span_layer(&provider)));
+
+    let config = Config::from_sources([("DPS_ELASTICSEARCH_NODES".to_string(), node)].into(), vec![]).unwrap();
+    let search_backend: Arc<dyn SearchBackend> = Arc::new(EClient::new(&config).unwrap());
+    let app = test::init_service(App::new()
+        .app_data(Data::from(search_backend))
+        .app_data(Data::new(AuthConfig { enabled: false, admin_key: None }))
+        .configure(crate::api(CorsConfig::default()))).await;
+
+    let resp = test::call_service(&app, TestRequest::get()
+        .uri("/api/mappings/airports")
+        .insert_header(("traceparent", format!("00-{}-00f067aa0ba902b7-01", TRACE_ID)))
+        .to_request()).await;
+    assert_eq!(resp.status(), 404);
+
+    // Every request to Elasticsearch belongs to the trace of the caller, under a span of its own
+    let node_requests = node_requests.lock().unwrap().clone();
+    assert!(!node_requests.is_empty());
+    for (head, _) in &node_requests {
+        let traceparent = head.lines().find_map(|x| x.strip_prefix("traceparent: ")).unwrap();
+        assert!(traceparent.starts_with(&format!("00-{}-", TRACE_ID)));
+        assert!(!traceparent.contains("00f067aa0ba902b7"));
+    }
+
+    provider.force_flush().unwrap();
+
+    let exported = exported.lock().unwrap().clone();
+    let (head, body) = exported.first().unwrap();
+    assert!(head.starts_with("POST /v1/traces"));
+
+    let contains = |needle: &[u8]| body.windows(needle.len()).any(|x| x == needle);
+    assert!(contains(&hex_decode(TRACE_ID)));
+    assert!(contains(b"GET /api/mappings/{index}"));
+    assert!(contains(b"fetch_index_metadata"));
+    assert!(contains(b"get_index"));
+}
+
+#[actix_web::test]
+async fn requests_outside_of_a_trace_get_an_unsampled_one() {
+    let (node, node_requests) = stand_in("200 OK", "{}");
+    let config = Config::from_sources([("DPS_ELASTICSEARCH_NODES".to_string(), node)].into(), vec![]).unwrap();
+    let client = EClient::new(&config).unwrap();
+
+    let resp = client.elastic.send("ping", |es, traceparent| async move {
+        es.ping().header(crate::models::pool::TRACEPARENT, traceparent).send().await
+    }).await;
+    assert!(resp.is_ok());
+
+    let (head, _) = node_requests.lock().unwrap()[0].clone();
+    let traceparent = head.lines().find_map(|x| x.strip_prefix("traceparent: ")).unwrap();
+    assert_eq!(traceparent.len(), 55);
+    assert!(traceparent.ends_with("-00"));
+}